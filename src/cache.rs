@@ -0,0 +1,319 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Caches of the outputs of stages, which can be shared by related experiments; see
+//! [`Experiment::cache_dir`](../experiment/struct.Experiment.html#method.cache_dir).
+
+use super::checksum::{Checksum, Sha256};
+use super::experiment::verify_outputs;
+use super::manifest::Artifact;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The file of an entry recording the checksums and paths of the outputs.
+const RECORD: &str = "outputs";
+
+/// The file of an entry recording the name of the stage that wrote it.
+const STAGE: &str = "stage";
+
+/// A cache directory written by experiments, to inspect and prune.
+///
+/// # Examples
+/// ```
+/// # use experiment::cache::Cache;
+/// # use experiment::experiment::{Experiment, StageOutcome};
+/// # use experiment::process::Process;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("cache").unwrap();
+/// let (index, run) = (tmp.path().join("index"), tmp.path().join("run"));
+/// let mut experiment = Experiment::new("bm25");
+/// experiment
+///     .cache_dir(tmp.path().join("cache"))
+///     .process("index", Process::new("sh", &["-c", "echo x > \"$0\"", index.to_str().unwrap()]))
+///     .outputs("index", &[&index])
+///     .process("search", Process::new("sh", &["-c", "echo y > \"$0\"", run.to_str().unwrap()]))
+///     .outputs("search", &[&run]);
+/// assert!(experiment.run().unwrap().success());
+///
+/// let cache = Cache::new(tmp.path().join("cache"));
+/// let entries = cache.entries().unwrap();
+/// let stages: Vec<_> = entries.iter().map(|entry| entry.stage.as_str()).collect();
+/// assert_eq!(stages.len(), 2);
+/// assert!(stages.contains(&"index") && stages.contains(&"search"));
+/// let search = entries.iter().find(|entry| entry.stage == "search").unwrap();
+/// assert_eq!(search.outputs, vec![run.display().to_string()]);
+/// assert_eq!(search.size, 2);
+///
+/// assert_eq!(cache.invalidate_stage("search").unwrap(), 1);
+/// let report = experiment.run().unwrap();
+/// assert_eq!(report.stages[0].outcome, StageOutcome::UpToDate);
+/// assert_eq!(report.stages[1].outcome, StageOutcome::Succeeded);
+///
+/// let key = &cache.entries().unwrap()[0].key;
+/// assert_eq!(cache.invalidate_key(&key[..8]).unwrap(), 1);
+/// assert_eq!(cache.entries().unwrap().len(), 1);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// An entry of a [`Cache`](struct.Cache.html), holding the outputs of a stage for one key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedOutputs {
+    /// The hash naming the entry.
+    pub key: String,
+    /// The name of the stage that wrote the entry.
+    pub stage: String,
+    /// The paths of the output files, with `{run_dir}` in place of the output directory of
+    /// the experiment.
+    pub outputs: Vec<String>,
+    /// The total size of the copies of the outputs, in bytes.
+    pub size: u64,
+}
+
+impl Cache {
+    /// Opens the cache in `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Cache {
+        Cache {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Lists the entries of the cache, ordered by key. A missing directory is an empty cache.
+    pub fn entries(&self) -> io::Result<Vec<CachedOutputs>> {
+        let dir = match std::fs::read_dir(&self.dir) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            dir => dir?,
+        };
+        let mut entries = Vec::new();
+        for entry in dir {
+            let path = entry?.path();
+            let key = match path.file_name().and_then(|name| name.to_str()) {
+                Some(key) if !key.starts_with('.') && path.is_dir() => String::from(key),
+                _ => continue,
+            };
+            let (stage, record) = match (
+                std::fs::read_to_string(path.join(STAGE)),
+                std::fs::read_to_string(path.join(RECORD)),
+            ) {
+                (Ok(stage), Ok(record)) => (stage, record),
+                _ => continue,
+            };
+            let mut size = 0;
+            for copy in std::fs::read_dir(&path)? {
+                let copy = copy?;
+                if copy.file_name() != STAGE && copy.file_name() != RECORD {
+                    size += total_size(&copy.path())?;
+                }
+            }
+            entries.push(CachedOutputs {
+                key,
+                stage,
+                outputs: record
+                    .lines()
+                    .filter_map(|line| line.split_once(' ').map(|(_, path)| String::from(path)))
+                    .collect(),
+                size,
+            });
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+
+    /// Removes the entries written by the stage, and returns how many there were.
+    pub fn invalidate_stage(&self, stage: &str) -> io::Result<usize> {
+        self.invalidate(|entry| entry.stage == stage)
+    }
+
+    /// Removes the entries whose keys start with `prefix`, and returns how many there were.
+    pub fn invalidate_key(&self, prefix: &str) -> io::Result<usize> {
+        self.invalidate(|entry| entry.key.starts_with(prefix))
+    }
+
+    fn invalidate<F: Fn(&CachedOutputs) -> bool>(&self, matches: F) -> io::Result<usize> {
+        let mut removed = 0;
+        for entry in self.entries()?.iter().filter(|entry| matches(entry)) {
+            remove(&self.dir.join(&entry.key))?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+/// An entry of a cache: a directory holding copies of the outputs of a stage, named after a
+/// hash of its key and the paths and contents of its inputs. The copy of the `n`-th output
+/// is named `n`.
+///
+/// Paths within the run directory are hashed and recorded relative to it, so that the
+/// experiments with different run directories share their entries.
+pub(crate) struct Entry {
+    path: PathBuf,
+    run_dir: Option<PathBuf>,
+}
+
+impl Entry {
+    /// The entry of a stage in `cache_dir`, given its key, its inputs, and the run directory
+    /// of its experiment, if any.
+    pub(crate) fn new(
+        cache_dir: &Path,
+        key: u64,
+        inputs: &[PathBuf],
+        run_dir: Option<&Path>,
+    ) -> io::Result<Entry> {
+        let run_dir = run_dir.map(Path::to_path_buf);
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{:016x}\n", key).as_bytes());
+        for input in inputs {
+            let checksum = Checksum::of_file(input).map_err(|err| {
+                io::Error::new(err.kind(), format!("Input {}: {}", input.display(), err))
+            })?;
+            hasher.update(portable(run_dir.as_deref(), input).as_bytes());
+            hasher.update(b"\0");
+            hasher.update(checksum.as_bytes());
+        }
+        Ok(Entry {
+            path: cache_dir.join(hasher.finish().to_string()),
+            run_dir,
+        })
+    }
+
+    /// Makes sure that the outputs have the contents recorded in the entry, copying them from
+    /// the entry if they do not, and returns them. Returns `None` if there is no complete
+    /// entry, in which case the stage must run.
+    pub(crate) fn restore(&self, stage: &str, outputs: &[PathBuf]) -> Option<Vec<Artifact>> {
+        let recorded = std::fs::read_to_string(self.path.join(RECORD)).ok()?;
+        let current = || {
+            verify_outputs(stage, outputs)
+                .ok()
+                .filter(|artifacts| self.record(artifacts) == recorded)
+        };
+        if let Some(artifacts) = current() {
+            return Some(artifacts);
+        }
+        for (idx, output) in outputs.iter().enumerate() {
+            replace(&self.path.join(idx.to_string()), output).ok()?;
+        }
+        current()
+    }
+
+    /// Replaces the entry with copies of the outputs. The entry is written to a temporary
+    /// directory first, so that an interrupted write never leaves an incomplete entry.
+    pub(crate) fn store(
+        &self,
+        stage: &str,
+        outputs: &[PathBuf],
+        artifacts: &[Artifact],
+    ) -> io::Result<()> {
+        static CALLS: AtomicU64 = AtomicU64::new(0);
+        let temporary = self.path.with_file_name(format!(
+            ".{}.{}.{}",
+            self.path.file_name().unwrap_or_default().to_string_lossy(),
+            std::process::id(),
+            CALLS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = remove(&temporary)
+            .and_then(|_| std::fs::create_dir(&temporary))
+            .and_then(|_| {
+                for (idx, output) in outputs.iter().enumerate() {
+                    copy(output, &temporary.join(idx.to_string()))?;
+                }
+                std::fs::write(temporary.join(STAGE), stage)?;
+                std::fs::write(temporary.join(RECORD), self.record(artifacts))
+            })
+            .and_then(|_| remove(&self.path))
+            .and_then(|_| std::fs::rename(&temporary, &self.path));
+        if written.is_err() {
+            let _ = remove(&temporary);
+        }
+        written
+    }
+
+    /// Lists the checksums and paths of the output files, one per line.
+    fn record(&self, artifacts: &[Artifact]) -> String {
+        artifacts
+            .iter()
+            .map(|artifact| {
+                let path = portable(self.run_dir.as_deref(), &artifact.path);
+                format!("{} {}\n", artifact.checksum, path)
+            })
+            .collect()
+    }
+}
+
+/// The path with the run directory, if it is within it, replaced with `{run_dir}`.
+fn portable(run_dir: Option<&Path>, path: &Path) -> String {
+    match run_dir.and_then(|dir| path.strip_prefix(dir).ok()) {
+        Some(relative) => Path::new("{run_dir}").join(relative).display().to_string(),
+        None => path.display().to_string(),
+    }
+}
+
+/// Replaces `to`, if it exists, with a copy of `from`.
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+    remove(to)?;
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    copy(from, to)
+}
+
+/// Copies a file, or a directory with all its contents.
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// The total size of a file, or of the files in a directory.
+fn total_size(path: &Path) -> io::Result<u64> {
+    if path.is_dir() {
+        let mut total = 0;
+        for entry in std::fs::read_dir(path)? {
+            total += total_size(&entry?.path())?;
+        }
+        Ok(total)
+    } else {
+        Ok(std::fs::metadata(path)?.len())
+    }
+}
+
+/// Removes a file or a directory with all its contents, if it exists.
+fn remove(path: &Path) -> io::Result<()> {
+    let removed = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    match removed {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
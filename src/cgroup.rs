@@ -0,0 +1,174 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Resource confinement with Linux control groups (cgroup v2).
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The default mount point of the cgroup v2 hierarchy.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// A cgroup v2 control group.
+///
+/// Processes are placed in the group with
+/// [`Process::cgroup`](../process/struct.Process.html#method.cgroup) right before they start,
+/// so all their descendants are accounted for and constrained as well.
+///
+/// Creating groups and setting limits requires write access to the cgroup hierarchy, and the
+/// `memory` and `cpu` controllers enabled in the parent's `cgroup.subtree_control`.
+///
+/// # Examples
+/// ```no_run
+/// # use experiment::cgroup::Cgroup;
+/// # use experiment::process::Process;
+/// # use std::time::Duration;
+/// let group = Cgroup::create("experiment/index-build").unwrap();
+/// group.memory_max(8 << 30).unwrap();
+/// group.cpu_max(Duration::from_millis(400), Duration::from_millis(100)).unwrap();
+/// let mut process = Process::new("build_index", &["collection"]);
+/// process.cgroup(&group);
+/// process.execute().unwrap();
+/// println!("{:?}", group.usage().unwrap());
+/// group.remove().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+/// Resources used by all processes that have been in a [`Cgroup`](struct.Cgroup.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CgroupUsage {
+    /// Peak memory usage in bytes, if reported by the kernel (`memory.peak`).
+    pub memory_peak: Option<u64>,
+    /// Total CPU time.
+    pub cpu_usage: Duration,
+    /// CPU time spent in user mode.
+    pub cpu_user: Duration,
+    /// CPU time spent in kernel mode.
+    pub cpu_system: Duration,
+}
+
+impl Cgroup {
+    /// Creates a new group at `name`, relative to [`CGROUP_ROOT`](constant.CGROUP_ROOT.html)
+    /// unless absolute. Fails if the group already exists.
+    pub fn create<P: AsRef<Path>>(name: P) -> io::Result<Cgroup> {
+        let path = Path::new(CGROUP_ROOT).join(name);
+        std::fs::create_dir(&path)?;
+        Ok(Cgroup { path })
+    }
+
+    /// Refers to an existing group at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Cgroup> {
+        let path = path.as_ref().to_path_buf();
+        if !path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{}: cgroup not found", path.display()),
+            ));
+        }
+        Ok(Cgroup { path })
+    }
+
+    /// The directory of the group.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Limits the memory of the group (`memory.max`).
+    pub fn memory_max(&self, bytes: u64) -> io::Result<()> {
+        self.write("memory.max", &bytes.to_string())
+    }
+
+    /// Limits the group to `quota` of CPU time per `period` (`cpu.max`); for example,
+    /// a quota of 400ms per 100ms corresponds to 4 CPUs.
+    pub fn cpu_max(&self, quota: Duration, period: Duration) -> io::Result<()> {
+        self.write(
+            "cpu.max",
+            &format!("{} {}", quota.as_micros(), period.as_micros()),
+        )
+    }
+
+    /// Reads the resources used by the group.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::cgroup::Cgroup;
+    /// # use tempdir::TempDir;
+    /// # use std::time::Duration;
+    /// let dir = TempDir::new("cgroup").unwrap();
+    /// std::fs::write(dir.path().join("memory.peak"), "1048576\n").unwrap();
+    /// std::fs::write(
+    ///     dir.path().join("cpu.stat"),
+    ///     "usage_usec 3000\nuser_usec 2000\nsystem_usec 1000\n",
+    /// )
+    /// .unwrap();
+    /// let usage = Cgroup::open(dir.path()).unwrap().usage().unwrap();
+    /// assert_eq!(usage.memory_peak, Some(1048576));
+    /// assert_eq!(usage.cpu_usage, Duration::from_millis(3));
+    /// assert_eq!(usage.cpu_user, Duration::from_millis(2));
+    /// assert_eq!(usage.cpu_system, Duration::from_millis(1));
+    /// ```
+    pub fn usage(&self) -> io::Result<CgroupUsage> {
+        let mut usage = CgroupUsage::default();
+        match std::fs::read_to_string(self.path.join("memory.peak")) {
+            Ok(peak) => usage.memory_peak = Some(parse_number(peak.trim())?),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        for line in std::fs::read_to_string(self.path.join("cpu.stat"))?.lines() {
+            let mut fields = line.split_whitespace();
+            let (key, value) = match (fields.next(), fields.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+            let field = match key {
+                "usage_usec" => &mut usage.cpu_usage,
+                "user_usec" => &mut usage.cpu_user,
+                "system_usec" => &mut usage.cpu_system,
+                _ => continue,
+            };
+            *field = Duration::from_micros(parse_number(value)?);
+        }
+        Ok(usage)
+    }
+
+    /// Removes the group. Fails if any process is still in it.
+    pub fn remove(self) -> io::Result<()> {
+        std::fs::remove_dir(&self.path)
+    }
+
+    fn write(&self, file: &str, value: &str) -> io::Result<()> {
+        std::fs::write(self.path.join(file), value)
+    }
+}
+
+fn parse_number(value: &str) -> io::Result<u64> {
+    value.parse().map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid cgroup value {}: {}", value, err),
+        )
+    })
+}
@@ -0,0 +1,169 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Conditional execution of processes, as with `&&` and `||` in a shell.
+
+use super::process::Process;
+use super::Verbosity;
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+
+/// The condition under which a process in a [`CommandChain`](struct.CommandChain.html) runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Connective {
+    /// Run if the preceding process succeeded (`&&`).
+    And,
+    /// Run if the preceding process failed (`||`).
+    Or,
+}
+
+/// A sequence of processes connected with `&&` and `||`, executed with short-circuiting like
+/// in a shell.
+///
+/// As in a shell, the operators have equal precedence and associate to the left: in
+/// `a && b || c`, `c` runs if either `a` or `b` fails. A process that is skipped passes on
+/// the exit status of the last process that ran.
+///
+/// # Examples
+/// ```
+/// # use experiment::chain::CommandChain;
+/// # use experiment::process::Process;
+/// # use experiment::Verbosity::Verbose;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("chain").unwrap();
+/// let marker = tmp.path().join("cleaned-up");
+/// let mut chain = CommandChain::new(Process::new("false", &Vec::<&str>::new()));
+/// chain
+///     .and(Process::new("echo", &["done"]))
+///     .or(Process::new("touch", &[&marker]));
+/// assert_eq!(
+///     chain.display(Verbose).to_string(),
+///     format!("false && echo done || touch {}", marker.display())
+/// );
+/// assert!(chain.execute().unwrap().success());
+/// assert!(marker.exists());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CommandChain {
+    first: Process,
+    rest: Vec<(Connective, Process)>,
+}
+
+/// A [`CommandChain`](struct.CommandChain.html) wrapper implementing `fmt::Display` trait.
+///
+/// This object is created with
+/// [`CommandChain::display`](struct.CommandChain.html#method.display) method.
+pub struct ChainDisplay<'a> {
+    chain: &'a CommandChain,
+    verbosity: Verbosity,
+}
+
+impl CommandChain {
+    /// Creates a chain starting with the process.
+    pub fn new(process: Process) -> CommandChain {
+        CommandChain {
+            first: process,
+            rest: Vec::new(),
+        }
+    }
+
+    /// Appends a process that runs only if the chain so far succeeded.
+    pub fn and(&mut self, process: Process) -> &mut CommandChain {
+        self.then(Connective::And, process)
+    }
+
+    /// Appends a process that runs only if the chain so far failed.
+    pub fn or(&mut self, process: Process) -> &mut CommandChain {
+        self.then(Connective::Or, process)
+    }
+
+    /// Appends a process with the given connective.
+    pub fn then(&mut self, connective: Connective, process: Process) -> &mut CommandChain {
+        self.rest.push((connective, process));
+        self
+    }
+
+    /// Creates a [`ChainDisplay`](struct.ChainDisplay.html) object with the desired verbosity.
+    pub fn display(&self, verbosity: Verbosity) -> ChainDisplay<'_> {
+        ChainDisplay {
+            chain: self,
+            verbosity,
+        }
+    }
+
+    /// Renders the chain as a single line that can be pasted into a POSIX shell.
+    /// See [`Process::to_shell_string`](../process/struct.Process.html#method.to_shell_string).
+    pub fn to_shell_string(&self) -> String {
+        let mut line = self.first.to_shell_string();
+        for (connective, process) in &self.rest {
+            line.push_str(&format!(" {} {}", connective, process.to_shell_string()));
+        }
+        line
+    }
+
+    /// Executes the chain and returns the exit status of the last process that ran.
+    /// Fails as soon as any process that is supposed to run cannot be executed.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::chain::CommandChain;
+    /// # use experiment::process::Process;
+    /// let mut chain = CommandChain::new(Process::new("true", &Vec::<&str>::new()));
+    /// chain
+    ///     .or(Process::new("unknown_process", &Vec::<&str>::new()))
+    ///     .and(Process::new("false", &Vec::<&str>::new()));
+    /// assert!(!chain.execute().unwrap().success());
+    /// ```
+    pub fn execute(&self) -> io::Result<ExitStatus> {
+        let mut status = self.first.execute()?;
+        for (connective, process) in &self.rest {
+            let run = match connective {
+                Connective::And => status.success(),
+                Connective::Or => !status.success(),
+            };
+            if run {
+                status = process.execute()?;
+            }
+        }
+        Ok(status)
+    }
+}
+
+impl fmt::Display for Connective {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Connective::And => write!(f, "&&"),
+            Connective::Or => write!(f, "||"),
+        }
+    }
+}
+
+impl<'a> fmt::Display for ChainDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.chain.first.display(self.verbosity))?;
+        for (connective, process) in &self.chain.rest {
+            write!(f, " {} {}", connective, process.display(self.verbosity))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Checkpoints recording the partial progress of long stages.
+
+use super::context::CHECKPOINT_VAR;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file in which a stage records its partial progress, so that it can continue from the
+/// last checkpoint when restarted after an interruption.
+///
+/// An [`Experiment`](../experiment/struct.Experiment.html) manages the checkpoint of each
+/// [checkpointed](../experiment/struct.Experiment.html#method.checkpointed) stage: processes
+/// find its path in the [`EXPERIMENT_CHECKPOINT`](../context/constant.CHECKPOINT_VAR.html)
+/// environment variable, closures in their
+/// [`ExecutionContext`](../context/struct.ExecutionContext.html#method.checkpoint). The
+/// contents are up to the stage; the checkpoint is cleared once the stage succeeds.
+///
+/// # Examples
+/// ```
+/// # use experiment::checkpoint::Checkpoint;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("checkpoint").unwrap();
+/// let checkpoint = Checkpoint::new(tmp.path().join("index.checkpoint"));
+/// assert_eq!(checkpoint.load().unwrap(), None);
+/// checkpoint.save(b"shard=17").unwrap();
+/// assert_eq!(checkpoint.load().unwrap(), Some(b"shard=17".to_vec()));
+/// checkpoint.clear().unwrap();
+/// assert!(!checkpoint.exists());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    /// Refers to the checkpoint stored at `path`, which need not exist yet.
+    pub fn new<P: AsRef<Path>>(path: P) -> Checkpoint {
+        Checkpoint {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Refers to the checkpoint given by the `EXPERIMENT_CHECKPOINT` environment variable, if
+    /// set; this is how a program run as a checkpointed stage finds its checkpoint.
+    pub fn from_env() -> Option<Checkpoint> {
+        std::env::var_os(CHECKPOINT_VAR).map(Checkpoint::new)
+    }
+
+    /// The path of the checkpoint file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks if any progress has been saved.
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Reads the saved progress, or `None` if nothing has been saved.
+    pub fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Atomically replaces the saved progress, so that an interruption while saving leaves
+    /// the previous checkpoint intact.
+    pub fn save(&self, data: &[u8]) -> io::Result<()> {
+        let mut temporary = OsString::from(self.path.as_os_str());
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        std::fs::write(&temporary, data)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+
+    /// Removes the saved progress, if any.
+    pub fn clear(&self) -> io::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
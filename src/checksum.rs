@@ -0,0 +1,325 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! SHA-256 checksums of files and streams.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+#[rustfmt::skip]
+const K: [u32; 64] = [
+    0x428a_2f98, 0x7137_4491, 0xb5c0_fbcf, 0xe9b5_dba5, 0x3956_c25b, 0x59f1_11f1, 0x923f_82a4,
+    0xab1c_5ed5, 0xd807_aa98, 0x1283_5b01, 0x2431_85be, 0x550c_7dc3, 0x72be_5d74, 0x80de_b1fe,
+    0x9bdc_06a7, 0xc19b_f174, 0xe49b_69c1, 0xefbe_4786, 0x0fc1_9dc6, 0x240c_a1cc, 0x2de9_2c6f,
+    0x4a74_84aa, 0x5cb0_a9dc, 0x76f9_88da, 0x983e_5152, 0xa831_c66d, 0xb003_27c8, 0xbf59_7fc7,
+    0xc6e0_0bf3, 0xd5a7_9147, 0x06ca_6351, 0x1429_2967, 0x27b7_0a85, 0x2e1b_2138, 0x4d2c_6dfc,
+    0x5338_0d13, 0x650a_7354, 0x766a_0abb, 0x81c2_c92e, 0x9272_2c85, 0xa2bf_e8a1, 0xa81a_664b,
+    0xc24b_8b70, 0xc76c_51a3, 0xd192_e819, 0xd699_0624, 0xf40e_3585, 0x106a_a070, 0x19a4_c116,
+    0x1e37_6c08, 0x2748_774c, 0x34b0_bcb5, 0x391c_0cb3, 0x4ed8_aa4a, 0x5b9c_ca4f, 0x682e_6ff3,
+    0x748f_82ee, 0x78a5_636f, 0x84c8_7814, 0x8cc7_0208, 0x90be_fffa, 0xa450_6ceb, 0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+#[rustfmt::skip]
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09_e667, 0xbb67_ae85, 0x3c6e_f372, 0xa54f_f53a, 0x510e_527f, 0x9b05_688c, 0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+/// Computes checksums of multiple files using up to `threads` threads, and returns them in the
+/// order of the paths. Fails if any of the files cannot be read.
+///
+/// # Examples
+/// ```
+/// # use experiment::checksum::{checksum_files, Checksum};
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("checksum").unwrap();
+/// let paths: Vec<_> = (0..10).map(|n| tmp.path().join(n.to_string())).collect();
+/// for (n, path) in paths.iter().enumerate() {
+///     std::fs::write(path, n.to_string()).unwrap();
+/// }
+/// let checksums = checksum_files(&paths, 4).unwrap();
+/// assert_eq!(checksums[7], Checksum::of(b"7"));
+/// ```
+pub fn checksum_files<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    threads: usize,
+) -> io::Result<Vec<Checksum>> {
+    crate::parallel_map(paths, threads, |path| Checksum::of_file(path))
+        .into_iter()
+        .zip(paths)
+        .map(|(result, path)| result.map_err(|err| with_path(err, path.as_ref())))
+        .collect()
+}
+
+/// Adds the path to the error message.
+fn with_path(err: io::Error, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
+
+/// A SHA-256 digest.
+///
+/// # Examples
+/// ```
+/// # use experiment::checksum::Checksum;
+/// assert_eq!(
+///     Checksum::of(b"abc").to_string(),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Checksum([u8; 32]);
+
+impl Checksum {
+    /// Computes the checksum of a byte slice.
+    pub fn of(data: &[u8]) -> Checksum {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// Computes the checksum of everything that can be read from `reader`.
+    pub fn of_reader<R: Read>(mut reader: R) -> io::Result<Checksum> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut reader, &mut hasher)?;
+        Ok(hasher.finish())
+    }
+
+    /// Computes the checksum of a file.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::checksum::Checksum;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("checksum").unwrap();
+    /// let path = tmp.path().join("file");
+    /// std::fs::write(&path, b"abc").unwrap();
+    /// assert_eq!(Checksum::of_file(&path).unwrap(), Checksum::of(b"abc"));
+    /// ```
+    pub fn of_file<P: AsRef<Path>>(path: P) -> io::Result<Checksum> {
+        Checksum::of_reader(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    /// The raw bytes of the digest.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// An incremental SHA-256 hasher, which is also a [`Write`] sink.
+///
+/// # Examples
+/// ```
+/// # use experiment::checksum::{Checksum, Sha256};
+/// let mut hasher = Sha256::new();
+/// hasher.update(b"a");
+/// hasher.update(b"bc");
+/// assert_eq!(hasher.finish(), Checksum::of(b"abc"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: [u8; 64],
+    buffered: usize,
+    length: u64,
+}
+
+impl Sha256 {
+    /// Creates a new hasher.
+    pub fn new() -> Sha256 {
+        Sha256 {
+            state: INITIAL_STATE,
+            buffer: [0; 64],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    /// Feeds data to the hasher.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if self.buffered > 0 {
+            let taken = data.len().min(64 - self.buffered);
+            self.buffer[self.buffered..self.buffered + taken].copy_from_slice(&data[..taken]);
+            self.buffered += taken;
+            data = &data[taken..];
+            if self.buffered < 64 {
+                return;
+            }
+            let block = self.buffer;
+            self.compress(&block);
+            self.buffered = 0;
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    /// Returns the number of bytes fed to the hasher so far.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Checks if no data has been fed to the hasher.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Finishes the computation and returns the checksum.
+    pub fn finish(mut self) -> Checksum {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80_u8];
+        let padded = (self.buffered + 1) % 64;
+        let zeros = if padded <= 56 {
+            56 - padded
+        } else {
+            120 - padded
+        };
+        padding.resize(1 + zeros, 0);
+        padding.extend_from_slice(&bit_length.to_be_bytes());
+        self.update(&padding);
+        debug_assert_eq!(self.buffered, 0);
+        let mut digest = [0_u8; 32];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        Checksum(digest)
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0_u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Sha256 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that computes the checksum of everything written through it, so that files do
+/// not have to be read again to verify them.
+///
+/// # Examples
+/// ```
+/// # use experiment::checksum::{Checksum, ChecksumWriter};
+/// # use std::io::Write;
+/// let mut writer = ChecksumWriter::new(Vec::new());
+/// writer.write_all(b"abc").unwrap();
+/// let (data, checksum, size) = writer.finish();
+/// assert_eq!(data, b"abc".to_vec());
+/// assert_eq!(checksum, Checksum::of(b"abc"));
+/// assert_eq!(size, 3);
+/// ```
+#[derive(Debug)]
+pub struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    /// Wraps a writer.
+    pub fn new(inner: W) -> ChecksumWriter<W> {
+        ChecksumWriter {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns the wrapped writer, the checksum, and the number of bytes written.
+    pub fn finish(self) -> (W, Checksum, u64) {
+        let size = self.hasher.len();
+        (self.inner, self.hasher.finish(), size)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,323 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Compression of files with external tools.
+
+use super::process::Process;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A compression format together with its compression level.
+///
+/// Compression is delegated to the `gzip` and `zstd` command line tools, which must be
+/// available in `PATH`. The default is `zstd` at level 3, which compresses large files several
+/// times faster than `gzip` at a comparable ratio.
+///
+/// # Examples
+/// ```
+/// # use experiment::compression::Codec;
+/// assert_eq!(Codec::default(), Codec::Zstd(3));
+/// assert_eq!("gzip:9".parse::<Codec>().unwrap(), Codec::Gzip(9));
+/// assert_eq!("zstd".parse::<Codec>().unwrap(), Codec::Zstd(3));
+/// assert_eq!(Codec::Gzip(9).to_string(), "gzip:9");
+/// assert!("brotli".parse::<Codec>().is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Codec {
+    /// No compression.
+    None,
+    /// `gzip` with a level between 1 and 9.
+    Gzip(u32),
+    /// `zstd` with a level between 1 and 19.
+    Zstd(i32),
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Codec::Zstd(3)
+    }
+}
+
+impl Codec {
+    /// Determines the codec of a file based on its extension, with the default level.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::compression::Codec;
+    /// assert_eq!(Codec::from_path("corpus.gz"), Codec::Gzip(6));
+    /// assert_eq!(Codec::from_path("index.zst"), Codec::Zstd(3));
+    /// assert_eq!(Codec::from_path("queries.txt"), Codec::None);
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Codec {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Codec::Gzip(6),
+            Some("zst") => Codec::Zstd(3),
+            _ => Codec::None,
+        }
+    }
+
+    /// The file extension (without the dot) of the compressed files.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Codec::None => None,
+            Codec::Gzip(_) => Some("gz"),
+            Codec::Zstd(_) => Some("zst"),
+        }
+    }
+
+    /// A process compressing its standard input to its standard output.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::compression::Codec;
+    /// # use experiment::Verbosity::Verbose;
+    /// let process = Codec::Zstd(19).compressor().unwrap();
+    /// assert_eq!(process.display(Verbose).to_string(), "zstd -q -c -19");
+    /// assert!(Codec::None.compressor().is_none());
+    /// ```
+    pub fn compressor(&self) -> Option<Process> {
+        let program = self.programs().last()?;
+        Some(Process::new(program, self.compress_args()))
+    }
+
+    /// A process decompressing its standard input to its standard output.
+    pub fn decompressor(&self) -> Option<Process> {
+        let program = self.programs().last()?;
+        Some(Process::new(program, self.decompress_args()))
+    }
+
+    /// Same as [`compressor`](#method.compressor), but runs the first program implementing
+    /// the codec found in `PATH`: for gzip, `pigz`, which compresses in parallel and produces
+    /// the same format, and `gzip` otherwise. Fails if none is found.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::compression::Codec;
+    /// let process = Codec::Gzip(9).available_compressor().unwrap().unwrap();
+    /// assert!(process.resolve().is_ok());
+    /// assert!(Codec::None.available_compressor().unwrap().is_none());
+    /// ```
+    pub fn available_compressor(&self) -> io::Result<Option<Process>> {
+        self.available(&self.compress_args())
+    }
+
+    /// Same as [`decompressor`](#method.decompressor), but looks for the program like
+    /// [`available_compressor`](#method.available_compressor).
+    pub fn available_decompressor(&self) -> io::Result<Option<Process>> {
+        self.available(&self.decompress_args())
+    }
+
+    /// The programs implementing the codec, from the most to the least preferred.
+    fn programs(&self) -> &'static [&'static str] {
+        match self {
+            Codec::None => &[],
+            Codec::Gzip(_) => &["pigz", "gzip"],
+            Codec::Zstd(_) => &["zstd"],
+        }
+    }
+
+    fn compress_args(&self) -> Vec<String> {
+        match self {
+            Codec::None => Vec::new(),
+            Codec::Gzip(level) => vec![String::from("-c"), format!("-{}", level)],
+            Codec::Zstd(level) => vec![
+                String::from("-q"),
+                String::from("-c"),
+                format!("-{}", level),
+            ],
+        }
+    }
+
+    fn decompress_args(&self) -> Vec<String> {
+        let args: &[&str] = match self {
+            Codec::None => &[],
+            Codec::Gzip(_) => &["-d", "-c"],
+            Codec::Zstd(_) => &["-q", "-d", "-c"],
+        };
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+
+    /// Creates a process running the first of the programs found in `PATH` with `args`.
+    fn available(&self, args: &[String]) -> io::Result<Option<Process>> {
+        if *self == Codec::None {
+            return Ok(None);
+        }
+        let programs = self.programs();
+        programs
+            .iter()
+            .map(|program| Process::new(program, args))
+            .find(|process| process.resolve().is_ok())
+            .map(Some)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("None of {} found in PATH", programs.join(", ")),
+                )
+            })
+    }
+
+    /// Compresses the file into a new one with the codec's extension appended, removes the
+    /// original, and returns the path to the compressed file. Does nothing for `Codec::None`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::compression::Codec;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("compression").unwrap();
+    /// let path = tmp.path().join("results.txt");
+    /// std::fs::write(&path, "a\n".repeat(1000)).unwrap();
+    /// let compressed = Codec::Gzip(6).compress_file(&path).unwrap();
+    /// assert_eq!(compressed, tmp.path().join("results.txt.gz"));
+    /// assert!(!path.exists());
+    /// let decompressed = Codec::Gzip(6).decompress_file(&compressed).unwrap();
+    /// assert_eq!(decompressed, path);
+    /// assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\n".repeat(1000));
+    /// ```
+    pub fn compress_file<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+        let path = path.as_ref();
+        match (self.compressor(), self.extension()) {
+            (Some(compressor), Some(extension)) => {
+                let mut target = path.as_os_str().to_os_string();
+                target.push(".");
+                target.push(extension);
+                let target = PathBuf::from(target);
+                transform(&compressor, path, &target)?;
+                Ok(target)
+            }
+            _ => Ok(path.to_path_buf()),
+        }
+    }
+
+    /// Decompresses the file into a new one without the codec's extension, removes the
+    /// original, and returns the path to the decompressed file. Does nothing for
+    /// `Codec::None`.
+    pub fn decompress_file<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+        let path = path.as_ref();
+        match (self.decompressor(), self.extension()) {
+            (Some(decompressor), Some(extension)) => {
+                if path.extension().and_then(|ext| ext.to_str()) != Some(extension) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: expected .{} extension", path.display(), extension),
+                    ));
+                }
+                let target = path.with_extension("");
+                transform(&decompressor, path, &target)?;
+                Ok(target)
+            }
+            _ => Ok(path.to_path_buf()),
+        }
+    }
+}
+
+/// Compresses multiple files with [`Codec::compress_file`](enum.Codec.html#method.compress_file)
+/// using up to `threads` threads, and returns the paths to the compressed files in the order
+/// of the inputs. Fails if any of the files fails to compress.
+///
+/// # Examples
+/// ```
+/// # use experiment::compression::{compress_files, Codec};
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("compression").unwrap();
+/// let paths: Vec<_> = (0..4).map(|n| tmp.path().join(format!("{}.txt", n))).collect();
+/// for path in &paths {
+///     std::fs::write(path, "data").unwrap();
+/// }
+/// let compressed = compress_files(&paths, Codec::Gzip(1), 2).unwrap();
+/// assert_eq!(compressed[3], tmp.path().join("3.txt.gz"));
+/// assert!(compressed.iter().all(|path| path.exists()));
+/// ```
+pub fn compress_files<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    codec: Codec,
+    threads: usize,
+) -> io::Result<Vec<PathBuf>> {
+    crate::parallel_map(paths, threads, |path| codec.compress_file(path))
+        .into_iter()
+        .collect()
+}
+
+/// Runs the process with `source` as input and `target` as output, and removes `source` on
+/// success, or `target` on failure.
+fn transform(process: &Process, source: &Path, target: &Path) -> io::Result<()> {
+    let status = process
+        .try_command()?
+        .stdin(File::open(source)?)
+        .stdout(File::create(target)?)
+        .status();
+    match status {
+        Ok(status) if status.success() => std::fs::remove_file(source),
+        Ok(status) => {
+            let _ = std::fs::remove_file(target);
+            Err(io::Error::other(format!(
+                "{} failed for {} ({})",
+                process.display(crate::Verbosity::Verbose),
+                source.display(),
+                status
+            )))
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(target);
+            Err(err)
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Codec::None => write!(f, "none"),
+            Codec::Gzip(level) => write!(f, "gzip:{}", level),
+            Codec::Zstd(level) => write!(f, "zstd:{}", level),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    /// Parses `none`, `gzip`, `zstd`, or either of the latter two followed by a colon and
+    /// the compression level.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        let level = parts.next();
+        let invalid_level = |level: &str| format!("Invalid compression level: {}", level);
+        match (name, level) {
+            ("none", None) => Ok(Codec::None),
+            ("gzip", None) => Ok(Codec::Gzip(6)),
+            ("gzip", Some(level)) => match level.parse() {
+                Ok(level) if (1..=9).contains(&level) => Ok(Codec::Gzip(level)),
+                _ => Err(invalid_level(level)),
+            },
+            ("zstd", None) => Ok(Codec::Zstd(3)),
+            ("zstd", Some(level)) => match level.parse() {
+                Ok(level) if (1..=19).contains(&level) => Ok(Codec::Zstd(level)),
+                _ => Err(invalid_level(level)),
+            },
+            _ => Err(format!("Unknown codec: {}", value)),
+        }
+    }
+}
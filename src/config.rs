@@ -0,0 +1,601 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Loading experiments and parameter grids from TOML files.
+
+use super::experiment::Experiment;
+use super::process::{Process, ProcessPipeline};
+use super::seeds::Seeds;
+use super::sweep::{self, Domain, Grid};
+use super::toml::{self, Item, Table, Value};
+use super::OverwritePolicy;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// A position in a configuration file; both the line and the column start at 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// The line number.
+    pub line: usize,
+    /// The column number, counted in characters.
+    pub column: usize,
+}
+
+/// An error in a configuration file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Where the error was found.
+    pub position: Position,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.position.line, self.position.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Parses an experiment from its TOML definition.
+///
+/// The top level has the following keys:
+/// - `name`: the name of the experiment (required);
+/// - `output_dir`: the output directory;
+/// - `overwrite`: `"fail"` (default) or `"force"`, the policy of creating the output directory;
+/// - `parameters`: a table of parameters, whose values are strings, numbers, or booleans;
+/// - `cache_dir`: the directory caching the outputs of stages; see
+///   [`Experiment::cache_dir`](../experiment/struct.Experiment.html#method.cache_dir);
+/// - `cleanup`: what happens to intermediate outputs after a successful run, one of `keep`
+///   (the default), `delete-on-success`, or `compress-on-success[:<codec>]` (see
+///   [`CleanupPolicy`](../experiment/enum.CleanupPolicy.html));
+/// - `seed`: the master seed of the [seeds](../seeds/struct.Seeds.html) of the stages, a
+///   non-negative integer;
+/// - `stage`: an array of tables, one per stage;
+/// - `grid` and `derived`: the parameter grid, which is ignored here and loaded with
+///   [`grid_from_str`](fn.grid_from_str.html).
+///
+/// Each stage has a `name` and a `command`, which is either an array of the program and its
+/// arguments, or a string with a pipeline in shell syntax (see
+/// [`ProcessPipeline::from_shell`](../process/struct.ProcessPipeline.html#method.from_shell)).
+/// Optionally, `cwd` sets its working directory, and `env` is a table of its environment
+/// variables, and `inputs` and `outputs` are arrays of the paths of files it reads and writes.
+/// The `intermediate` array lists outputs only needed by later stages, which are subject to
+/// the cleanup policy.
+/// It runs after the stages or outputs listed in `depends_on`
+/// (see [`Experiment::depends_on`](../experiment/struct.Experiment.html#method.depends_on)).
+/// Its `on_failure` policy is one of `abort` (the default), `continue`, `retry(<n>)`, or
+/// `run(<stage>)` (see [`FailurePolicy`](../experiment/enum.FailurePolicy.html)).
+/// Commands, redirection paths, and working directories may refer to parameters
+/// and to `{run_id}`, `{run_dir}`, and `{stage_dir}` with placeholders, which are replaced
+/// when the experiment [runs](../experiment/struct.Experiment.html#method.run). Unknown keys
+/// are rejected to catch typos.
+///
+/// # Examples
+/// ```
+/// # use experiment::config;
+/// # use experiment::Verbosity::Verbose;
+/// let experiment = config::from_str(r#"
+/// name = "bm25"
+/// output_dir = "results"
+/// seed = 42
+///
+/// [parameters]
+/// k1 = 0.9
+/// b = 0.4
+///
+/// [[stage]]
+/// name = "index"
+/// command = ["build_index", "--collection", "corpus"]
+/// cwd = "data"
+/// env = { RAYON_NUM_THREADS = 8 }
+/// inputs = ["data/corpus"]
+/// outputs = ["data/index"]
+///
+/// [[stage]]
+/// name = "queries"
+/// command = "zcat topics.gz | parse --queries > queries.txt"
+/// depends_on = ["index"]
+/// on_failure = "retry(2)"
+/// "#).unwrap();
+/// assert_eq!(experiment.name(), "bm25");
+/// assert_eq!(experiment.stage_names(), vec!["index", "queries"]);
+/// assert_eq!(experiment.parameters()["k1"], "0.9");
+/// assert_eq!(experiment.manifest().unwrap().master_seed, Some(42));
+/// let lines = experiment.display().to_string();
+/// assert_eq!(
+///     lines.lines().collect::<Vec<_>>(),
+///     vec![
+///         "bm25",
+///         "[index] (cd data && env RAYON_NUM_THREADS=8 build_index --collection corpus)",
+///         "[queries] zcat topics.gz",
+///         "\t| parse --queries > queries.txt",
+///     ]
+/// );
+///
+/// let err = config::from_str("name = \"bm25\"\n[[stage]]\nname = \"index\"\ncomand = []\n")
+///     .unwrap_err();
+/// assert_eq!(err.to_string(), "4:10: Unknown key `comand`");
+/// let err = config::from_str("name = \"bm25\"\nthreads = 1x\n").unwrap_err();
+/// assert_eq!(err.to_string(), "2:11: Invalid value `1x`");
+/// let err = config::from_str(
+///     "name = \"bm25\"\n[[stage]]\nname = \"index\"\ncommand = \"true\"\non_failure = \"skip\"\n",
+/// )
+/// .unwrap_err();
+/// assert_eq!(err.to_string(), "5:14: Unknown failure policy: skip");
+/// ```
+pub fn from_str(text: &str) -> Result<Experiment, ConfigError> {
+    let root = toml::parse(text)?;
+    let start = Position { line: 1, column: 1 };
+    let known = [
+        "name",
+        "output_dir",
+        "overwrite",
+        "parameters",
+        "stage",
+        "grid",
+        "derived",
+        "seed",
+        "cache_dir",
+        "cleanup",
+    ];
+    let fields = Fields::new(&root, start, &known)?;
+    let mut experiment = Experiment::new(fields.required_string("name")?);
+    if let Some(dir) = fields.string("output_dir")? {
+        let policy = match fields.get("overwrite") {
+            None => OverwritePolicy::Fail,
+            Some(item) => match string(item, "overwrite")? {
+                "fail" => OverwritePolicy::Fail,
+                "force" => OverwritePolicy::Force,
+                other => {
+                    return Err(error(
+                        item.position,
+                        format!("Invalid overwrite policy `{}`", other),
+                    ))
+                }
+            },
+        };
+        experiment.output_dir(dir, policy);
+    }
+    if let Some(item) = fields.get("parameters") {
+        for (name, value) in table(item, "parameters")? {
+            experiment.parameter(name, &scalar(value, name)?);
+        }
+    }
+    if let Some(dir) = fields.string("cache_dir")? {
+        experiment.cache_dir(dir);
+    }
+    if let Some(item) = fields.get("cleanup") {
+        let policy = string(item, "cleanup")?
+            .parse()
+            .map_err(|err: String| error(item.position, err))?;
+        experiment.cleanup(policy);
+    }
+    if let Some(item) = fields.get("seed") {
+        match &item.value {
+            Value::Integer(seed) if *seed >= 0 => {
+                experiment.seeds(Seeds::new(*seed as u64));
+            }
+            Value::Integer(_) => return Err(error(item.position, "Seed must not be negative")),
+            other => return Err(mismatch(item.position, "seed", "integer", other)),
+        }
+    }
+    if let Some(item) = fields.get("stage") {
+        let stages = match &item.value {
+            Value::Array(stages) => stages,
+            other => return Err(mismatch(item.position, "stage", "array", other)),
+        };
+        for stage in stages {
+            add_stage(&mut experiment, stage)?;
+        }
+    }
+    Ok(experiment)
+}
+
+/// Reads and parses an experiment from a TOML file; see [`from_str`](fn.from_str.html).
+/// Errors in the definition are reported as `InvalidData` with the path and the position.
+///
+/// # Examples
+/// ```
+/// # use experiment::config;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("config").unwrap();
+/// let path = tmp.path().join("experiment.toml");
+/// std::fs::write(&path, "name = 'bm25'\n[[stage]]\nname = 'index'\n").unwrap();
+/// let err = config::load(&path).unwrap_err();
+/// assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+/// assert_eq!(
+///     err.to_string(),
+///     format!("{}:2:1: Missing key `command`", path.display())
+/// );
+/// ```
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Experiment> {
+    read(path.as_ref(), from_str)
+}
+
+/// Parses the parameter grid from the `grid` table of a TOML definition; the grid is empty if
+/// the table is missing.
+///
+/// Each key of the table declares a parameter, in the order of appearance, whose values are
+/// given by either:
+/// - an array of strings, numbers, or booleans, which keep their types;
+/// - `{ range = [start, end, step] }`: integers from `start` to `end`, inclusive, where `step`
+///   is optional and defaults to 1;
+/// - `{ linspace = [start, end, n] }` or `{ logspace = [start, end, n] }`: `n` floats evenly
+///   spaced on a linear or logarithmic scale.
+///
+/// The optional `derived` table declares parameters computed from the others with
+/// expressions; see [`Grid::derive`](../sweep/struct.Grid.html#method.derive).
+///
+/// # Examples
+/// ```
+/// # use experiment::config;
+/// # use experiment::sweep::Value;
+/// let grid = config::grid_from_str(r#"
+/// name = "bm25"
+///
+/// [grid]
+/// algorithm = ["maxscore", "wand"]
+/// k = { range = [10, 30, 10] }
+/// mu = { logspace = [100, 10000, 3] }
+/// quantized = [false, true]
+///
+/// [derived]
+/// output = '"{algorithm}-{k}.run"'
+/// depth = "k * 2"
+/// "#).unwrap();
+/// assert_eq!(grid.len(), 2 * 3 * 3 * 2);
+/// let first = &grid.combinations()[0];
+/// assert_eq!(
+///     first.to_string(),
+///     "algorithm=maxscore,k=10,mu=100,quantized=false,output=maxscore-10.run,depth=20"
+/// );
+/// assert_eq!(first.value("k"), Some(&Value::Integer(10)));
+/// assert_eq!(first.value("mu"), Some(&Value::Float(100.0)));
+///
+/// let err = config::grid_from_str("[grid]\nk = { range = [10] }\n").unwrap_err();
+/// assert_eq!(err.to_string(), "2:15: `range` must have 2 or 3 elements");
+/// ```
+pub fn grid_from_str(text: &str) -> Result<Grid, ConfigError> {
+    let root = toml::parse(text)?;
+    let mut grid = Grid::new();
+    if let Some(item) = root.get("grid") {
+        for (name, values) in in_order(table(item, "grid")?) {
+            grid.domain(name, domain(values, name)?);
+        }
+    }
+    if let Some(item) = root.get("derived") {
+        for (name, expression) in in_order(table(item, "derived")?) {
+            grid.derive(name, string(expression, name)?)
+                .map_err(|err| error(expression.position, err.to_string()))?;
+        }
+    }
+    Ok(grid)
+}
+
+/// Reads and parses a parameter grid from a TOML file; see
+/// [`grid_from_str`](fn.grid_from_str.html) and [`load`](fn.load.html).
+pub fn load_grid<P: AsRef<Path>>(path: P) -> io::Result<Grid> {
+    read(path.as_ref(), grid_from_str)
+}
+
+fn read<T>(path: &Path, parse: fn(&str) -> Result<T, ConfigError>) -> io::Result<T> {
+    let text = std::fs::read_to_string(path)?;
+    parse(&text).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}", path.display(), err),
+        )
+    })
+}
+
+/// The entries of the table in the order they appear in the file.
+fn in_order(table: &Table) -> Vec<(&String, &Item)> {
+    let mut entries: Vec<_> = table.iter().collect();
+    entries.sort_by_key(|(_, item)| (item.position.line, item.position.column));
+    entries
+}
+
+fn domain(item: &Item, key: &str) -> Result<Domain, ConfigError> {
+    match &item.value {
+        Value::Array(values) => Ok(Domain::Values(
+            values
+                .iter()
+                .map(|value| typed(value, key))
+                .collect::<Result<_, _>>()?,
+        )),
+        Value::Table(table) => {
+            let fields = Fields::new(table, item.position, &["range", "linspace", "logspace"])?;
+            match (
+                fields.get("range"),
+                fields.get("linspace"),
+                fields.get("logspace"),
+            ) {
+                (Some(bounds), None, None) => range(bounds),
+                (None, Some(bounds), None) => {
+                    let (start, end, n) = spacing(bounds, "linspace", false)?;
+                    Ok(Domain::linspace(start, end, n))
+                }
+                (None, None, Some(bounds)) => {
+                    let (start, end, n) = spacing(bounds, "logspace", true)?;
+                    Ok(Domain::logspace(start, end, n))
+                }
+                _ => Err(error(
+                    item.position,
+                    format!(
+                        "`{}` must have exactly one of `range`, `linspace`, or `logspace`",
+                        key
+                    ),
+                )),
+            }
+        }
+        other => Err(mismatch(item.position, key, "array or table", other)),
+    }
+}
+
+/// Parses `[start, end]` or `[start, end, step]`.
+fn range(bounds: &Item) -> Result<Domain, ConfigError> {
+    match elements(bounds, "range")? {
+        [start, end] => Ok(Domain::range(integer(start)?, integer(end)?, 1)),
+        [start, end, step] => match integer(step)? {
+            value if value > 0 => Ok(Domain::range(integer(start)?, integer(end)?, value)),
+            _ => Err(error(step.position, "Range step must be positive")),
+        },
+        _ => Err(error(bounds.position, "`range` must have 2 or 3 elements")),
+    }
+}
+
+/// Parses `[start, end, n]`, where the bounds must be positive if `positive` is set.
+fn spacing(bounds: &Item, key: &str, positive: bool) -> Result<(f64, f64, usize), ConfigError> {
+    match elements(bounds, key)? {
+        [start, end, n] => {
+            let bound = |item: &Item| match float(item)? {
+                value if positive && value <= 0.0 => Err(error(
+                    item.position,
+                    format!("`{}` bounds must be positive", key),
+                )),
+                value => Ok(value),
+            };
+            let count = match integer(n)? {
+                count if count >= 0 => count as usize,
+                _ => return Err(error(n.position, "Count must not be negative")),
+            };
+            Ok((bound(start)?, bound(end)?, count))
+        }
+        _ => Err(error(
+            bounds.position,
+            format!("`{}` must have 3 elements", key),
+        )),
+    }
+}
+
+fn elements<'a>(item: &'a Item, key: &str) -> Result<&'a [Item], ConfigError> {
+    match &item.value {
+        Value::Array(elements) => Ok(elements),
+        other => Err(mismatch(item.position, key, "array", other)),
+    }
+}
+
+fn typed(item: &Item, key: &str) -> Result<sweep::Value, ConfigError> {
+    match &item.value {
+        Value::String(value) => Ok(sweep::Value::String(value.clone())),
+        Value::Integer(value) => Ok(sweep::Value::Integer(*value)),
+        Value::Float(value) => Ok(sweep::Value::Float(*value)),
+        Value::Boolean(value) => Ok(sweep::Value::Boolean(*value)),
+        other => Err(mismatch(
+            item.position,
+            key,
+            "string, number, or boolean",
+            other,
+        )),
+    }
+}
+
+fn integer(item: &Item) -> Result<i64, ConfigError> {
+    match &item.value {
+        Value::Integer(value) => Ok(*value),
+        other => Err(error(
+            item.position,
+            format!("Expected an integer, found {}", other.type_name()),
+        )),
+    }
+}
+
+fn float(item: &Item) -> Result<f64, ConfigError> {
+    match &item.value {
+        Value::Integer(value) => Ok(*value as f64),
+        Value::Float(value) => Ok(*value),
+        other => Err(error(
+            item.position,
+            format!("Expected a number, found {}", other.type_name()),
+        )),
+    }
+}
+
+fn add_stage(experiment: &mut Experiment, stage: &Item) -> Result<(), ConfigError> {
+    let fields = Fields::new(
+        table(stage, "stage")?,
+        stage.position,
+        &[
+            "name",
+            "command",
+            "cwd",
+            "env",
+            "inputs",
+            "outputs",
+            "intermediate",
+            "depends_on",
+            "on_failure",
+        ],
+    )?;
+    let name = fields.required_string("name")?;
+    let command = fields.required("command")?;
+    let mut pipeline = match &command.value {
+        Value::String(line) => {
+            ProcessPipeline::from_shell(line).map_err(|err| error(command.position, err))?
+        }
+        Value::Array(words) if !words.is_empty() => {
+            let words = words
+                .iter()
+                .map(|word| string(word, "command"))
+                .collect::<Result<Vec<_>, _>>()?;
+            ProcessPipeline::from(Process::new(words[0], &words[1..]))
+        }
+        Value::Array(_) => return Err(error(command.position, "Empty command")),
+        other => {
+            return Err(mismatch(
+                command.position,
+                "command",
+                "string or array",
+                other,
+            ))
+        }
+    };
+    if let Some(dir) = fields.string("cwd")? {
+        pipeline.current_dir(dir);
+    }
+    if let Some(item) = fields.get("env") {
+        for (key, value) in table(item, "env")? {
+            pipeline.env(key, &scalar(value, key)?);
+        }
+    }
+    experiment.pipeline(name, pipeline);
+    if let Some(item) = fields.get("inputs") {
+        experiment.inputs(name, strings(item, "inputs")?);
+    }
+    if let Some(item) = fields.get("outputs") {
+        experiment.outputs(name, strings(item, "outputs")?);
+    }
+    if let Some(item) = fields.get("intermediate") {
+        experiment.intermediate(name, strings(item, "intermediate")?);
+    }
+    if let Some(item) = fields.get("depends_on") {
+        experiment.depends_on(name, strings(item, "depends_on")?);
+    }
+    if let Some(item) = fields.get("on_failure") {
+        let policy = string(item, "on_failure")?
+            .parse()
+            .map_err(|err: String| error(item.position, err))?;
+        experiment.on_failure(name, policy);
+    }
+    Ok(())
+}
+
+/// The fields of a table, checked against the known keys.
+struct Fields<'a> {
+    table: &'a Table,
+    position: Position,
+}
+
+impl<'a> Fields<'a> {
+    /// Fails if the table has a key that is not `known`. The position of the table is used
+    /// to report missing keys.
+    fn new(
+        table: &'a Table,
+        position: Position,
+        known: &[&str],
+    ) -> Result<Fields<'a>, ConfigError> {
+        if let Some((key, item)) = table.iter().find(|(key, _)| !known.contains(&key.as_str())) {
+            return Err(error(item.position, format!("Unknown key `{}`", key)));
+        }
+        Ok(Fields { table, position })
+    }
+
+    fn get(&self, key: &str) -> Option<&'a Item> {
+        self.table.get(key)
+    }
+
+    fn required(&self, key: &str) -> Result<&'a Item, ConfigError> {
+        self.get(key)
+            .ok_or_else(|| error(self.position, format!("Missing key `{}`", key)))
+    }
+
+    fn string(&self, key: &str) -> Result<Option<&'a str>, ConfigError> {
+        self.get(key).map(|item| string(item, key)).transpose()
+    }
+
+    fn required_string(&self, key: &str) -> Result<&'a str, ConfigError> {
+        string(self.required(key)?, key)
+    }
+}
+
+fn string<'a>(item: &'a Item, key: &str) -> Result<&'a str, ConfigError> {
+    match &item.value {
+        Value::String(value) => Ok(value),
+        other => Err(mismatch(item.position, key, "string", other)),
+    }
+}
+
+fn strings<'a>(item: &'a Item, key: &str) -> Result<Vec<&'a str>, ConfigError> {
+    match &item.value {
+        Value::Array(values) => values.iter().map(|value| string(value, key)).collect(),
+        other => Err(mismatch(item.position, key, "array", other)),
+    }
+}
+
+fn table<'a>(item: &'a Item, key: &str) -> Result<&'a Table, ConfigError> {
+    match &item.value {
+        Value::Table(table) => Ok(table),
+        other => Err(mismatch(item.position, key, "table", other)),
+    }
+}
+
+/// Converts a string, number, or boolean to a string.
+fn scalar(item: &Item, key: &str) -> Result<String, ConfigError> {
+    match &item.value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        other => Err(mismatch(
+            item.position,
+            key,
+            "string, number, or boolean",
+            other,
+        )),
+    }
+}
+
+fn mismatch(position: Position, key: &str, expected: &str, found: &Value) -> ConfigError {
+    error(
+        position,
+        format!(
+            "`{}` must be a {}, found {}",
+            key,
+            expected,
+            found.type_name()
+        ),
+    )
+}
+
+fn error<S: Into<String>>(position: Position, message: S) -> ConfigError {
+    ConfigError {
+        position,
+        message: message.into(),
+    }
+}
@@ -0,0 +1,221 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::checkpoint::Checkpoint;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the environment variable holding the run ID.
+pub const RUN_ID_VAR: &str = "EXPERIMENT_RUN_ID";
+
+/// Name of the environment variable holding the stage name.
+pub const STAGE_VAR: &str = "EXPERIMENT_STAGE";
+
+/// Name of the environment variable holding the output directory.
+pub const OUTPUT_DIR_VAR: &str = "EXPERIMENT_OUTPUT_DIR";
+
+/// Name of the environment variable holding the path of the checkpoint file.
+pub const CHECKPOINT_VAR: &str = "EXPERIMENT_CHECKPOINT";
+
+/// Name of the environment variable holding the scratch directory of the worker.
+pub const SCRATCH_DIR_VAR: &str = "EXPERIMENT_SCRATCH_DIR";
+
+/// Information about the experiment run that is passed down to every child process through
+/// the environment variables [`EXPERIMENT_RUN_ID`](constant.RUN_ID_VAR.html),
+/// [`EXPERIMENT_STAGE`](constant.STAGE_VAR.html),
+/// [`EXPERIMENT_OUTPUT_DIR`](constant.OUTPUT_DIR_VAR.html),
+/// [`EXPERIMENT_CHECKPOINT`](constant.CHECKPOINT_VAR.html), and
+/// [`EXPERIMENT_SCRATCH_DIR`](constant.SCRATCH_DIR_VAR.html).
+///
+/// A process receives the context [set on it](../process/struct.Process.html#method.context)
+/// or, failing that, the context [entered](#method.enter) on the current thread. Experiments
+/// enter the context of each stage while it runs, so processes started by a closure stage
+/// receive it too, as long as they are started on the thread that runs the closure.
+///
+/// The context is not a part of the [fingerprint](../process/struct.Process.html#method.fingerprint)
+/// of a process, as it typically differs between otherwise identical runs.
+///
+/// # Examples
+/// ```
+/// # use experiment::context::ExecutionContext;
+/// # use experiment::process::Process;
+/// # use std::str::from_utf8;
+/// let mut context = ExecutionContext::new("run-0001");
+/// context.stage("index").output_dir("/tmp/results");
+/// let mut process = Process::new("printenv", &["EXPERIMENT_RUN_ID", "EXPERIMENT_STAGE"]);
+/// process.context(&context);
+/// let output = process.command().output().expect("Failed to run process");
+/// assert_eq!(from_utf8(&output.stdout).unwrap(), "run-0001\nindex\n");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExecutionContext {
+    run_id: String,
+    stage: Option<String>,
+    output_dir: Option<PathBuf>,
+    checkpoint: Option<PathBuf>,
+    scratch_dir: Option<PathBuf>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<ExecutionContext>> = const { RefCell::new(None) };
+}
+
+impl ExecutionContext {
+    /// Creates a context for the given run ID.
+    pub fn new(run_id: &str) -> ExecutionContext {
+        ExecutionContext {
+            run_id: String::from(run_id),
+            stage: None,
+            output_dir: None,
+            checkpoint: None,
+            scratch_dir: None,
+        }
+    }
+
+    /// Sets the name of the current stage.
+    pub fn stage(&mut self, stage: &str) -> &mut ExecutionContext {
+        self.stage = Some(String::from(stage));
+        self
+    }
+
+    /// Sets the output directory of the run.
+    pub fn output_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut ExecutionContext {
+        self.output_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the path of the checkpoint file of the current stage, or unsets it.
+    pub fn checkpoint_file<P: AsRef<Path>>(&mut self, path: Option<P>) -> &mut ExecutionContext {
+        self.checkpoint = path.map(|path| path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Returns the checkpoint of the current stage, if it is checkpointed.
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.checkpoint.as_ref().map(Checkpoint::new)
+    }
+
+    /// Sets the scratch directory for temporary files, private to the worker running the
+    /// experiment (see [`SweepExecutor`](../sweep/struct.SweepExecutor.html)).
+    pub fn scratch_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut ExecutionContext {
+        self.scratch_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Returns the scratch directory, if set.
+    pub fn scratch(&self) -> Option<&Path> {
+        self.scratch_dir.as_deref()
+    }
+
+    /// Returns the run ID.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Makes this the context of the processes started on the current thread that have no
+    /// context of their own, until the returned guard is dropped, at which point the
+    /// previously entered context is restored.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::context::ExecutionContext;
+    /// # use experiment::process::Process;
+    /// # use std::str::from_utf8;
+    /// let printenv = Process::new("printenv", &["EXPERIMENT_RUN_ID"]);
+    /// let run_id = || {
+    ///     let output = printenv.command().output().unwrap();
+    ///     String::from(from_utf8(&output.stdout).unwrap())
+    /// };
+    /// {
+    ///     let _outer = ExecutionContext::new("outer").enter();
+    ///     {
+    ///         let _inner = ExecutionContext::new("inner").enter();
+    ///         assert_eq!(run_id(), "inner\n");
+    ///         assert_eq!(ExecutionContext::current().unwrap().run_id(), "inner");
+    ///     }
+    ///     assert_eq!(run_id(), "outer\n");
+    ///     let mut process = printenv.clone();
+    ///     process.context(&ExecutionContext::new("own"));
+    ///     let output = process.command().output().unwrap();
+    ///     assert_eq!(from_utf8(&output.stdout).unwrap(), "own\n");
+    /// }
+    /// assert!(ExecutionContext::current().is_none());
+    /// ```
+    pub fn enter(&self) -> ContextGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        ContextGuard { previous }
+    }
+
+    /// Returns the context [entered](#method.enter) on the current thread, if any.
+    pub fn current() -> Option<ExecutionContext> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Returns the environment variables defined by this context.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::context::ExecutionContext;
+    /// let context = ExecutionContext::new("run-0001");
+    /// assert_eq!(
+    ///     context.env_vars(),
+    ///     vec![("EXPERIMENT_RUN_ID", String::from("run-0001"))]
+    /// );
+    /// ```
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![(RUN_ID_VAR, self.run_id.clone())];
+        if let Some(stage) = &self.stage {
+            vars.push((STAGE_VAR, stage.clone()));
+        }
+        if let Some(dir) = &self.output_dir {
+            vars.push((OUTPUT_DIR_VAR, dir.to_string_lossy().into_owned()));
+        }
+        if let Some(path) = &self.checkpoint {
+            vars.push((CHECKPOINT_VAR, path.to_string_lossy().into_owned()));
+        }
+        if let Some(dir) = &self.scratch_dir {
+            vars.push((SCRATCH_DIR_VAR, dir.to_string_lossy().into_owned()));
+        }
+        vars
+    }
+
+    /// Sets the environment variables of this context on a command.
+    pub fn apply(&self, cmd: &mut Command) {
+        cmd.envs(self.env_vars());
+    }
+}
+
+/// Restores the previously [entered](struct.ExecutionContext.html#method.enter) context of
+/// the current thread when dropped.
+#[must_use = "the context is left as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct ContextGuard {
+    previous: Option<ExecutionContext>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
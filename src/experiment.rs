@@ -0,0 +1,2687 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Experiments consisting of named stages executed in order.
+
+use super::cache;
+use super::checkpoint::Checkpoint;
+use super::checksum::Checksum;
+use super::compression::{compress_files, Codec};
+use super::context::ExecutionContext;
+use super::fingerprint::CacheKey;
+use super::lock::RunLock;
+use super::manifest::{Artifact, Manifest};
+use super::process::{Process, ProcessPipeline};
+use super::rundir::RunDir;
+use super::script::{ShellCommand, ShellScript};
+use super::seeds::Seeds;
+use super::template;
+use super::{safe_mkdir, OverwritePolicy, Verbosity};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The directory within the output directory of an experiment where the stamps of completed
+/// stages are stored; see [`Experiment::resume`](struct.Experiment.html#method.resume).
+pub const STAMP_DIR: &str = ".stamps";
+
+/// The directory within the output directory of an experiment where the checkpoints of
+/// stages are stored; see [`Experiment::checkpointed`](struct.Experiment.html#method.checkpointed).
+pub const CHECKPOINT_DIR: &str = ".checkpoints";
+
+/// A named, ordered collection of stages, each of which is a process, a pipeline, a closure,
+/// or a [nested experiment](#method.experiment).
+///
+/// Running an experiment executes the stages one after another, and stops at the first one
+/// that fails. Every process receives an [`ExecutionContext`](../context/struct.ExecutionContext.html)
+/// with the name of the experiment as its run ID, the name of its stage, and the output
+/// directory if set; closures are passed the same context, which is also
+/// [entered](../context/struct.ExecutionContext.html#method.enter) while they run, so that
+/// processes they start receive it as well.
+///
+/// Before anything runs, `{name}` placeholders in the commands of the stages and in the paths
+/// they redirect to are replaced with the values of the [parameters](#method.parameter) and
+/// of the following variables (see [`CommandTemplate`](../template/struct.CommandTemplate.html)
+/// for which braces are placeholders):
+/// - `{run_id}`: the [run ID](#method.run_id), by default the name of the experiment;
+/// - `{run_dir}`: the output directory;
+/// - `{stage_dir}`: the subdirectory of the output directory named after the stage, which is
+///   created right before the stage runs if used;
+/// - `{seed}`: the seed of the stage, if [`seeds`](#method.seeds) are set.
+///
+/// An unknown placeholder fails the run before any stage starts.
+///
+/// # Examples
+/// ```
+/// # use experiment::experiment::{Experiment, StageOutcome};
+/// # use experiment::process::{Process, ProcessPipeline};
+/// # use experiment::OverwritePolicy;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("experiment").unwrap();
+/// let output_dir = tmp.path().join("results");
+/// let mut experiment = Experiment::new("bm25");
+/// experiment
+///     .output_dir(&output_dir, OverwritePolicy::Fail)
+///     .parameter("k", "10")
+///     .process(
+///         "index",
+///         Process::new("sh", &["-c", "echo index > $EXPERIMENT_OUTPUT_DIR/$EXPERIMENT_STAGE"]),
+///     )
+///     .pipeline(
+///         "search",
+///         ProcessPipeline::from_shell("echo {run_id} {k} > {stage_dir}/results").unwrap(),
+///     )
+///     .closure("check", |context| {
+///         assert_eq!(context.run_id(), "bm25");
+///         let stage = Process::new("printenv", &["EXPERIMENT_STAGE"]).command().output()?;
+///         assert_eq!(stage.stdout, b"check\n");
+///         Ok(())
+///     })
+///     .process("evaluate", Process::new("false", &Vec::<&str>::new()))
+///     .process("report", Process::new("true", &Vec::<&str>::new()));
+/// let report = experiment.run().unwrap();
+/// assert_eq!(std::fs::read_to_string(output_dir.join("index")).unwrap(), "index\n");
+/// assert!(output_dir.join("manifest.json").exists());
+/// assert_eq!(
+///     std::fs::read_to_string(output_dir.join("search/results")).unwrap(),
+///     "bm25 10\n"
+/// );
+/// assert!(!report.success());
+/// assert_eq!(report.stages[2].outcome, StageOutcome::Succeeded);
+/// assert!(matches!(report.stages[3].outcome, StageOutcome::Failed(_)));
+/// assert_eq!(report.stage("report").unwrap().outcome, StageOutcome::Skipped);
+///
+/// // The output directory already exists.
+/// assert!(experiment.run().is_err());
+///
+/// let mut experiment = Experiment::new("bm25");
+/// experiment.process("search", Process::new("search", &["-k", "{k}"]));
+/// assert_eq!(
+///     experiment.run().unwrap_err().to_string(),
+///     "Stage `search`: Unknown placeholder `{k}` in `{k}`"
+/// );
+/// ```
+#[derive(Debug)]
+pub struct Experiment {
+    name: String,
+    run_id: Option<String>,
+    stages: Vec<(String, Action)>,
+    verbosity: Verbosity,
+    output_dir: Option<(PathBuf, OverwritePolicy)>,
+    parameters: BTreeMap<String, String>,
+    recorded_env: Vec<String>,
+    version_probes: Vec<(String, Process)>,
+    seeds: Option<Seeds>,
+    files: BTreeMap<String, StageFiles>,
+    dependencies: BTreeMap<String, Vec<String>>,
+    cache_dir: Option<PathBuf>,
+    cache_keys: BTreeMap<String, KeyFn>,
+    checkpointed: BTreeMap<String, usize>,
+    failure_policies: BTreeMap<String, FailurePolicy>,
+    time_budget: Option<(Duration, Duration)>,
+    hooks: BTreeMap<String, HookPoint>,
+    cleanup: CleanupPolicy,
+}
+
+/// What an [`Experiment`](struct.Experiment.html) does with the
+/// [intermediate](struct.Experiment.html#method.intermediate) outputs of its stages at the end
+/// of a run.
+///
+/// # Examples
+/// ```
+/// # use experiment::compression::Codec;
+/// # use experiment::experiment::CleanupPolicy;
+/// assert_eq!("keep".parse::<CleanupPolicy>().unwrap(), CleanupPolicy::Keep);
+/// assert_eq!(
+///     "delete-on-success".parse::<CleanupPolicy>().unwrap(),
+///     CleanupPolicy::DeleteOnSuccess
+/// );
+/// assert_eq!(
+///     "compress-on-success".parse::<CleanupPolicy>().unwrap(),
+///     CleanupPolicy::CompressOnSuccess(Codec::default())
+/// );
+/// assert_eq!(
+///     "compress-on-success:gzip:9".parse::<CleanupPolicy>().unwrap(),
+///     CleanupPolicy::CompressOnSuccess(Codec::Gzip(9))
+/// );
+/// assert_eq!(
+///     CleanupPolicy::CompressOnSuccess(Codec::Zstd(3)).to_string(),
+///     "compress-on-success:zstd:3"
+/// );
+/// assert!("delete".parse::<CleanupPolicy>().is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum CleanupPolicy {
+    /// Keep them.
+    #[default]
+    Keep,
+    /// Delete them if all stages succeeded.
+    DeleteOnSuccess,
+    /// Compress the files among them with the codec if all stages succeeded.
+    CompressOnSuccess(Codec),
+}
+
+/// A setup or teardown action of an [`Experiment`](struct.Experiment.html), run as a stage of
+/// its own; see [`Experiment::before`](struct.Experiment.html#method.before).
+///
+/// Processes and pipelines convert into hooks, closures are wrapped with
+/// [`Hook::closure`](#method.closure).
+#[derive(Debug)]
+pub struct Hook {
+    action: Action,
+}
+
+/// Where a hook runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HookPoint {
+    Before(String),
+    After(String),
+    Start,
+    End,
+}
+
+/// What an [`Experiment`](struct.Experiment.html) does when a stage fails.
+///
+/// # Examples
+/// ```
+/// # use experiment::experiment::FailurePolicy;
+/// assert_eq!("abort".parse::<FailurePolicy>().unwrap(), FailurePolicy::Abort);
+/// assert_eq!("continue".parse::<FailurePolicy>().unwrap(), FailurePolicy::Continue);
+/// assert_eq!("retry(3)".parse::<FailurePolicy>().unwrap(), FailurePolicy::Retry(3));
+/// assert_eq!(
+///     "run(cleanup)".parse::<FailurePolicy>().unwrap(),
+///     FailurePolicy::Run(String::from("cleanup"))
+/// );
+/// assert_eq!(FailurePolicy::Retry(3).to_string(), "retry(3)");
+/// assert!("retry".parse::<FailurePolicy>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum FailurePolicy {
+    /// Skip the remaining stages.
+    #[default]
+    Abort,
+    /// Run the remaining stages anyway.
+    Continue,
+    /// Run the stage again, up to the given number of times, and abort if it keeps failing.
+    Retry(usize),
+    /// Run the named stage, e.g., to clean up, and skip the remaining ones.
+    Run(String),
+}
+
+/// The files a stage of an [`Experiment`](struct.Experiment.html) reads and writes.
+#[derive(Clone, Debug, Default)]
+struct StageFiles {
+    inputs: Vec<PathBuf>,
+    outputs: Vec<PathBuf>,
+    /// The outputs only needed by later stages.
+    intermediate: Vec<PathBuf>,
+}
+
+/// A stage of an [`Experiment`](struct.Experiment.html) with its placeholders replaced.
+struct Resolved {
+    /// The interpolated process or pipeline; `None` for closures.
+    action: Option<Action>,
+    /// The fingerprint of the process or pipeline interpolated with the run directory left
+    /// as a placeholder, which keys the cache.
+    fingerprint: Option<u64>,
+    /// Whether the stage refers to `{stage_dir}`.
+    uses_stage_dir: bool,
+    files: StageFiles,
+    /// The names of the stages or the paths of the outputs the stage depends on.
+    dependencies: Vec<String>,
+}
+
+/// What a stage of an [`Experiment`](struct.Experiment.html) does.
+enum Action {
+    Process(Box<Process>),
+    Pipeline(ProcessPipeline),
+    Closure(Box<StageFn>),
+    Experiment(Box<Experiment>),
+}
+
+/// A closure run as a stage of an [`Experiment`](struct.Experiment.html).
+type StageFn = dyn Fn(&ExecutionContext) -> io::Result<()> + Send + Sync;
+
+/// A closure adding to the [cache key](struct.Experiment.html#method.cache_key) of a stage.
+struct KeyFn(Box<dyn Fn(&mut CacheKey) + Send + Sync>);
+
+/// The outcome of a single stage of an [`Experiment`](struct.Experiment.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StageOutcome {
+    /// The stage finished successfully.
+    Succeeded,
+    /// The stage failed to start, exited unsuccessfully, or returned an error.
+    Failed(String),
+    /// The stage was not run.
+    Skipped,
+    /// The stage was not run because it had completed in an earlier run, see
+    /// [`Experiment::resume`](struct.Experiment.html#method.resume), or its outputs were found
+    /// in the cache, see [`Experiment::cache_dir`](struct.Experiment.html#method.cache_dir).
+    UpToDate,
+}
+
+/// The report of a single stage of an [`Experiment`](struct.Experiment.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageReport {
+    /// The name of the stage.
+    pub name: String,
+    /// What the stage did.
+    pub outcome: StageOutcome,
+    /// How long the stage ran; zero if skipped.
+    pub duration: Duration,
+}
+
+/// The report of a run of an [`Experiment`](struct.Experiment.html), with one entry per stage
+/// in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExperimentReport {
+    /// Reports of the stages, in order.
+    pub stages: Vec<StageReport>,
+    /// Whether the [time budget](struct.Experiment.html#method.time_budget) ran out before
+    /// all stages could finish.
+    pub partial: bool,
+}
+
+/// Printable representation of an [`Experiment`](struct.Experiment.html), listing its stages.
+pub struct ExperimentDisplay<'a> {
+    experiment: &'a Experiment,
+}
+
+/// How the stages of a run are executed.
+struct Execution {
+    /// Whether the output of process stages is labeled with their names.
+    labeled: bool,
+    /// When the time budget runs out, and how long running stages have to stop after that.
+    deadline: Option<(Instant, Duration)>,
+    /// Set once a stage is stopped, or not started, because the time budget ran out.
+    out_of_time: AtomicBool,
+    /// The verified outputs of the stages that succeeded, by index.
+    artifacts: Mutex<BTreeMap<usize, Vec<Artifact>>>,
+}
+
+/// The progress of the stages run concurrently by an [`Experiment`](struct.Experiment.html).
+struct Schedule {
+    reports: Vec<Option<StageReport>>,
+    started: Vec<bool>,
+    aborted: bool,
+}
+
+/// Printable plan of a run of an [`Experiment`](struct.Experiment.html), listing the commands
+/// of its stages with all placeholders replaced. It can also be exported as a
+/// [shell script](#method.to_script).
+pub struct ExperimentPlan<'a> {
+    experiment: &'a Experiment,
+    resolved: Vec<Resolved>,
+    graph: Graph,
+}
+
+/// The dependencies between the stages of an [`Experiment`](struct.Experiment.html).
+struct Graph {
+    /// The indices of the stages each stage depends on.
+    dependencies: Vec<Vec<usize>>,
+    /// The indices of the stages each teardown hook waits for, whether or not they succeed.
+    after: Vec<Vec<usize>>,
+    /// For each stage, whether it is a teardown hook, with the index of its stage, if any.
+    teardown: Vec<Option<Option<usize>>>,
+    /// The indices of all stages, each after its dependencies.
+    order: Vec<usize>,
+}
+
+impl Experiment {
+    /// Creates an experiment with no stages, displayed with brief verbosity.
+    pub fn new(name: &str) -> Experiment {
+        Experiment {
+            name: String::from(name),
+            run_id: None,
+            stages: Vec::new(),
+            verbosity: Verbosity::Brief(3),
+            output_dir: None,
+            parameters: BTreeMap::new(),
+            recorded_env: Vec::new(),
+            version_probes: Vec::new(),
+            seeds: None,
+            files: BTreeMap::new(),
+            dependencies: BTreeMap::new(),
+            cache_dir: None,
+            cache_keys: BTreeMap::new(),
+            checkpointed: BTreeMap::new(),
+            failure_policies: BTreeMap::new(),
+            time_budget: None,
+            hooks: BTreeMap::new(),
+            cleanup: CleanupPolicy::Keep,
+        }
+    }
+
+    /// The name of the experiment.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the ID of the run, which replaces `{run_id}` placeholders, is passed to the
+    /// stages in their [`ExecutionContext`](../context/struct.ExecutionContext.html), and is
+    /// recorded in the [manifest](#method.manifest); by default, it is the name of the
+    /// experiment.
+    pub fn run_id(&mut self, id: &str) -> &mut Experiment {
+        self.run_id = Some(String::from(id));
+        self
+    }
+
+    /// Runs in an allocated [run directory](../rundir/struct.RunDir.html): sets the
+    /// [output directory](#method.output_dir) to it, which already exists, and the
+    /// [run ID](#method.run_id) to its ID.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::Experiment;
+    /// # use experiment::process::Process;
+    /// # use experiment::rundir::{RunDir, RunIdScheme};
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("runs").unwrap();
+    /// let run = RunDir::allocate(tmp.path(), "bm25", &RunIdScheme::Sequential, OverwritePolicy::Fail)
+    ///     .unwrap();
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .run_dir(&run)
+    ///     .process("echo", Process::new("sh", &["-c", "echo {run_id} > {run_dir}/id"]));
+    /// assert!(experiment.run().unwrap().success());
+    /// assert_eq!(std::fs::read_to_string(run.path().join("id")).unwrap(), "run-0001\n");
+    /// assert_eq!(experiment.manifest().unwrap().run_id, "run-0001");
+    /// ```
+    pub fn run_dir(&mut self, run: &RunDir) -> &mut Experiment {
+        self.output_dir(run.path(), OverwritePolicy::Force)
+            .run_id(run.id())
+    }
+
+    /// The context of a run, with the run ID.
+    pub(crate) fn context(&self) -> ExecutionContext {
+        ExecutionContext::new(self.run_id.as_deref().unwrap_or(&self.name))
+    }
+
+    /// Sets how the commands of the stages are shown in the
+    /// [`display`](#method.display) and in error messages.
+    pub fn verbosity(&mut self, verbosity: Verbosity) -> &mut Experiment {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Sets the output directory, which is created with [`safe_mkdir`](../fn.safe_mkdir.html)
+    /// and the given policy before any stage runs, and passed to the stages in their
+    /// [`ExecutionContext`](../context/struct.ExecutionContext.html).
+    pub fn output_dir<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        policy: OverwritePolicy,
+    ) -> &mut Experiment {
+        self.output_dir = Some((dir.as_ref().to_path_buf(), policy));
+        for (name, action) in &mut self.stages {
+            if let Action::Experiment(experiment) = action {
+                experiment.output_dir(dir.as_ref().join(name.as_str()), OverwritePolicy::Force);
+            }
+        }
+        self
+    }
+
+    /// Sets a parameter of the experiment, such as a setting of the evaluated method.
+    pub fn parameter(&mut self, name: &str, value: &str) -> &mut Experiment {
+        self.parameters
+            .insert(String::from(name), String::from(value));
+        self
+    }
+
+    /// Returns the parameters of the experiment.
+    pub fn parameters(&self) -> &BTreeMap<String, String> {
+        &self.parameters
+    }
+
+    /// Sets the master seed from which the seeds of the stages are derived, which replace
+    /// `{seed}` placeholders in their commands, and are recorded in the
+    /// [manifest](#method.manifest).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::Experiment;
+    /// # use experiment::process::Process;
+    /// # use experiment::seeds::Seeds;
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .seeds(Seeds::new(42))
+    ///     .process("sample", Process::new("sample", &["--seed", "{seed}"]));
+    /// let manifest = experiment.manifest().unwrap();
+    /// let seed = Seeds::new(42).seed("sample");
+    /// assert_eq!(manifest.master_seed, Some(42));
+    /// assert_eq!(manifest.seeds["sample"], seed);
+    /// assert_eq!(
+    ///     manifest.commands[0].1,
+    ///     Some(format!("sample --seed {}", seed))
+    /// );
+    /// ```
+    pub fn seeds(&mut self, seeds: Seeds) -> &mut Experiment {
+        self.seeds = Some(seeds);
+        self
+    }
+
+    /// Declares files read by a stage, which determine whether its outputs can be taken from
+    /// the [cache](#method.cache_dir). The paths may contain placeholders like the commands.
+    pub fn inputs<I, P>(&mut self, stage: &str, paths: I) -> &mut Experiment
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let files = self.files.entry(String::from(stage)).or_default();
+        files
+            .inputs
+            .extend(paths.into_iter().map(|path| path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Declares files written by a stage. The paths may contain placeholders like the
+    /// commands.
+    ///
+    /// After the stage runs, each output must exist and be non-empty, or the stage fails;
+    /// this catches commands that exit successfully without producing anything. The sizes
+    /// and checksums of the output files are recorded as
+    /// [artifacts](../manifest/struct.Artifact.html) in the manifest written at the end of
+    /// the run. Directories are only checked to contain something.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::checksum::Checksum;
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .output_dir(tmp.path(), OverwritePolicy::Force)
+    ///     .process("index", Process::new("sh", &["-c", "printf abc > {run_dir}/index"]))
+    ///     .outputs("index", &["{run_dir}/index"])
+    ///     .process("search", Process::new("touch", &["{run_dir}/run"]))
+    ///     .outputs("search", &["{run_dir}/run"]);
+    /// let report = experiment.run().unwrap();
+    /// assert_eq!(report.stages[0].outcome, StageOutcome::Succeeded);
+    /// assert_eq!(
+    ///     report.stages[1].outcome,
+    ///     StageOutcome::Failed(format!("Output {}: Empty file", tmp.path().join("run").display()))
+    /// );
+    ///
+    /// let manifest = std::fs::read_to_string(tmp.path().join("manifest.json")).unwrap();
+    /// assert!(manifest.contains(&format!("\"checksum\": \"{}\"", Checksum::of(b"abc"))));
+    /// ```
+    pub fn outputs<I, P>(&mut self, stage: &str, paths: I) -> &mut Experiment
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let files = self.files.entry(String::from(stage)).or_default();
+        files
+            .outputs
+            .extend(paths.into_iter().map(|path| path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Declares that a stage runs only after the given dependencies have succeeded, and is
+    /// skipped otherwise. Each dependency is either the name of another stage, or a path,
+    /// possibly with placeholders, declared as one of its [outputs](#method.outputs).
+    ///
+    /// Stages also depend on the stages whose outputs they declare as their
+    /// [inputs](#method.inputs). The stages run in the order they are declared in, except that
+    /// each one runs after its dependencies; independent branches run concurrently with
+    /// [`run_parallel`](#method.run_parallel). Running fails without running anything if a
+    /// dependency is unknown or the dependencies form a cycle.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity;
+    /// let no_args: &[&str] = &[];
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .verbosity(Verbosity::Verbose)
+    ///     .process("evaluate", Process::new("evaluate", no_args))
+    ///     .depends_on("evaluate", &["query-a", "query-b"])
+    ///     .process("query-a", Process::new("query", &["a"]))
+    ///     .depends_on("query-a", &["index.bin"])
+    ///     .process("query-b", Process::new("false", no_args))
+    ///     .depends_on("query-b", &["index"])
+    ///     .process("index", Process::new("true", no_args))
+    ///     .outputs("index", &["index.bin"]);
+    /// assert_eq!(
+    ///     experiment.plan().unwrap().to_string(),
+    ///     "bm25\n\
+    ///      [index] true\n\
+    ///      [query-a] query a (after index)\n\
+    ///      [query-b] false (after index)\n\
+    ///      [evaluate] evaluate (after query-a, query-b)"
+    /// );
+    ///
+    /// experiment.depends_on("index", &["evaluate"]);
+    /// let err = experiment.run().unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "Dependency cycle between stages `evaluate`, `query-a`, `query-b`, `index`"
+    /// );
+    /// ```
+    pub fn depends_on<I, S>(&mut self, stage: &str, dependencies: I) -> &mut Experiment
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.dependencies
+            .entry(String::from(stage))
+            .or_default()
+            .extend(
+                dependencies
+                    .into_iter()
+                    .map(|dep| String::from(dep.as_ref())),
+            );
+        self
+    }
+
+    /// Declares outputs of a stage that are only needed by later stages, e.g., an index
+    /// queried by the next ones, to be disposed of according to the
+    /// [cleanup policy](#method.cleanup) at the end of a run. They are also declared as its
+    /// [outputs](#method.outputs).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{CleanupPolicy, Experiment};
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .output_dir(tmp.path(), OverwritePolicy::Force)
+    ///     .process("index", Process::new("sh", &["-c", "echo x > {run_dir}/index"]))
+    ///     .intermediate("index", &["{run_dir}/index"])
+    ///     .process("search", Process::new("cp", &["{run_dir}/index", "{run_dir}/run"]))
+    ///     .cleanup(CleanupPolicy::DeleteOnSuccess);
+    /// assert!(experiment.run().unwrap().success());
+    /// assert!(!tmp.path().join("index").exists());
+    /// assert!(tmp.path().join("run").exists());
+    /// ```
+    pub fn intermediate<I, P>(&mut self, stage: &str, paths: I) -> &mut Experiment
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let files = self.files.entry(String::from(stage)).or_default();
+        for path in paths {
+            files.outputs.push(path.as_ref().to_path_buf());
+            files.intermediate.push(path.as_ref().to_path_buf());
+        }
+        self
+    }
+
+    /// Sets what happens to the [intermediate](#method.intermediate) outputs at the end of a
+    /// run; by default, they are kept. They are only deleted or compressed once all stages
+    /// have succeeded, so that failed runs can be inspected and resumed. Directories are
+    /// deleted, but not compressed.
+    pub fn cleanup(&mut self, policy: CleanupPolicy) -> &mut Experiment {
+        self.cleanup = policy;
+        self
+    }
+
+    /// Enables make-like caching of the stages with declared [outputs](#method.outputs) in
+    /// `dir`, which can be shared by related experiments.
+    ///
+    /// After such a stage succeeds, its outputs are copied to an entry of the cache, named
+    /// after a hash of its [key](#method.cache_key) and the contents of its
+    /// [inputs](#method.inputs). The stage is skipped in later runs if an entry exists for
+    /// the same key and inputs: outputs that are missing or changed are restored from the
+    /// entry. Closures cannot be fingerprinted, so closure stages are never cached. Entries
+    /// are listed and removed with [`Cache`](../cache/struct.Cache.html).
+    ///
+    /// The output directory is left out of the key: commands and paths that differ only in
+    /// `{run_dir}` or `{stage_dir}` share entries, so that experiments writing to different
+    /// directories reuse each other's outputs.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let input = tmp.path().join("corpus");
+    /// let output = tmp.path().join("index");
+    /// std::fs::write(&input, "documents").unwrap();
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .cache_dir(tmp.path().join("cache"))
+    ///     .process("index", Process::new("cp", &[&input, &output]))
+    ///     .inputs("index", &[&input])
+    ///     .outputs("index", &[&output]);
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::Succeeded);
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::UpToDate);
+    ///
+    /// std::fs::write(&input, "more documents").unwrap();
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::Succeeded);
+    /// assert_eq!(std::fs::read_to_string(&output).unwrap(), "more documents");
+    ///
+    /// // Both versions are cached, and restored on demand.
+    /// std::fs::write(&input, "documents").unwrap();
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::UpToDate);
+    /// assert_eq!(std::fs::read_to_string(&output).unwrap(), "documents");
+    /// std::fs::remove_file(&output).unwrap();
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::UpToDate);
+    /// assert_eq!(std::fs::read_to_string(&output).unwrap(), "documents");
+    ///
+    /// let copy = tmp.path().join("copy");
+    /// experiment
+    ///     .closure("copy", move |_| std::fs::copy(&output, &copy).map(|_| ()))
+    ///     .outputs("copy", &[tmp.path().join("copy")]);
+    /// assert_eq!(experiment.run().unwrap().stages[1].outcome, StageOutcome::Succeeded);
+    /// assert_eq!(experiment.run().unwrap().stages[1].outcome, StageOutcome::Succeeded);
+    ///
+    /// let rerun = |dir: &str| {
+    ///     let mut experiment = Experiment::new("bm25");
+    ///     experiment
+    ///         .output_dir(tmp.path().join(dir), OverwritePolicy::Fail)
+    ///         .cache_dir(tmp.path().join("cache"))
+    ///         .process("index", Process::new("cp", &[input.to_str().unwrap(), "{run_dir}/index"]))
+    ///         .inputs("index", &[&input])
+    ///         .outputs("index", &["{run_dir}/index"]);
+    ///     experiment.run().unwrap().stages[0].outcome.clone()
+    /// };
+    /// assert_eq!(rerun("first"), StageOutcome::Succeeded);
+    /// assert_eq!(rerun("second"), StageOutcome::UpToDate);
+    /// let index = tmp.path().join("second").join("index");
+    /// assert_eq!(std::fs::read_to_string(index).unwrap(), "documents");
+    /// ```
+    pub fn cache_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Experiment {
+        self.cache_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Adds properties of the environment that affect the outputs of a stage, such as
+    /// environment variables or CPU features, to its key in the [cache](#method.cache_dir).
+    /// By default, the key is the [fingerprint](../process/struct.Process.html#method.fingerprint)
+    /// of its command.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let output = tmp.path().join("index");
+    /// let build = Process::new("sh", &["-c", "echo $BUILD > \"$0\"", output.to_str().unwrap()]);
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .cache_dir(tmp.path().join("cache"))
+    ///     .process("index", build)
+    ///     .outputs("index", &[&output])
+    ///     .cache_key("index", |key| {
+    ///         key.value(&std::env::var("BUILD").unwrap_or_default());
+    ///     });
+    /// std::env::set_var("BUILD", "debug");
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::Succeeded);
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::UpToDate);
+    /// std::env::set_var("BUILD", "release");
+    /// assert_eq!(experiment.run().unwrap().stages[0].outcome, StageOutcome::Succeeded);
+    /// assert_eq!(std::fs::read_to_string(&output).unwrap(), "release\n");
+    /// ```
+    pub fn cache_key<F>(&mut self, stage: &str, key: F) -> &mut Experiment
+    where
+        F: Fn(&mut CacheKey) + Send + Sync + 'static,
+    {
+        self.cache_keys
+            .insert(String::from(stage), KeyFn(Box::new(key)));
+        self
+    }
+
+    /// Sets what happens when a stage fails; by default, the remaining stages are skipped.
+    ///
+    /// A cleanup stage run with [`FailurePolicy::Run`](enum.FailurePolicy.html#variant.Run)
+    /// must be a stage of the experiment. It runs right after the failure, regardless of its
+    /// position, unless it has already started; if it is declared last, it also runs at the
+    /// end of a successful run. Either way, it runs at most once per run.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, FailurePolicy, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # use std::sync::Arc;
+    /// let no_args: &[&str] = &[];
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .process("optional", Process::new("false", no_args))
+    ///     .on_failure("optional", FailurePolicy::Continue)
+    ///     .process("flaky", Process::new("false", no_args))
+    ///     .on_failure("flaky", FailurePolicy::Run(String::from("cleanup")))
+    ///     .process("evaluate", Process::new("true", no_args))
+    ///     .process("cleanup", Process::new("true", no_args));
+    /// let report = experiment.run().unwrap();
+    /// let outcomes: Vec<_> = report.stages.iter().map(|stage| &stage.outcome).collect();
+    /// assert!(matches!(outcomes[0], StageOutcome::Failed(_)));
+    /// assert!(matches!(outcomes[1], StageOutcome::Failed(_)));
+    /// assert_eq!(outcomes[2], &StageOutcome::Skipped);
+    /// assert_eq!(outcomes[3], &StageOutcome::Succeeded);
+    ///
+    /// let cleanups = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&cleanups);
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .closure("cleanup", move |_| {
+    ///         counter.fetch_add(1, Ordering::SeqCst);
+    ///         Ok(())
+    ///     })
+    ///     .process("flaky", Process::new("sh", &["-c", "sleep 0.1; false"]))
+    ///     .on_failure("flaky", FailurePolicy::Run(String::from("cleanup")));
+    /// assert!(!experiment.run_parallel(2).unwrap().success());
+    /// assert!(!experiment.run().unwrap().success());
+    /// assert_eq!(cleanups.load(Ordering::SeqCst), 2);
+    /// ```
+    pub fn on_failure(&mut self, stage: &str, policy: FailurePolicy) -> &mut Experiment {
+        self.failure_policies.insert(String::from(stage), policy);
+        self
+    }
+
+    /// Limits the wall-clock time of a run: once the budget runs out, no new stages start, and
+    /// the running process and pipeline stages are asked to terminate (with `SIGTERM` on Unix)
+    /// and killed if they are still running after the grace period. Closures cannot be
+    /// stopped and run to completion.
+    ///
+    /// The stopped stages fail, the ones not started are skipped, and the report of the run
+    /// is marked as [partial](struct.ExperimentReport.html#structfield.partial). The stamps of
+    /// the completed stages are written as usual, so that the run can be
+    /// [resumed](#method.resume) later.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use std::time::{Duration, Instant};
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .time_budget(Duration::from_millis(500), Duration::from_millis(100))
+    ///     .process("index", Process::new("true", &[] as &[&str]))
+    ///     .process("search", Process::new("sleep", &["10"]))
+    ///     .process("evaluate", Process::new("true", &[] as &[&str]));
+    /// let start = Instant::now();
+    /// let report = experiment.run().unwrap();
+    /// assert!(start.elapsed() < Duration::from_secs(5));
+    /// assert!(report.partial);
+    /// assert_eq!(report.stages[0].outcome, StageOutcome::Succeeded);
+    /// assert!(matches!(report.stages[1].outcome, StageOutcome::Failed(_)));
+    /// assert_eq!(report.stages[2].outcome, StageOutcome::Skipped);
+    /// ```
+    pub fn time_budget(&mut self, budget: Duration, grace: Duration) -> &mut Experiment {
+        self.time_budget = Some((budget, grace));
+        self
+    }
+
+    /// Gives a stage a [`Checkpoint`](../checkpoint/struct.Checkpoint.html) in the
+    /// [`CHECKPOINT_DIR`](constant.CHECKPOINT_DIR.html) of the output directory, which must be
+    /// set. If the stage fails after saving progress, it is restarted, up to `max_restarts`
+    /// times, to continue from the checkpoint; the checkpoint is also kept for
+    /// [resuming](#method.resume) the experiment later, and cleared once the stage succeeds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let output_dir = tmp.path().join("results");
+    /// // Fails after the first half, and completes the second half when restarted.
+    /// let script = r#"
+    ///     if [ -e "$EXPERIMENT_CHECKPOINT" ]; then echo second >> {run_dir}/index
+    ///     else echo first > {run_dir}/index; echo 1 > "$EXPERIMENT_CHECKPOINT"; exit 1
+    ///     fi"#;
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .output_dir(&output_dir, OverwritePolicy::Fail)
+    ///     .process("index", Process::new("sh", &["-c", script]))
+    ///     .checkpointed("index", 1);
+    /// assert!(experiment.run().unwrap().success());
+    /// assert_eq!(
+    ///     std::fs::read_to_string(output_dir.join("index")).unwrap(),
+    ///     "first\nsecond\n"
+    /// );
+    /// assert!(!output_dir.join(".checkpoints/index").exists());
+    /// ```
+    pub fn checkpointed(&mut self, stage: &str, max_restarts: usize) -> &mut Experiment {
+        self.checkpointed.insert(String::from(stage), max_restarts);
+        self
+    }
+
+    /// Records the value of an environment variable in the [manifest](#method.manifest) of
+    /// each run.
+    pub fn record_env(&mut self, name: &str) -> &mut Experiment {
+        self.recorded_env.push(String::from(name));
+        self
+    }
+
+    /// Declares a process reporting the version of a tool, e.g., `rustc -V`, which is run
+    /// before the first stage, and whose output is recorded in the [manifest](#method.manifest)
+    /// under `name`. A failing probe fails the run before any stage starts if the manifest is
+    /// written, i.e., if the output directory is set.
+    pub fn version_probe(&mut self, name: &str, process: Process) -> &mut Experiment {
+        self.version_probes.push((String::from(name), process));
+        self
+    }
+
+    /// Creates the [`Manifest`](../manifest/struct.Manifest.html) of a run starting now, with
+    /// the commands of the stages after replacing their placeholders, the parameters, the
+    /// recorded environment variables, and the outputs of the version probes. This is what
+    /// [`run`](#method.run) writes to the output directory.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::Experiment;
+    /// # use experiment::process::Process;
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .parameter("k", "10")
+    ///     .record_env("PATH")
+    ///     .version_probe("sh", Process::new("echo", &["sh 5.2"]))
+    ///     .process("search", Process::new("search", &["-k", "{k}"]))
+    ///     .closure("check", |_| Ok(()));
+    /// let manifest = experiment.manifest().unwrap();
+    /// assert_eq!(
+    ///     manifest.commands,
+    ///     vec![
+    ///         (String::from("search"), Some(String::from("search -k 10"))),
+    ///         (String::from("check"), None),
+    ///     ]
+    /// );
+    /// assert_eq!(manifest.parameters["k"], "10");
+    /// assert!(manifest.environment.contains_key("PATH"));
+    /// assert_eq!(manifest.versions["sh"], "sh 5.2");
+    /// ```
+    pub fn manifest(&self) -> io::Result<Manifest> {
+        let resolved = self.resolve()?;
+        self.build_manifest(&resolved)
+    }
+
+    /// Adds a stage running a process, which fails if the process exits unsuccessfully.
+    pub fn process(&mut self, name: &str, process: Process) -> &mut Experiment {
+        self.stages
+            .push((String::from(name), Action::Process(Box::new(process))));
+        self
+    }
+
+    /// Adds a stage running a pipeline, which fails if any of its processes exits
+    /// unsuccessfully; see [`ProcessPipeline::pipefail`](../process/struct.ProcessPipeline.html#method.pipefail).
+    pub fn pipeline(&mut self, name: &str, pipeline: ProcessPipeline) -> &mut Experiment {
+        self.stages
+            .push((String::from(name), Action::Pipeline(pipeline)));
+        self
+    }
+
+    /// Adds a stage calling a closure, which fails if the closure returns an error.
+    pub fn closure<F>(&mut self, name: &str, closure: F) -> &mut Experiment
+    where
+        F: Fn(&ExecutionContext) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.stages
+            .push((String::from(name), Action::Closure(Box::new(closure))));
+        self
+    }
+
+    /// Adds a stage running another experiment, which fails if any stage of that experiment
+    /// fails. If this experiment has an output directory, the nested one writes to its
+    /// subdirectory named after the stage, instead of its own output directory.
+    ///
+    /// The nested experiment is run as a whole, and resolves its placeholders on its own.
+    /// Like a closure, it cannot be fingerprinted, so its stamp only records that it
+    /// completed, and it is never cached.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let mut index = Experiment::new("index");
+    /// index
+    ///     .process("build", Process::new("sh", &["-c", "echo x > {run_dir}/index"]))
+    ///     .process("check", Process::new("test", &["-s", "{run_dir}/index"]));
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .output_dir(tmp.path().join("results"), OverwritePolicy::Fail)
+    ///     .experiment("index", index)
+    ///     .process("search", Process::new("cat", &["{run_dir}/index/index"]));
+    /// assert!(experiment.run().unwrap().success());
+    /// assert!(tmp.path().join("results/index/manifest.json").exists());
+    ///
+    /// let mut broken = Experiment::new("index");
+    /// broken.process("build", Process::new("false", &[] as &[&str]));
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .experiment("index", broken)
+    ///     .process("search", Process::new("true", &[] as &[&str]));
+    /// let report = experiment.run().unwrap();
+    /// assert_eq!(
+    ///     report.stages[0].outcome,
+    ///     StageOutcome::Failed(String::from("Experiment `index` failed: build"))
+    /// );
+    /// assert_eq!(report.stages[1].outcome, StageOutcome::Skipped);
+    /// ```
+    pub fn experiment(&mut self, name: &str, mut experiment: Experiment) -> &mut Experiment {
+        if let Some((dir, _)) = &self.output_dir {
+            experiment.output_dir(dir.join(name), OverwritePolicy::Force);
+        }
+        self.stages
+            .push((String::from(name), Action::Experiment(Box::new(experiment))));
+        self
+    }
+
+    /// Adds a hook run as a stage named `<stage>:before` right before the given stage, which
+    /// is skipped if the hook fails.
+    ///
+    /// Hooks are stages like any other: they are listed in the [plan](#method.plan) and the
+    /// report of the run, and can refer to placeholders. The hooks of a stage are placed next
+    /// to it; if there are several of the same kind, their names are numbered from the second
+    /// one on, e.g., `index:before:2`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, Hook, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity;
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .verbosity(Verbosity::Verbose)
+    ///     .process("index", Process::new("build_index", &["corpus"]))
+    ///     .process("search", Process::new("false", &[] as &[&str]))
+    ///     .process("evaluate", Process::new("evaluate", &["run"]))
+    ///     .before("search", Process::new("cat", &["index"]))
+    ///     .after("search", Process::new("dmesg", &["--since", "{run_id}"]))
+    ///     .at_start(Hook::closure(|_| Ok(())))
+    ///     .at_end(Process::new("true", &[] as &[&str]));
+    /// assert_eq!(
+    ///     experiment.plan().unwrap().to_string(),
+    ///     "bm25\n\
+    ///      [run:start] <closure>\n\
+    ///      [index] build_index corpus\n\
+    ///      [search:before] cat index\n\
+    ///      [search] false\n\
+    ///      [search:after] dmesg --since bm25\n\
+    ///      [evaluate] evaluate run\n\
+    ///      [run:end] true"
+    /// );
+    ///
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .process("search", Process::new("false", &[] as &[&str]))
+    ///     .process("evaluate", Process::new("true", &[] as &[&str]))
+    ///     .after("search", Process::new("true", &[] as &[&str]))
+    ///     .at_end(Process::new("true", &[] as &[&str]));
+    /// let report = experiment.run().unwrap();
+    /// let outcomes: Vec<_> = report
+    ///     .stages
+    ///     .iter()
+    ///     .map(|stage| (stage.name.as_str(), &stage.outcome))
+    ///     .collect();
+    /// assert!(matches!(outcomes[0], ("search", StageOutcome::Failed(_))));
+    /// assert_eq!(outcomes[1], ("search:after", &StageOutcome::Succeeded));
+    /// assert_eq!(outcomes[2], ("evaluate", &StageOutcome::Skipped));
+    /// assert_eq!(outcomes[3], ("run:end", &StageOutcome::Succeeded));
+    /// ```
+    pub fn before<H: Into<Hook>>(&mut self, stage: &str, hook: H) -> &mut Experiment {
+        let position = self.stage_index(stage);
+        self.add_hook(
+            HookPoint::Before(String::from(stage)),
+            hook.into(),
+            position,
+        )
+    }
+
+    /// Adds a hook run as a stage named `<stage>:after` once the given stage finishes, even
+    /// if it fails and the failure aborts the run, but not if it does not run at all; see
+    /// [`before`](#method.before).
+    pub fn after<H: Into<Hook>>(&mut self, stage: &str, hook: H) -> &mut Experiment {
+        let position = self.stage_index(stage).map(|idx| idx + 1);
+        self.add_hook(HookPoint::After(String::from(stage)), hook.into(), position)
+    }
+
+    /// Adds a hook run as a stage named `run:start` before all other stages, which are
+    /// skipped if the hook fails; see [`before`](#method.before).
+    pub fn at_start<H: Into<Hook>>(&mut self, hook: H) -> &mut Experiment {
+        let position = self
+            .stages
+            .iter()
+            .take_while(|(name, _)| self.hooks.get(name) == Some(&HookPoint::Start))
+            .count();
+        self.add_hook(HookPoint::Start, hook.into(), Some(position))
+    }
+
+    /// Adds a hook run as a stage named `run:end` after all other stages, even if some of
+    /// them fail and abort the run; see [`before`](#method.before).
+    pub fn at_end<H: Into<Hook>>(&mut self, hook: H) -> &mut Experiment {
+        self.add_hook(HookPoint::End, hook.into(), None)
+    }
+
+    /// Inserts the hook as a stage at the given position, or at the end.
+    fn add_hook(
+        &mut self,
+        point: HookPoint,
+        hook: Hook,
+        position: Option<usize>,
+    ) -> &mut Experiment {
+        let base = match &point {
+            HookPoint::Before(stage) => format!("{}:before", stage),
+            HookPoint::After(stage) => format!("{}:after", stage),
+            HookPoint::Start => String::from("run:start"),
+            HookPoint::End => String::from("run:end"),
+        };
+        let name = (1..)
+            .map(|n| match n {
+                1 => base.clone(),
+                n => format!("{}:{}", base, n),
+            })
+            .find(|name| self.stage_index(name).is_none())
+            .expect("Unbounded names");
+        let mut position = position.unwrap_or(self.stages.len());
+        if let HookPoint::After(_) = point {
+            // Place it after the earlier hooks of the same stage.
+            while position < self.stages.len()
+                && self.hooks.get(&self.stages[position].0) == Some(&point)
+            {
+                position += 1;
+            }
+        }
+        self.stages.insert(position, (name.clone(), hook.action));
+        self.hooks.insert(name, point);
+        self
+    }
+
+    /// Names of the stages, in order.
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Creates an [`ExperimentDisplay`](struct.ExperimentDisplay.html) object listing the
+    /// stages with the experiment's verbosity.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::Experiment;
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .process("index", Process::new("build_index", &["-c", "corpus", "-o", "index"]))
+    ///     .pipeline(
+    ///         "queries",
+    ///         pipeline!(Process::new("zcat", &["topics.gz"]), Process::new("parse", &["-q"])),
+    ///     )
+    ///     .closure("cleanup", |_| Ok(()));
+    /// assert_eq!(
+    ///     experiment.display().to_string(),
+    ///     "bm25\n\
+    ///      [index] build_index -c corpus -o ...\n\
+    ///      [queries] zcat topics.gz\n\t| parse -q\n\
+    ///      [cleanup] <closure>"
+    /// );
+    /// ```
+    pub fn display(&self) -> ExperimentDisplay<'_> {
+        ExperimentDisplay { experiment: self }
+    }
+
+    /// Resolves the commands of all stages, as they would be run, without running anything or
+    /// touching the file system. The returned [`ExperimentPlan`](struct.ExperimentPlan.html)
+    /// lists them with the experiment's verbosity, after the output directory, if set.
+    ///
+    /// Fails if a placeholder cannot be replaced. To plan a whole sweep, plan each of the
+    /// [experiments of a grid](../sweep/struct.Grid.html#method.experiments).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::Experiment;
+    /// # use experiment::process::Process;
+    /// # use experiment::{OverwritePolicy, Verbosity};
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .output_dir("results", OverwritePolicy::Fail)
+    ///     .parameter("k1", "0.9")
+    ///     .process("index", Process::new("build_index", &["-o", "{run_dir}/index"]))
+    ///     .process("search", Process::new("search", &["--k1", "{k1}", "-o", "{stage_dir}"]));
+    /// assert_eq!(
+    ///     experiment.plan().unwrap().to_string(),
+    ///     "bm25 -> results\n\
+    ///      [index] build_index -o results/index\n\
+    ///      [search] search --k1 0.9 -o ..."
+    /// );
+    /// experiment.process("typo", Process::new("evaluate", &["{k2}"]));
+    /// assert!(experiment.plan().is_err());
+    /// # #[cfg(feature = "sweep")]
+    /// # {
+    /// # use experiment::sweep::Grid;
+    /// # use experiment::template::CommandTemplate;
+    ///
+    /// let mut grid = Grid::new();
+    /// grid.parameter("k", &[10, 1000])
+    ///     .output_dir("results", OverwritePolicy::Fail);
+    /// let query = CommandTemplate::new("query", &["-k", "{k}"]);
+    /// let plans = grid
+    ///     .experiments("queries", &[("query", query)])
+    ///     .unwrap()
+    ///     .iter_mut()
+    ///     .map(|experiment| experiment.verbosity(Verbosity::Verbose).plan().unwrap().to_string())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(
+    ///     plans,
+    ///     vec![
+    ///         "queries[k=10] -> results/k=10\n[query] query -k 10",
+    ///         "queries[k=1000] -> results/k=1000\n[query] query -k 1000",
+    ///     ]
+    /// );
+    /// # }
+    /// ```
+    pub fn plan(&self) -> io::Result<ExperimentPlan<'_>> {
+        let resolved = self.resolve()?;
+        let graph = self.graph(&resolved)?;
+        Ok(ExperimentPlan {
+            experiment: self,
+            resolved,
+            graph,
+        })
+    }
+
+    /// Checks that everything the stages need before they start is in place: the declared
+    /// [inputs](#method.inputs) exist and are readable, the programs of processes and
+    /// pipelines can be [found](../process/struct.Process.html#method.resolve), and their
+    /// working directories exist. Inputs produced by stages as their declared
+    /// [outputs](#method.outputs), and paths in the output directory, are left out, since
+    /// they are only created during the run.
+    ///
+    /// Every problem found is reported in a single `NotFound` error, one per line. The check
+    /// also runs before the first stage of each [run](#method.run), so that a typo does not
+    /// surface hours into it.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::Experiment;
+    /// # use experiment::process::Process;
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .process("index", Process::new("cp", &["corpus", "index"]))
+    ///     .inputs("index", &["corpus"])
+    ///     .outputs("index", &["index"])
+    ///     .process("search", Process::new("search_index", &["index"]))
+    ///     .inputs("search", &["index"]);
+    /// let err = experiment.preflight().unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "Pre-flight check failed:\n\
+    ///      [index] input corpus: No such file or directory (os error 2)\n\
+    ///      [search] search_index: executable not found"
+    /// );
+    /// assert_eq!(experiment.run().unwrap_err().to_string(), err.to_string());
+    /// ```
+    pub fn preflight(&self) -> io::Result<()> {
+        self.check_preflight(&self.resolve()?)
+    }
+
+    fn check_preflight(&self, resolved: &[Resolved]) -> io::Result<()> {
+        let run_dir = self.output_dir.as_ref().map(|(dir, _)| dir);
+        let created = |path: &Path| {
+            run_dir.is_some_and(|dir| path.starts_with(dir))
+                || resolved
+                    .iter()
+                    .flat_map(|stage| &stage.files.outputs)
+                    .any(|output| path.starts_with(output))
+        };
+        let mut problems = Vec::new();
+        for ((name, action), stage) in self.stages.iter().zip(resolved) {
+            let mut problem = |what: String| problems.push(format!("[{}] {}", name, what));
+            for input in stage.files.inputs.iter().filter(|input| !created(input)) {
+                let readable = if input.is_dir() {
+                    std::fs::read_dir(input).map(|_| ())
+                } else {
+                    std::fs::File::open(input).map(|_| ())
+                };
+                if let Err(err) = readable {
+                    problem(format!("input {}: {}", input.display(), err));
+                }
+            }
+            let processes = match stage.action.as_ref().unwrap_or(action) {
+                Action::Process(process) => std::slice::from_ref(&**process),
+                Action::Pipeline(pipeline) => pipeline.processes(),
+                Action::Closure(_) | Action::Experiment(_) => &[],
+            };
+            for process in processes {
+                match process.get_current_dir() {
+                    Some(dir) if created(dir) => continue,
+                    Some(dir) if !dir.is_dir() => {
+                        problem(format!("directory {}: not found", dir.display()));
+                        continue;
+                    }
+                    _ => {}
+                }
+                if let Err(err) = process.resolve() {
+                    problem(err.to_string());
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Pre-flight check failed:\n{}", problems.join("\n")),
+            ))
+        }
+    }
+
+    /// Runs all stages in order, each after its [dependencies](#method.depends_on), and returns
+    /// the report of each of them in the order of declaration. Once a stage fails,
+    /// the remaining ones are skipped, unless its [failure policy](#method.on_failure) says
+    /// otherwise.
+    ///
+    /// If the output directory is set, it is [locked](../lock/struct.RunLock.html) for the
+    /// duration of the run, and the [manifest](#method.manifest) of the run is written to it
+    /// before the first stage starts. Fails without running anything if a placeholder cannot
+    /// be replaced, a version probe fails, another run holds the lock, or the output directory
+    /// or the manifest cannot be written.
+    ///
+    /// After each stage succeeds, a stamp with its [fingerprint](../process/struct.Process.html#method.fingerprint)
+    /// is written to the [`STAMP_DIR`](constant.STAMP_DIR.html) of the output directory, so
+    /// that the run can be [resumed](#method.resume).
+    pub fn run(&self) -> io::Result<ExperimentReport> {
+        self.execute(false, 1, self.context())
+    }
+
+    /// Runs the stages like [`run`](#method.run), but on up to `jobs` threads, with the
+    /// output of each process stage streamed line by line, labeled with the name of the
+    /// stage (see [`Process::execute_labeled`](../process/struct.Process.html#method.execute_labeled)).
+    ///
+    /// Stages are assumed to be independent, except that a stage starts only after all its
+    /// [dependencies](#method.depends_on) have succeeded, and is skipped if any of them did
+    /// not.
+    /// When a stage fails and its [failure policy](#method.on_failure) aborts the run, no new
+    /// stages start, but the running ones finish. The failed stages can be listed with
+    /// [`ExperimentReport::failures`](struct.ExperimentReport.html#method.failures).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{Experiment, FailurePolicy, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .output_dir(tmp.path(), OverwritePolicy::Force)
+    ///     .process("index", Process::new("sh", &["-c", "sleep 0.2; echo x > {run_dir}/index"]))
+    ///     .outputs("index", &["{run_dir}/index"]);
+    /// for k in &[10, 100, 1000] {
+    ///     let name = format!("query-{}", k);
+    ///     let query = format!("cat {{run_dir}}/index > {{run_dir}}/{}", name);
+    ///     experiment
+    ///         .process(&name, Process::new("sh", &["-c", &query]))
+    ///         .inputs(&name, &["{run_dir}/index"])
+    ///         .on_failure(&name, FailurePolicy::Continue);
+    /// }
+    /// experiment
+    ///     .process("fail", Process::new("false", &[] as &[&str]))
+    ///     .on_failure("fail", FailurePolicy::Continue);
+    /// let report = experiment.run_parallel(4).unwrap();
+    /// assert!(!report.success());
+    /// let failures: Vec<_> = report.failures().iter().map(|stage| &stage.name).collect();
+    /// assert_eq!(failures, vec!["fail"]);
+    /// assert_eq!(std::fs::read_to_string(tmp.path().join("query-1000")).unwrap(), "x\n");
+    /// ```
+    pub fn run_parallel(&self, jobs: usize) -> io::Result<ExperimentReport> {
+        self.execute(false, jobs.max(1), self.context())
+    }
+
+    /// Runs the stages like [`run`](#method.run), but skips the leading stages that have a
+    /// stamp matching their current fingerprint, i.e., that completed in an earlier run and
+    /// have not changed since, and whose [outputs](#method.outputs) are all still in place.
+    /// All stages from the first one with a missing or different stamp onwards are run.
+    /// Closures cannot be fingerprinted, so their stamps only record that they completed.
+    /// The outputs of the skipped stages are recorded in the manifest as if they had run.
+    ///
+    /// The output directory must be set, and is created if missing regardless of the
+    /// overwrite policy.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::checksum::Checksum;
+    /// # use experiment::experiment::{Experiment, StageOutcome};
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("experiment").unwrap();
+    /// let experiment = |evaluate: &str| {
+    ///     let mut experiment = Experiment::new("bm25");
+    ///     experiment
+    ///         .output_dir(tmp.path(), OverwritePolicy::Fail)
+    ///         .process("index", Process::new("sh", &["-c", "echo x >> {run_dir}/index"]))
+    ///         .process("evaluate", Process::new(evaluate, &[] as &[&str]));
+    ///     experiment
+    /// };
+    /// let report = experiment("false").resume().unwrap();
+    /// assert!(matches!(report.stages[1].outcome, StageOutcome::Failed(_)));
+    ///
+    /// let report = experiment("true").resume().unwrap();
+    /// assert_eq!(report.stages[0].outcome, StageOutcome::UpToDate);
+    /// assert_eq!(report.stages[1].outcome, StageOutcome::Succeeded);
+    /// assert!(report.success());
+    /// assert_eq!(std::fs::read_to_string(tmp.path().join("index")).unwrap(), "x\n");
+    ///
+    /// let mut experiment = experiment("true");
+    /// experiment.outputs("index", &["{run_dir}/index"]);
+    /// let report = experiment.resume().unwrap();
+    /// assert_eq!(report.stages[0].outcome, StageOutcome::UpToDate);
+    /// let manifest = std::fs::read_to_string(tmp.path().join("manifest.json")).unwrap();
+    /// assert!(manifest.contains(&format!("\"checksum\": \"{}\"", Checksum::of(b"x\n"))));
+    ///
+    /// std::fs::remove_file(tmp.path().join("index")).unwrap();
+    /// let report = experiment.resume().unwrap();
+    /// assert_eq!(report.stages[0].outcome, StageOutcome::Succeeded);
+    /// ```
+    pub fn resume(&self) -> io::Result<ExperimentReport> {
+        self.execute(true, 1, self.context())
+    }
+
+    /// Runs the stages like [`run`](#method.run), starting with the given context.
+    #[cfg(feature = "executor")]
+    pub(crate) fn run_in(&self, context: ExecutionContext) -> io::Result<ExperimentReport> {
+        self.execute(false, 1, context)
+    }
+
+    fn execute(
+        &self,
+        resume: bool,
+        jobs: usize,
+        mut context: ExecutionContext,
+    ) -> io::Result<ExperimentReport> {
+        let execution = Execution {
+            labeled: jobs > 1,
+            deadline: self
+                .time_budget
+                .map(|(budget, grace)| (Instant::now() + budget, grace)),
+            out_of_time: AtomicBool::new(false),
+            artifacts: Mutex::new(BTreeMap::new()),
+        };
+        let resolved = self.resolve()?;
+        let graph = self.graph(&resolved)?;
+        self.check_preflight(&resolved)?;
+        for policy in self.failure_policies.values() {
+            if let FailurePolicy::Run(cleanup) = policy {
+                if self.stage_index(cleanup).is_none() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown cleanup stage `{}`", cleanup),
+                    ));
+                }
+            }
+        }
+        let mut _lock = None;
+        let mut manifest = None;
+        match &self.output_dir {
+            Some((dir, policy)) => {
+                if resume {
+                    std::fs::create_dir_all(dir)?;
+                } else {
+                    safe_mkdir(dir, *policy)?;
+                }
+                _lock = Some(RunLock::acquire(dir)?);
+                std::fs::create_dir_all(dir.join(STAMP_DIR))?;
+                let started = self.build_manifest(&resolved)?;
+                started.write(dir)?;
+                manifest = Some((started, dir));
+                context.output_dir(dir);
+            }
+            None if resume => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Resuming requires an output directory",
+                ))
+            }
+            None if !self.checkpointed.is_empty() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Checkpointed stages require an output directory",
+                ))
+            }
+            None => {}
+        }
+        if let Some(dir) = &self.cache_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let reports = if jobs > 1 {
+            self.run_concurrently(&resolved, &graph, &execution, &context, jobs)
+        } else {
+            self.run_sequentially(&resolved, &graph, &execution, &mut context, resume)
+        };
+        let stages = reports
+            .into_iter()
+            .zip(&self.stages)
+            .map(|(report, (name, _))| {
+                report.unwrap_or_else(|| StageReport {
+                    name: name.clone(),
+                    outcome: StageOutcome::Skipped,
+                    duration: Duration::default(),
+                })
+            })
+            .collect();
+        let report = ExperimentReport {
+            stages,
+            partial: execution.out_of_time.load(Ordering::SeqCst),
+        };
+        if let Some((mut manifest, dir)) = manifest {
+            let artifacts = execution.artifacts.into_inner().unwrap();
+            manifest.artifacts = artifacts.into_values().flatten().collect();
+            manifest.write(dir)?;
+        }
+        if report.success() {
+            self.clean_up(&resolved).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Cleaning up intermediate outputs: {}", err),
+                )
+            })?;
+        }
+        Ok(report)
+    }
+
+    /// Applies the cleanup policy to the intermediate outputs of all stages.
+    fn clean_up(&self, resolved: &[Resolved]) -> io::Result<()> {
+        let paths = resolved
+            .iter()
+            .flat_map(|stage| &stage.files.intermediate)
+            .filter(|path| path.exists());
+        match self.cleanup {
+            CleanupPolicy::Keep => Ok(()),
+            CleanupPolicy::DeleteOnSuccess => {
+                for path in paths {
+                    if path.is_dir() {
+                        std::fs::remove_dir_all(path)?;
+                    } else {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                Ok(())
+            }
+            CleanupPolicy::CompressOnSuccess(codec) => {
+                let files: Vec<_> = paths.filter(|path| path.is_file()).collect();
+                let threads = std::thread::available_parallelism().map_or(1, usize::from);
+                compress_files(&files, codec, threads).map(|_| ())
+            }
+        }
+    }
+
+    /// Runs the stages in order, until one fails and its policy aborts the run. If `resume` is
+    /// set, the leading stages with up-to-date stamps are not run again.
+    fn run_sequentially(
+        &self,
+        resolved: &[Resolved],
+        graph: &Graph,
+        execution: &Execution,
+        context: &mut ExecutionContext,
+        resume: bool,
+    ) -> Vec<Option<StageReport>> {
+        let mut reports: Vec<Option<StageReport>> = vec![None; self.stages.len()];
+        let mut up_to_date = resume;
+        let mut aborted = false;
+        for &idx in &graph.order {
+            if aborted && graph.teardown[idx].is_none() {
+                continue;
+            }
+            if execution.expired() {
+                break;
+            }
+            let (name, _) = &self.stages[idx];
+            if graph.blocked(idx, &reports) {
+                reports[idx] = Some(StageReport {
+                    name: name.clone(),
+                    outcome: StageOutcome::Skipped,
+                    duration: Duration::default(),
+                });
+                continue;
+            }
+            let report = self.run_stage(idx, &resolved[idx], execution, context, up_to_date);
+            up_to_date = up_to_date && report.outcome == StageOutcome::UpToDate;
+            let failed = matches!(report.outcome, StageOutcome::Failed(_));
+            reports[idx] = Some(report);
+            if !failed {
+                continue;
+            }
+            match self.failure_policy(name) {
+                FailurePolicy::Continue => continue,
+                FailurePolicy::Abort | FailurePolicy::Retry(_) => {}
+                FailurePolicy::Run(cleanup) => {
+                    // A cleanup stage that already ran is not run again.
+                    if let Some(cleanup) = self.stage_index(cleanup) {
+                        if reports[cleanup].is_none() {
+                            let report = self.run_stage(
+                                cleanup,
+                                &resolved[cleanup],
+                                execution,
+                                context,
+                                false,
+                            );
+                            reports[cleanup] = Some(report);
+                        }
+                    }
+                }
+            }
+            aborted = true;
+        }
+        reports
+    }
+
+    /// Runs the stages on up to `jobs` threads. A stage starts once all its dependencies have
+    /// finished, and is skipped unless all of them succeeded.
+    fn run_concurrently(
+        &self,
+        resolved: &[Resolved],
+        graph: &Graph,
+        execution: &Execution,
+        context: &ExecutionContext,
+        jobs: usize,
+    ) -> Vec<Option<StageReport>> {
+        let state = Mutex::new(Schedule {
+            reports: vec![None; self.stages.len()],
+            started: vec![false; self.stages.len()],
+            aborted: false,
+        });
+        let finished = Condvar::new();
+        let worker = || {
+            let mut context = context.clone();
+            let mut schedule = state.lock().expect("Poisoned lock");
+            loop {
+                let aborted = schedule.aborted;
+                let pending: Vec<_> = (graph.order.iter().copied())
+                    .filter(|&idx| {
+                        !schedule.started[idx] && (!aborted || graph.teardown[idx].is_some())
+                    })
+                    .collect();
+                let ready = pending.iter().copied().find(|&idx| {
+                    graph.dependencies[idx]
+                        .iter()
+                        .chain(&graph.after[idx])
+                        .all(|&dep| {
+                            schedule.reports[dep].is_some() || (aborted && !schedule.started[dep])
+                        })
+                });
+                let idx = match ready {
+                    Some(idx) => idx,
+                    None if pending.is_empty() => break,
+                    None => {
+                        schedule = finished.wait(schedule).expect("Poisoned lock");
+                        continue;
+                    }
+                };
+                if execution.expired() {
+                    break;
+                }
+                schedule.started[idx] = true;
+                let (name, _) = &self.stages[idx];
+                if graph.blocked(idx, &schedule.reports) {
+                    schedule.reports[idx] = Some(StageReport {
+                        name: name.clone(),
+                        outcome: StageOutcome::Skipped,
+                        duration: Duration::default(),
+                    });
+                    finished.notify_all();
+                    continue;
+                }
+                drop(schedule);
+                let report = self.run_stage(idx, &resolved[idx], execution, &mut context, false);
+                let failed = matches!(report.outcome, StageOutcome::Failed(_));
+                schedule = state.lock().expect("Poisoned lock");
+                schedule.reports[idx] = Some(report);
+                let mut cleanup = None;
+                if failed {
+                    match self.failure_policy(name) {
+                        FailurePolicy::Continue => {}
+                        FailurePolicy::Abort | FailurePolicy::Retry(_) => schedule.aborted = true,
+                        FailurePolicy::Run(stage) => {
+                            schedule.aborted = true;
+                            // Another worker may have started the cleanup stage already.
+                            cleanup = self
+                                .stage_index(stage)
+                                .filter(|&cleanup| !schedule.started[cleanup]);
+                        }
+                    }
+                }
+                if let Some(cleanup) = cleanup {
+                    schedule.started[cleanup] = true;
+                    finished.notify_all();
+                    drop(schedule);
+                    let report =
+                        self.run_stage(cleanup, &resolved[cleanup], execution, &mut context, false);
+                    schedule = state.lock().expect("Poisoned lock");
+                    schedule.reports[cleanup] = Some(report);
+                }
+                finished.notify_all();
+            }
+        };
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.min(self.stages.len()) {
+                scope.spawn(worker);
+            }
+        });
+        state.into_inner().expect("Poisoned lock").reports
+    }
+
+    /// Runs a stage as many times as its failure policy allows, unless `check_stamp` is set
+    /// and its stamp is up to date with all outputs in place, or its outputs are cached.
+    fn run_stage(
+        &self,
+        idx: usize,
+        resolved: &Resolved,
+        execution: &Execution,
+        context: &mut ExecutionContext,
+        check_stamp: bool,
+    ) -> StageReport {
+        let (name, action) = &self.stages[idx];
+        let action = resolved.action.as_ref().unwrap_or(action);
+        let stamp = stamp(action);
+        let stamp_path = self
+            .output_dir
+            .as_ref()
+            .map(|(dir, _)| dir.join(STAMP_DIR).join(name));
+        let report = |outcome, duration| StageReport {
+            name: name.clone(),
+            outcome,
+            duration,
+        };
+        let files = &resolved.files;
+        let up_to_date = |artifacts| {
+            execution.artifacts.lock().unwrap().insert(idx, artifacts);
+            StageOutcome::UpToDate
+        };
+        if check_stamp {
+            let recorded = stamp_path
+                .as_ref()
+                .and_then(|path| std::fs::read_to_string(path).ok());
+            if recorded.as_ref() == Some(&stamp) {
+                if let Ok(artifacts) = verify_outputs(name, &files.outputs) {
+                    return report(up_to_date(artifacts), Duration::default());
+                }
+            }
+        }
+        let start = Instant::now();
+        let cache_entry = match &self.cache_dir {
+            Some(dir) if !files.outputs.is_empty() => match self.cache_entry(dir, name, resolved) {
+                Ok(entry) => entry,
+                Err(err) => return report(StageOutcome::Failed(err.to_string()), start.elapsed()),
+            },
+            _ => None,
+        };
+        if let Some(entry) = &cache_entry {
+            if let Some(artifacts) = entry.restore(name, &files.outputs) {
+                let outcome = match stamp_path.map(|path| std::fs::write(path, &stamp)) {
+                    Some(Err(err)) => StageOutcome::Failed(err.to_string()),
+                    _ => up_to_date(artifacts),
+                };
+                return report(outcome, start.elapsed());
+            }
+        }
+        context.stage(name);
+        let attempts = match self.failure_policy(name) {
+            FailurePolicy::Retry(retries) => retries + 1,
+            _ => 1,
+        };
+        let mut result = Ok(Vec::new());
+        for attempt in 0..attempts {
+            if attempt > 0 && execution.expired() {
+                break;
+            }
+            let mut attempt: io::Result<()> = Ok(());
+            if let Some(path) = &stamp_path {
+                attempt = remove_if_exists(path);
+            }
+            if let (Some((dir, _)), true) = (&self.output_dir, resolved.uses_stage_dir) {
+                attempt = attempt.and_then(|_| std::fs::create_dir_all(dir.join(name)));
+            }
+            attempt = attempt.and_then(|_| self.run_checkpointed(name, action, execution, context));
+            let mut attempt = attempt.and_then(|_| verify_outputs(name, &files.outputs));
+            if let (Ok(artifacts), Some(entry)) = (&attempt, &cache_entry) {
+                if let Err(err) = entry.store(name, &files.outputs, artifacts) {
+                    attempt = Err(err);
+                }
+            }
+            if let (Ok(_), Some(path)) = (&attempt, &stamp_path) {
+                if let Err(err) = std::fs::write(path, &stamp) {
+                    attempt = Err(err);
+                }
+            }
+            result = attempt;
+            if result.is_ok() {
+                break;
+            }
+        }
+        let outcome = match result {
+            Ok(artifacts) => {
+                execution.artifacts.lock().unwrap().insert(idx, artifacts);
+                StageOutcome::Succeeded
+            }
+            Err(err) => {
+                if err.kind() == io::ErrorKind::TimedOut && execution.expired() {
+                    execution.out_of_time.store(true, Ordering::SeqCst);
+                }
+                StageOutcome::Failed(err.to_string())
+            }
+        };
+        report(outcome, start.elapsed())
+    }
+
+    /// The cache entry of a stage, or `None` for a closure or a nested experiment.
+    fn cache_entry(
+        &self,
+        cache_dir: &Path,
+        stage: &str,
+        resolved: &Resolved,
+    ) -> io::Result<Option<cache::Entry>> {
+        let mut key = match resolved.fingerprint {
+            Some(fingerprint) => CacheKey::new(fingerprint),
+            None => return Ok(None),
+        };
+        if let Some(KeyFn(extend)) = self.cache_keys.get(stage) {
+            extend(&mut key);
+        }
+        let run_dir = self.output_dir.as_ref().map(|(dir, _)| dir.as_path());
+        cache::Entry::new(cache_dir, key.finish(), &resolved.files.inputs, run_dir).map(Some)
+    }
+
+    fn failure_policy(&self, stage: &str) -> &FailurePolicy {
+        self.failure_policies
+            .get(stage)
+            .unwrap_or(&FailurePolicy::Abort)
+    }
+
+    fn stage_index(&self, stage: &str) -> Option<usize> {
+        self.stages.iter().position(|(name, _)| name == stage)
+    }
+
+    /// Finds the dependencies of all stages, and orders the stages so that each one comes
+    /// after its dependencies, and otherwise in the order of declaration.
+    fn graph(&self, resolved: &[Resolved]) -> io::Result<Graph> {
+        let producer = |path: &Path| {
+            resolved
+                .iter()
+                .position(|stage| stage.files.outputs.iter().any(|output| output == path))
+        };
+        let mut dependencies = Vec::with_capacity(resolved.len());
+        for (idx, stage) in resolved.iter().enumerate() {
+            let mut deps = Vec::new();
+            for dependency in &stage.dependencies {
+                match self
+                    .stage_index(dependency)
+                    .or_else(|| producer(Path::new(dependency)))
+                {
+                    Some(dep) => deps.push(dep),
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "Stage `{}`: Unknown dependency `{}`",
+                                self.stages[idx].0, dependency
+                            ),
+                        ))
+                    }
+                }
+            }
+            deps.extend(
+                stage
+                    .files
+                    .inputs
+                    .iter()
+                    .filter_map(|input| producer(input)),
+            );
+            let name = &self.stages[idx].0;
+            for (hook, point) in &self.hooks {
+                let applies = match point {
+                    HookPoint::Before(stage) => stage == name,
+                    HookPoint::Start => !matches!(
+                        self.hooks.get(name),
+                        Some(HookPoint::Start) | Some(HookPoint::End)
+                    ),
+                    HookPoint::After(_) | HookPoint::End => false,
+                };
+                if applies {
+                    deps.extend(self.stage_index(hook));
+                }
+            }
+            deps.retain(|&dep| dep != idx);
+            deps.sort_unstable();
+            deps.dedup();
+            dependencies.push(deps);
+        }
+        let mut after = vec![Vec::new(); resolved.len()];
+        let mut teardown = vec![None; resolved.len()];
+        for (idx, (name, _)) in self.stages.iter().enumerate() {
+            match self.hooks.get(name) {
+                Some(HookPoint::After(stage)) => {
+                    let target = self.stage_index(stage).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Stage `{}`: Unknown stage `{}`", name, stage),
+                        )
+                    })?;
+                    after[idx].push(target);
+                    teardown[idx] = Some(Some(target));
+                }
+                Some(HookPoint::End) => {
+                    after[idx] = (0..resolved.len())
+                        .filter(|&other| {
+                            self.hooks.get(&self.stages[other].0) != Some(&HookPoint::End)
+                        })
+                        .collect();
+                    teardown[idx] = Some(None);
+                }
+                Some(HookPoint::Before(stage)) if self.stage_index(stage).is_none() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Stage `{}`: Unknown stage `{}`", name, stage),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        let mut order = Vec::with_capacity(resolved.len());
+        let mut ordered = vec![false; resolved.len()];
+        while let Some(idx) = (0..resolved.len()).find(|&idx| {
+            !ordered[idx]
+                && dependencies[idx]
+                    .iter()
+                    .chain(&after[idx])
+                    .all(|&dep| ordered[dep])
+        }) {
+            ordered[idx] = true;
+            order.push(idx);
+        }
+        if order.len() < resolved.len() {
+            let cycle: Vec<_> = (0..resolved.len())
+                .filter(|&idx| !ordered[idx])
+                .map(|idx| format!("`{}`", self.stages[idx].0))
+                .collect();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Dependency cycle between stages {}", cycle.join(", ")),
+            ));
+        }
+        Ok(Graph {
+            dependencies,
+            after,
+            teardown,
+            order,
+        })
+    }
+
+    /// Replaces the placeholders in the commands and files of all stages.
+    fn resolve(&self) -> io::Result<Vec<Resolved>> {
+        self.stages
+            .iter()
+            .map(|(name, action)| self.interpolate(name, action))
+            .collect()
+    }
+
+    fn build_manifest(&self, resolved: &[Resolved]) -> io::Result<Manifest> {
+        let run_id = self.run_id.as_deref().unwrap_or(&self.name);
+        let mut manifest = Manifest::new(&self.name, run_id);
+        manifest.commands = self
+            .stages
+            .iter()
+            .zip(resolved)
+            .map(|((name, _), resolved)| {
+                let command = match &resolved.action {
+                    Some(Action::Process(process)) => Some(process.to_shell_string()),
+                    Some(Action::Pipeline(pipeline)) => Some(pipeline.to_shell_string()),
+                    Some(Action::Closure(_)) | Some(Action::Experiment(_)) | None => None,
+                };
+                (name.clone(), command)
+            })
+            .collect();
+        manifest.parameters = self.parameters.clone();
+        if let Some(seeds) = self.seeds {
+            manifest.master_seed = Some(seeds.master());
+            manifest.seeds = self
+                .stages
+                .iter()
+                .map(|(name, _)| (name.clone(), seeds.seed(name)))
+                .collect();
+        }
+        for name in &self.recorded_env {
+            manifest.record_env(name);
+        }
+        for (name, process) in &self.version_probes {
+            manifest.probe_version(name, process)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Replaces the placeholders in the command of a process or pipeline stage, and in the
+    /// paths of its declared files.
+    fn interpolate(&self, stage: &str, action: &Action) -> io::Result<Resolved> {
+        let run_dir = self
+            .output_dir
+            .as_ref()
+            .and_then(|(dir, _)| dir.to_str())
+            .map(String::from);
+        let stage_dir = self
+            .output_dir
+            .as_ref()
+            .and_then(|(dir, _)| dir.join(stage).to_str().map(String::from));
+        let seed = self.seeds.map(|seeds| seeds.seed(stage).to_string());
+        let uses_stage_dir = Cell::new(false);
+        let lookup = |name: &str| match name {
+            "run_id" => Some(self.run_id.as_deref().unwrap_or(&self.name)),
+            "seed" if seed.is_some() => seed.as_deref(),
+            "run_dir" => run_dir.as_deref(),
+            "stage_dir" => {
+                uses_stage_dir.set(true);
+                stage_dir.as_deref()
+            }
+            _ => self.parameters.get(name).map(String::as_str),
+        };
+        let interpolated = match action {
+            Action::Process(process) => process
+                .interpolate(lookup)
+                .map(|process| Some(Action::Process(Box::new(process)))),
+            Action::Pipeline(pipeline) => pipeline
+                .interpolate(lookup)
+                .map(|pipeline| Some(Action::Pipeline(pipeline))),
+            Action::Closure(_) | Action::Experiment(_) => Ok(None),
+        };
+        let portable_stage_dir = format!("{{run_dir}}/{}", stage);
+        let portable = |name: &str| match name {
+            "run_dir" => Some("{run_dir}"),
+            "stage_dir" => Some(portable_stage_dir.as_str()),
+            _ => lookup(name),
+        };
+        let fingerprint = match action {
+            Action::Process(process) => process
+                .interpolate(portable)
+                .ok()
+                .map(|process| process.fingerprint()),
+            Action::Pipeline(pipeline) => pipeline
+                .interpolate(portable)
+                .ok()
+                .map(|pipeline| pipeline.fingerprint()),
+            Action::Closure(_) | Action::Experiment(_) => None,
+        };
+        let paths = |paths: &[PathBuf]| {
+            paths
+                .iter()
+                .map(|path| match path.to_str() {
+                    Some(text) => template::interpolate(text, lookup)
+                        .map(PathBuf::from)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err)),
+                    None => Ok(path.clone()),
+                })
+                .collect::<io::Result<Vec<_>>>()
+        };
+        let files = self.files.get(stage).cloned().unwrap_or_default();
+        let dependencies = self.dependencies.get(stage).map_or(&[][..], Vec::as_slice);
+        let resolve = || -> io::Result<Resolved> {
+            Ok(Resolved {
+                action: interpolated?,
+                fingerprint,
+                files: StageFiles {
+                    inputs: paths(&files.inputs)?,
+                    outputs: paths(&files.outputs)?,
+                    intermediate: paths(&files.intermediate)?,
+                },
+                uses_stage_dir: uses_stage_dir.get(),
+                dependencies: dependencies
+                    .iter()
+                    .map(|dependency| template::interpolate(dependency, lookup))
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+            })
+        };
+        resolve().map_err(|err| io::Error::new(err.kind(), format!("Stage `{}`: {}", stage, err)))
+    }
+
+    /// Runs the stage, restarting it if it is checkpointed, fails, and has saved progress.
+    fn run_checkpointed(
+        &self,
+        stage: &str,
+        action: &Action,
+        execution: &Execution,
+        context: &mut ExecutionContext,
+    ) -> io::Result<()> {
+        let (checkpoint, max_restarts) = match (self.checkpointed.get(stage), &self.output_dir) {
+            (Some(&max_restarts), Some((dir, _))) => {
+                let dir = dir.join(CHECKPOINT_DIR);
+                std::fs::create_dir_all(&dir)?;
+                (Checkpoint::new(dir.join(stage)), max_restarts)
+            }
+            _ => {
+                context.checkpoint_file(None::<&Path>);
+                return self.run_action(stage, action, execution, context);
+            }
+        };
+        context.checkpoint_file(Some(checkpoint.path()));
+        let mut restarts = 0;
+        loop {
+            match self.run_action(stage, action, execution, context) {
+                Ok(()) => return checkpoint.clear(),
+                Err(_)
+                    if restarts < max_restarts && checkpoint.exists() && !execution.expired() =>
+                {
+                    restarts += 1
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn run_action(
+        &self,
+        stage: &str,
+        action: &Action,
+        execution: &Execution,
+        context: &ExecutionContext,
+    ) -> io::Result<()> {
+        match action {
+            Action::Process(process) => {
+                let mut process = Process::clone(process);
+                process.context(context);
+                let label = if execution.labeled { Some(stage) } else { None };
+                let status = process.execute_within(label, execution.deadline)?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!(
+                        "{} failed: {}",
+                        process.display(self.verbosity),
+                        status
+                    )))
+                }
+            }
+            Action::Pipeline(pipeline) => {
+                let mut pipeline = pipeline.clone();
+                pipeline.context(context);
+                match execution.deadline {
+                    Some((deadline, grace)) => pipeline.pipefail_until(deadline, grace),
+                    None => pipeline.pipefail(),
+                }
+                .map(|_| ())
+            }
+            Action::Closure(closure) => {
+                let _entered = context.enter();
+                closure(context)
+            }
+            Action::Experiment(experiment) => {
+                let report = experiment.run()?;
+                let failures: Vec<_> = report
+                    .failures()
+                    .iter()
+                    .map(|stage| stage.name.as_str())
+                    .collect();
+                match (report.success(), failures.is_empty()) {
+                    (true, _) => Ok(()),
+                    (false, true) => Err(io::Error::other(format!(
+                        "Experiment `{}` did not complete",
+                        experiment.name
+                    ))),
+                    (false, false) => Err(io::Error::other(format!(
+                        "Experiment `{}` failed: {}",
+                        experiment.name,
+                        failures.join(", ")
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+impl Execution {
+    /// Checks if the time budget has run out, and records it if so.
+    fn expired(&self) -> bool {
+        let expired = self
+            .deadline
+            .is_some_and(|(deadline, _)| Instant::now() >= deadline);
+        if expired {
+            self.out_of_time.store(true, Ordering::SeqCst);
+        }
+        expired
+    }
+}
+
+impl Graph {
+    /// Checks if a stage must be skipped: if any of its dependencies has not succeeded, or,
+    /// for a teardown hook of a stage, if that stage has not run.
+    fn blocked(&self, idx: usize, reports: &[Option<StageReport>]) -> bool {
+        let outcome = |dep: usize| reports[dep].as_ref().map(|report| &report.outcome);
+        let failed = self.dependencies[idx].iter().any(|&dep| {
+            !matches!(
+                outcome(dep),
+                Some(StageOutcome::Succeeded) | Some(StageOutcome::UpToDate)
+            )
+        });
+        let orphaned = match self.teardown[idx] {
+            Some(Some(target)) => matches!(outcome(target), None | Some(StageOutcome::Skipped)),
+            _ => false,
+        };
+        failed || orphaned
+    }
+}
+
+/// The contents of the stamp of a stage: the fingerprint of its command, or a marker for
+/// closures.
+fn stamp(action: &Action) -> String {
+    match action {
+        Action::Process(process) => format!("{:016x}\n", process.fingerprint()),
+        Action::Pipeline(pipeline) => format!("{:016x}\n", pipeline.fingerprint()),
+        Action::Closure(_) => String::from("closure\n"),
+        Action::Experiment(_) => String::from("experiment\n"),
+    }
+}
+
+/// Fails if any of the outputs is missing or empty, and returns the sizes and checksums of
+/// the output files.
+pub(crate) fn verify_outputs(stage: &str, outputs: &[PathBuf]) -> io::Result<Vec<Artifact>> {
+    let mut artifacts = Vec::with_capacity(outputs.len());
+    for output in outputs {
+        let error = |err: io::Error| {
+            io::Error::new(err.kind(), format!("Output {}: {}", output.display(), err))
+        };
+        let metadata = std::fs::metadata(output).map_err(error)?;
+        if metadata.is_dir() {
+            if std::fs::read_dir(output).map_err(error)?.next().is_none() {
+                return Err(error(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Empty directory",
+                )));
+            }
+        } else if metadata.len() == 0 {
+            return Err(error(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Empty file",
+            )));
+        } else {
+            artifacts.push(Artifact {
+                stage: String::from(stage),
+                path: output.clone(),
+                size: metadata.len(),
+                checksum: Checksum::of_file(output).map_err(error)?,
+            });
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Escapes the characters with a special meaning in HTML.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+impl fmt::Debug for KeyFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("KeyFn")
+    }
+}
+
+impl fmt::Debug for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Process(process) => f.debug_tuple("Process").field(process).finish(),
+            Action::Pipeline(pipeline) => f.debug_tuple("Pipeline").field(pipeline).finish(),
+            Action::Closure(_) => f.write_str("Closure"),
+            Action::Experiment(experiment) => {
+                f.debug_tuple("Experiment").field(experiment).finish()
+            }
+        }
+    }
+}
+
+impl Hook {
+    /// Creates a hook calling a closure, which fails if the closure returns an error.
+    pub fn closure<F>(closure: F) -> Hook
+    where
+        F: Fn(&ExecutionContext) -> io::Result<()> + Send + Sync + 'static,
+    {
+        Hook {
+            action: Action::Closure(Box::new(closure)),
+        }
+    }
+}
+
+impl From<Process> for Hook {
+    fn from(process: Process) -> Hook {
+        Hook {
+            action: Action::Process(Box::new(process)),
+        }
+    }
+}
+
+impl From<ProcessPipeline> for Hook {
+    fn from(pipeline: ProcessPipeline) -> Hook {
+        Hook {
+            action: Action::Pipeline(pipeline),
+        }
+    }
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailurePolicy::Abort => write!(f, "abort"),
+            FailurePolicy::Continue => write!(f, "continue"),
+            FailurePolicy::Retry(retries) => write!(f, "retry({})", retries),
+            FailurePolicy::Run(stage) => write!(f, "run({})", stage),
+        }
+    }
+}
+
+impl FromStr for FailurePolicy {
+    type Err = String;
+
+    /// Parses `abort`, `continue`, `retry(<n>)`, or `run(<stage>)`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let argument = |name: &str| {
+            value
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('('))
+                .and_then(|rest| rest.strip_suffix(')'))
+                .map(str::trim)
+        };
+        match value {
+            "abort" => Ok(FailurePolicy::Abort),
+            "continue" => Ok(FailurePolicy::Continue),
+            _ => match (argument("retry"), argument("run")) {
+                (Some(retries), _) => retries
+                    .parse()
+                    .map(FailurePolicy::Retry)
+                    .map_err(|_| format!("Invalid number of retries: {}", retries)),
+                (_, Some(stage)) if !stage.is_empty() => {
+                    Ok(FailurePolicy::Run(String::from(stage)))
+                }
+                _ => Err(format!("Unknown failure policy: {}", value)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for CleanupPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CleanupPolicy::Keep => write!(f, "keep"),
+            CleanupPolicy::DeleteOnSuccess => write!(f, "delete-on-success"),
+            CleanupPolicy::CompressOnSuccess(codec) => write!(f, "compress-on-success:{}", codec),
+        }
+    }
+}
+
+impl FromStr for CleanupPolicy {
+    type Err = String;
+
+    /// Parses `keep`, `delete-on-success`, or `compress-on-success`, optionally followed by a
+    /// colon and a [codec](../compression/enum.Codec.html).
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "keep" => Ok(CleanupPolicy::Keep),
+            "delete-on-success" => Ok(CleanupPolicy::DeleteOnSuccess),
+            "compress-on-success" => Ok(CleanupPolicy::CompressOnSuccess(Codec::default())),
+            _ => match value.strip_prefix("compress-on-success:") {
+                Some(codec) => codec.parse().map(CleanupPolicy::CompressOnSuccess),
+                None => Err(format!("Unknown cleanup policy: {}", value)),
+            },
+        }
+    }
+}
+
+impl ExperimentReport {
+    /// Checks if all stages succeeded, either in this run or in an earlier one.
+    pub fn success(&self) -> bool {
+        self.stages.iter().all(|stage| {
+            stage.outcome == StageOutcome::Succeeded || stage.outcome == StageOutcome::UpToDate
+        })
+    }
+
+    /// Lists the reports of the stages that failed.
+    pub fn failures(&self) -> Vec<&StageReport> {
+        self.stages
+            .iter()
+            .filter(|stage| matches!(stage.outcome, StageOutcome::Failed(_)))
+            .collect()
+    }
+
+    /// Finds the report of the stage with the given name.
+    pub fn stage(&self, name: &str) -> Option<&StageReport> {
+        self.stages.iter().find(|stage| stage.name == name)
+    }
+
+    /// The total time spent running the stages.
+    pub fn duration(&self) -> Duration {
+        self.stages.iter().map(|stage| stage.duration).sum()
+    }
+
+    /// Renders the report as a Markdown table with a row per stage, followed by a summary
+    /// line like the last line of its `Display` output.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{ExperimentReport, StageOutcome, StageReport};
+    /// # use std::time::Duration;
+    /// let report = ExperimentReport {
+    ///     stages: vec![
+    ///         StageReport {
+    ///             name: String::from("index"),
+    ///             outcome: StageOutcome::Succeeded,
+    ///             duration: Duration::from_millis(1500),
+    ///         },
+    ///         StageReport {
+    ///             name: String::from("search"),
+    ///             outcome: StageOutcome::Failed(String::from("search | sort failed")),
+    ///             duration: Duration::from_millis(250),
+    ///         },
+    ///     ],
+    ///     partial: false,
+    /// };
+    /// assert_eq!(
+    ///     report.to_markdown(),
+    ///     "| Stage | Outcome | Duration |\n\
+    ///      | --- | --- | --- |\n\
+    ///      | index | succeeded | 1.5s |\n\
+    ///      | search | failed: search \\| sort failed | 250.0ms |\n\
+    ///      \n\
+    ///      1 of 2 stages succeeded in 1.8s\n"
+    /// );
+    /// ```
+    pub fn to_markdown(&self) -> String {
+        let escape = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+        let mut markdown = String::from("| Stage | Outcome | Duration |\n| --- | --- | --- |\n");
+        for stage in &self.stages {
+            markdown.push_str(&format!(
+                "| {} | {} | {:.1?} |\n",
+                escape(&stage.name),
+                escape(&stage.outcome.to_string()),
+                stage.duration
+            ));
+        }
+        markdown.push_str(&format!("\n{}\n", self.summary()));
+        markdown
+    }
+
+    /// Renders the report as an HTML table with a row per stage, with the outcome of each
+    /// stage in the `class` attribute of its row, followed by a summary paragraph.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::{ExperimentReport, StageOutcome, StageReport};
+    /// # use std::time::Duration;
+    /// let report = ExperimentReport {
+    ///     stages: vec![StageReport {
+    ///         name: String::from("search"),
+    ///         outcome: StageOutcome::Failed(String::from("exit status: 1 <stderr>")),
+    ///         duration: Duration::from_secs(2),
+    ///     }],
+    ///     partial: true,
+    /// };
+    /// let html = report.to_html();
+    /// assert!(html.starts_with(
+    ///     "<table>\n<tr><th>Stage</th><th>Outcome</th><th>Duration</th></tr>\n"
+    /// ));
+    /// assert!(html.contains(
+    ///     "<tr class=\"failed\"><td>search</td>\
+    ///      <td>failed: exit status: 1 &lt;stderr&gt;</td><td>2.0s</td></tr>"
+    /// ));
+    /// assert!(html.ends_with("<p>0 of 1 stages succeeded in 2.0s (out of time)</p>\n"));
+    /// ```
+    pub fn to_html(&self) -> String {
+        let mut html =
+            String::from("<table>\n<tr><th>Stage</th><th>Outcome</th><th>Duration</th></tr>\n");
+        for stage in &self.stages {
+            let class = match stage.outcome {
+                StageOutcome::Succeeded => "succeeded",
+                StageOutcome::Failed(_) => "failed",
+                StageOutcome::Skipped => "skipped",
+                StageOutcome::UpToDate => "up-to-date",
+            };
+            html.push_str(&format!(
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{:.1?}</td></tr>\n",
+                class,
+                escape_html(&stage.name),
+                escape_html(&stage.outcome.to_string()),
+                stage.duration
+            ));
+        }
+        html.push_str(&format!(
+            "</table>\n<p>{}</p>\n",
+            escape_html(&self.summary())
+        ));
+        html
+    }
+
+    /// How many stages succeeded, and how long they took.
+    fn summary(&self) -> String {
+        let succeeded = self
+            .stages
+            .iter()
+            .filter(|stage| {
+                matches!(
+                    stage.outcome,
+                    StageOutcome::Succeeded | StageOutcome::UpToDate
+                )
+            })
+            .count();
+        format!(
+            "{} of {} stages succeeded in {:.1?}{}",
+            succeeded,
+            self.stages.len(),
+            self.duration(),
+            if self.partial { " (out of time)" } else { "" }
+        )
+    }
+}
+
+impl fmt::Display for StageOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StageOutcome::Succeeded => write!(f, "succeeded"),
+            StageOutcome::Failed(err) => write!(f, "failed: {}", err),
+            StageOutcome::Skipped => write!(f, "skipped"),
+            StageOutcome::UpToDate => write!(f, "up to date"),
+        }
+    }
+}
+
+/// Shows a line per stage with its outcome and duration, and a summary line.
+///
+/// # Examples
+/// ```
+/// # use experiment::experiment::{Experiment, StageOutcome};
+/// # use experiment::process::Process;
+/// let mut experiment = Experiment::new("bm25");
+/// experiment
+///     .process("index", Process::new("true", &[] as &[&str]))
+///     .process("search", Process::new("false", &[] as &[&str]))
+///     .process("evaluate", Process::new("true", &[] as &[&str]));
+/// let report = experiment.run().unwrap().to_string();
+/// let lines: Vec<_> = report.lines().collect();
+/// assert!(lines[0].starts_with("[index] succeeded ("));
+/// assert!(lines[1].starts_with("[search] failed: false failed: exit status: 1 ("));
+/// assert_eq!(lines[2], "[evaluate] skipped (0.0ns)");
+/// assert!(lines[3].starts_with("1 of 3 stages succeeded in "));
+/// ```
+impl fmt::Display for ExperimentReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "[{}] {} ({:.1?})",
+                stage.name, stage.outcome, stage.duration
+            )?;
+        }
+        write!(f, "{}", self.summary())
+    }
+}
+
+impl<'a> fmt::Display for ExperimentDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verbosity = self.experiment.verbosity;
+        write!(f, "{}", self.experiment.name)?;
+        for (name, action) in &self.experiment.stages {
+            write!(f, "\n[{}] ", name)?;
+            write_action(f, action, verbosity)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ExperimentPlan<'a> {
+    /// Exports the plan as a standalone [`ShellScript`](../script/struct.ShellScript.html)
+    /// running the stages one after another in the order of the plan, each preceded by a
+    /// comment with its name. The output directory, and the stage directories the commands
+    /// refer to, are created first. Closures cannot be exported, so they are left as
+    /// comments.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::experiment::Experiment;
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// let mut experiment = Experiment::new("bm25");
+    /// experiment
+    ///     .output_dir("results", OverwritePolicy::Fail)
+    ///     .process("search", Process::new("search", &["-i", "{run_dir}/index", "-o", "{stage_dir}"]))
+    ///     .depends_on("search", &["index"])
+    ///     .closure("check", |_| Ok(()))
+    ///     .process("index", Process::new("build_index", &["-o", "{run_dir}/index"]));
+    /// let script = experiment.plan().unwrap().to_script().to_string();
+    /// let lines: Vec<_> = script.lines().collect();
+    /// assert_eq!(
+    ///     lines,
+    ///     vec![
+    ///         "#!/usr/bin/env bash",
+    ///         "set -euo pipefail",
+    ///         "",
+    ///         "# bm25",
+    ///         "mkdir -p results",
+    ///         "",
+    ///         "# check: a closure, which cannot be exported",
+    ///         "",
+    ///         "# index",
+    ///         "build_index -o results/index",
+    ///         "",
+    ///         "# search",
+    ///         "mkdir -p results/search",
+    ///         "search -i results/index -o results/search",
+    ///     ]
+    /// );
+    /// ```
+    pub fn to_script(&self) -> ShellScript {
+        let experiment = self.experiment;
+        let mut script = ShellScript::new();
+        script.comment(&experiment.name);
+        let mkdir = |dir: &Path| Process::new("mkdir", [Path::new("-p"), dir]);
+        if let Some((dir, _)) = &experiment.output_dir {
+            script.command(&mkdir(dir));
+        }
+        for &idx in &self.graph.order {
+            let (name, action) = &experiment.stages[idx];
+            let resolved = &self.resolved[idx];
+            script.blank_line();
+            let command: &dyn ShellCommand = match resolved.action.as_ref().unwrap_or(action) {
+                Action::Process(process) => &**process,
+                Action::Pipeline(pipeline) => pipeline,
+                Action::Closure(_) => {
+                    script.comment(&format!("{}: a closure, which cannot be exported", name));
+                    continue;
+                }
+                Action::Experiment(nested) => {
+                    script.comment(&format!(
+                        "{}: the experiment `{}`, which is exported with its own plan",
+                        name, nested.name
+                    ));
+                    continue;
+                }
+            };
+            script.comment(name);
+            if let (Some((dir, _)), true) = (&experiment.output_dir, resolved.uses_stage_dir) {
+                script.command(&mkdir(&dir.join(name)));
+            }
+            script.command(command);
+        }
+        script
+    }
+}
+
+impl<'a> fmt::Display for ExperimentPlan<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let experiment = self.experiment;
+        write!(f, "{}", experiment.name)?;
+        if let Some((dir, _)) = &experiment.output_dir {
+            write!(f, " -> {}", dir.display())?;
+        }
+        for &idx in &self.graph.order {
+            let (name, action) = &experiment.stages[idx];
+            write!(f, "\n[{}] ", name)?;
+            let action = self.resolved[idx].action.as_ref().unwrap_or(action);
+            write_action(f, action, experiment.verbosity)?;
+            let dependencies = &self.graph.dependencies[idx];
+            if !dependencies.is_empty() {
+                let names: Vec<_> = dependencies
+                    .iter()
+                    .map(|&dep| experiment.stages[dep].0.as_str())
+                    .filter(|name| !experiment.hooks.contains_key(*name))
+                    .collect();
+                if !names.is_empty() {
+                    write!(f, " (after {})", names.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_action(f: &mut fmt::Formatter, action: &Action, verbosity: Verbosity) -> fmt::Result {
+    match action {
+        Action::Process(process) => write!(f, "{}", process.display(verbosity)),
+        Action::Pipeline(pipeline) => write!(f, "{}", pipeline.display(verbosity)),
+        Action::Closure(_) => write!(f, "<closure>"),
+        Action::Experiment(experiment) => write!(f, "<experiment {}>", experiment.name),
+    }
+}
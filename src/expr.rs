@@ -0,0 +1,267 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Arithmetic expressions over parameter values.
+
+use super::sweep::Value;
+use super::template::interpolate;
+use std::cell::RefCell;
+
+/// A parsed expression: integer and float literals, string literals with `{name}`
+/// placeholders, names of parameters, parentheses, unary `-`, and the binary `+`, `-`, `*`,
+/// `/`, and `%` with the usual precedence.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Expr {
+    Literal(Value),
+    Name(String),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+impl Expr {
+    /// Parses the expression.
+    pub(crate) fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = Parser {
+            text,
+            chars: text.char_indices().peekable(),
+        };
+        let expr = parser.sum()?;
+        parser.skip_whitespace();
+        match parser.chars.peek() {
+            None => Ok(expr),
+            Some(&(_, c)) => Err(parser.unexpected(c)),
+        }
+    }
+
+    /// The names the expression refers to, including those in placeholders of string
+    /// literals, in the order of appearance.
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_names(&mut names);
+        names
+    }
+
+    fn collect_names(&self, names: &mut Vec<String>) {
+        match self {
+            Expr::Literal(Value::String(text)) => {
+                let found = RefCell::new(Vec::new());
+                let _ = interpolate(text, |name| {
+                    found.borrow_mut().push(String::from(name));
+                    Some("")
+                });
+                names.extend(found.into_inner());
+            }
+            Expr::Literal(_) => {}
+            Expr::Name(name) => names.push(name.clone()),
+            Expr::Negate(expr) => expr.collect_names(names),
+            Expr::Binary(left, _, right) => {
+                left.collect_names(names);
+                right.collect_names(names);
+            }
+        }
+    }
+
+    /// Evaluates the expression with the values returned by `lookup`. Arithmetic on two
+    /// integers yields an integer, with division rounding toward zero; strings taking part in
+    /// arithmetic are parsed as numbers.
+    pub(crate) fn evaluate<F>(&self, lookup: &F) -> Result<Value, String>
+    where
+        F: Fn(&str) -> Option<Value>,
+    {
+        match self {
+            Expr::Literal(Value::String(text)) => {
+                let mut names = Vec::new();
+                self.collect_names(&mut names);
+                let values = names
+                    .into_iter()
+                    .filter_map(|name| lookup(&name).map(|value| (name, value.to_string())))
+                    .collect::<Vec<_>>();
+                let text = interpolate(text, |name| {
+                    values
+                        .iter()
+                        .find(|(key, _)| key == name)
+                        .map(|(_, value)| value.as_str())
+                })?;
+                Ok(Value::String(text))
+            }
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Name(name) => lookup(name).ok_or_else(|| format!("Unknown name `{}`", name)),
+            Expr::Negate(expr) => match number(expr.evaluate(lookup)?)? {
+                Value::Integer(value) => Ok(Value::Integer(-value)),
+                Value::Float(value) => Ok(Value::Float(-value)),
+                _ => unreachable!("Numbers are integers or floats"),
+            },
+            Expr::Binary(left, op, right) => {
+                let left = number(left.evaluate(lookup)?)?;
+                let right = number(right.evaluate(lookup)?)?;
+                arithmetic(left, *op, right)
+            }
+        }
+    }
+}
+
+/// Converts strings to numbers, and fails for booleans.
+fn number(value: Value) -> Result<Value, String> {
+    match value {
+        Value::String(text) => text
+            .parse()
+            .map(Value::Integer)
+            .or_else(|_| text.parse().map(Value::Float))
+            .map_err(|_| format!("`{}` is not a number", text)),
+        Value::Boolean(value) => Err(format!("`{}` is not a number", value)),
+        value => Ok(value),
+    }
+}
+
+fn arithmetic(left: Value, op: char, right: Value) -> Result<Value, String> {
+    let float = |value: &Value| match value {
+        Value::Integer(value) => *value as f64,
+        Value::Float(value) => *value,
+        _ => unreachable!("Numbers are integers or floats"),
+    };
+    if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+        let result = match op {
+            '+' => a.checked_add(*b),
+            '-' => a.checked_sub(*b),
+            '*' => a.checked_mul(*b),
+            '/' if *b == 0 => return Err(String::from("Division by zero")),
+            '/' => a.checked_div(*b),
+            '%' if *b == 0 => return Err(String::from("Division by zero")),
+            _ => a.checked_rem(*b),
+        };
+        return result
+            .map(Value::Integer)
+            .ok_or_else(|| format!("Overflow in `{} {} {}`", a, op, b));
+    }
+    let (a, b) = (float(&left), float(&right));
+    Ok(Value::Float(match op {
+        '+' => a + b,
+        '-' => a - b,
+        '*' => a * b,
+        '/' => a / b,
+        _ => a % b,
+    }))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.operator(&['+', '-']) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(op) = self.operator(&['*', '/', '%']) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.operator(&['-']).is_some() {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        let (start, c) = match self.chars.next() {
+            Some(next) => next,
+            None => return Err(format!("Unexpected end of `{}`", self.text)),
+        };
+        match c {
+            '(' => {
+                let expr = self.sum()?;
+                match self.operator(&[')']) {
+                    Some(_) => Ok(expr),
+                    None => Err(format!("Unclosed `(` in `{}`", self.text)),
+                }
+            }
+            '"' => {
+                let mut literal = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some((_, '"')) => return Ok(Expr::Literal(Value::String(literal))),
+                        Some((_, c)) => literal.push(c),
+                        None => return Err(format!("Unclosed `\"` in `{}`", self.text)),
+                    }
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let end = self.take_while(|c| c.is_ascii_alphanumeric() || c == '.');
+                let literal = &self.text[start..end];
+                literal
+                    .parse()
+                    .map(Value::Integer)
+                    .or_else(|_| literal.parse().map(Value::Float))
+                    .map(Expr::Literal)
+                    .map_err(|_| format!("Invalid number `{}` in `{}`", literal, self.text))
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let end = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '.');
+                Ok(Expr::Name(String::from(&self.text[start..end])))
+            }
+            c => Err(self.unexpected(c)),
+        }
+    }
+
+    /// Consumes the next non-whitespace character if it is one of `ops`.
+    fn operator(&mut self, ops: &[char]) -> Option<char> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some(&(_, c)) if ops.contains(&c) => {
+                self.chars.next();
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    /// Consumes characters satisfying the predicate, and returns the end of the consumed
+    /// text.
+    fn take_while<P: Fn(char) -> bool>(&mut self, predicate: P) -> usize {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !predicate(c) {
+                break;
+            }
+            self.chars.next();
+        }
+        self.chars.peek().map_or(self.text.len(), |&(idx, _)| idx)
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn unexpected(&self, c: char) -> String {
+        format!("Unexpected `{}` in `{}`", c, self.text)
+    }
+}
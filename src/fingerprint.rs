@@ -0,0 +1,192 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// A hasher producing values that are stable across runs, platforms, and compiler versions.
+///
+/// Unlike [`DefaultHasher`](https://doc.rust-lang.org/std/collections/hash_map/struct.DefaultHasher.html),
+/// the output of this hasher (64-bit FNV-1a) is fully specified, which makes it suitable
+/// for cache keys that are persisted on disk.
+///
+/// # Examples
+/// ```
+/// # use experiment::fingerprint::StableHasher;
+/// # use std::hash::Hasher;
+/// let mut hasher = StableHasher::new();
+/// hasher.write_str("abc");
+/// let mut other = StableHasher::new();
+/// other.write_str("abc");
+/// assert_eq!(hasher.finish(), other.finish());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    /// Creates a new hasher.
+    pub fn new() -> StableHasher {
+        StableHasher {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+
+    /// Writes a string prefixed with its length, so that consecutive strings cannot be
+    /// confused with one another (e.g., `"ab", "c"` and `"a", "bc"`).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::fingerprint::StableHasher;
+    /// # use std::hash::Hasher;
+    /// let mut first = StableHasher::new();
+    /// first.write_str("ab");
+    /// first.write_str("c");
+    /// let mut second = StableHasher::new();
+    /// second.write_str("a");
+    /// second.write_str("bc");
+    /// assert_ne!(first.finish(), second.finish());
+    /// ```
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write(value.as_bytes());
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= u64::from(*byte);
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+}
+
+/// A cache key combining a fingerprint with selected properties of the environment, such as
+/// environment variables and CPU features, that affect the outcome of a computation.
+///
+/// # Examples
+/// ```
+/// # use experiment::fingerprint::CacheKey;
+/// # use experiment::process::Process;
+/// let process = Process::new("build_index", &["--simd"]);
+/// let key = CacheKey::new(process.fingerprint())
+///     .env_var("RUSTFLAGS")
+///     .cpu_feature("avx512f")
+///     .finish();
+/// assert_ne!(key, process.fingerprint());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CacheKey {
+    hasher: StableHasher,
+}
+
+impl CacheKey {
+    /// Creates a key based on the given fingerprint.
+    pub fn new(fingerprint: u64) -> CacheKey {
+        let mut hasher = StableHasher::new();
+        hasher.write_u64(fingerprint);
+        CacheKey { hasher }
+    }
+
+    /// Includes the value of an environment variable (or its absence) in the key.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::fingerprint::CacheKey;
+    /// let without = CacheKey::new(0).env_var("EXPERIMENT_CACHE_KEY_DOC").finish();
+    /// std::env::set_var("EXPERIMENT_CACHE_KEY_DOC", "1");
+    /// let with = CacheKey::new(0).env_var("EXPERIMENT_CACHE_KEY_DOC").finish();
+    /// assert_ne!(without, with);
+    /// ```
+    pub fn env_var(&mut self, name: &str) -> &mut CacheKey {
+        self.hasher.write_str(name);
+        match std::env::var_os(name) {
+            Some(value) => {
+                self.hasher.write_u8(1);
+                self.hasher.write_str(&value.to_string_lossy());
+            }
+            None => self.hasher.write_u8(0),
+        }
+        self
+    }
+
+    /// Includes the availability of a CPU feature, as named by the `flags` field of
+    /// `/proc/cpuinfo` (e.g., `avx2` or `avx512f`), in the key.
+    pub fn cpu_feature(&mut self, feature: &str) -> &mut CacheKey {
+        self.hasher.write_str(feature);
+        self.hasher.write_u8(u8::from(cpu_has_feature(feature)));
+        self
+    }
+
+    /// Includes an arbitrary string, such as the hostname or a tool version, in the key.
+    pub fn value(&mut self, value: &str) -> &mut CacheKey {
+        self.hasher.write_str(value);
+        self
+    }
+
+    /// Returns the key.
+    pub fn finish(&self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+/// Checks if the CPU supports a feature, as named by the `flags` field of `/proc/cpuinfo`.
+/// Always returns `false` where that file is not available.
+///
+/// # Examples
+/// ```
+/// # use experiment::fingerprint::cpu_has_feature;
+/// assert!(!cpu_has_feature("no-such-feature"));
+/// ```
+pub fn cpu_has_feature(feature: &str) -> bool {
+    std::fs::read_to_string("/proc/cpuinfo")
+        .map(|cpuinfo| {
+            cpuinfo
+                .lines()
+                .filter(|line| line.starts_with("flags"))
+                .take(1)
+                .any(|line| line.split_whitespace().any(|flag| flag == feature))
+        })
+        .unwrap_or(false)
+}
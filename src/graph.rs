@@ -0,0 +1,310 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Processes connected in directed acyclic graphs.
+
+use super::process::{spawn_all, Process};
+use super::Verbosity;
+use os_pipe::{pipe, PipeWriter};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, ExitStatus};
+use std::sync::{Arc, Mutex};
+
+/// An identifier of a process in a [`ProcessGraph`](struct.ProcessGraph.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// The position of the process in the order of insertion.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// A generalization of a [`ProcessPipeline`](../process/struct.ProcessPipeline.html) to a
+/// directed acyclic graph of processes.
+///
+/// The standard output of a process that is connected to multiple consumers is copied to each
+/// of them (fan-out), and a process connected to multiple producers reads their outputs merged
+/// line by line, in the order in which the lines arrive (fan-in). A process with no producers
+/// inherits the standard input, and one with no consumers the standard output.
+///
+/// # Examples
+/// ```
+/// # use experiment::graph::ProcessGraph;
+/// # use experiment::process::Process;
+/// let mut graph = ProcessGraph::new();
+/// let source = graph.add(Process::new("echo", &["-e", "a\\nb\\nc"]));
+/// let first = graph.add(Process::new("grep", &["a"]));
+/// let second = graph.add(Process::new("grep", &["c"]));
+/// let merge = graph.add(Process::new("sort", &Vec::<&str>::new()));
+/// graph
+///     .connect(source, first)
+///     .connect(source, second)
+///     .connect(first, merge)
+///     .connect(second, merge);
+/// let statuses = graph.execute().unwrap();
+/// assert!(statuses.iter().all(|status| status.success()));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ProcessGraph {
+    processes: Vec<Process>,
+    edges: Vec<(usize, usize)>,
+}
+
+/// A [`ProcessGraph`](struct.ProcessGraph.html) wrapper implementing `fmt::Display` trait.
+///
+/// This object is created with
+/// [`ProcessGraph::display`](struct.ProcessGraph.html#method.display) method.
+pub struct GraphDisplay<'a> {
+    graph: &'a ProcessGraph,
+    verbosity: Verbosity,
+}
+
+impl ProcessGraph {
+    /// Creates an empty graph.
+    pub fn new() -> ProcessGraph {
+        ProcessGraph::default()
+    }
+
+    /// Adds a process to the graph and returns its identifier.
+    pub fn add(&mut self, process: Process) -> NodeId {
+        self.processes.push(process);
+        NodeId(self.processes.len() - 1)
+    }
+
+    /// Connects the standard output of `from` to the standard input of `to`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the identifiers does not belong to the graph.
+    pub fn connect(&mut self, from: NodeId, to: NodeId) -> &mut ProcessGraph {
+        assert!(
+            from.0 < self.processes.len() && to.0 < self.processes.len(),
+            "Node does not belong to the graph"
+        );
+        if !self.edges.contains(&(from.0, to.0)) {
+            self.edges.push((from.0, to.0));
+        }
+        self
+    }
+
+    /// The process with the given identifier.
+    pub fn process(&self, node: NodeId) -> &Process {
+        &self.processes[node.0]
+    }
+
+    /// Creates a [`GraphDisplay`](struct.GraphDisplay.html) object with the desired verbosity.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::graph::ProcessGraph;
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut graph = ProcessGraph::new();
+    /// let source = graph.add(Process::new("zcat", &["corpus.gz"]));
+    /// let titles = graph.add(Process::new("extract", &["title"]));
+    /// let bodies = graph.add(Process::new("extract", &["body"]));
+    /// graph.connect(source, titles).connect(source, bodies);
+    /// assert_eq!(
+    ///     graph.display(Verbose).to_string(),
+    ///     "[0] zcat corpus.gz -> 1, 2\n[1] extract title\n[2] extract body"
+    /// );
+    /// ```
+    pub fn display(&self, verbosity: Verbosity) -> GraphDisplay<'_> {
+        GraphDisplay {
+            graph: self,
+            verbosity,
+        }
+    }
+
+    /// Orders the processes so that every one comes after all of its producers.
+    /// Fails if the graph contains a cycle.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::graph::ProcessGraph;
+    /// # use experiment::process::Process;
+    /// let mut graph = ProcessGraph::new();
+    /// let a = graph.add(Process::new("a", &Vec::<&str>::new()));
+    /// let b = graph.add(Process::new("b", &Vec::<&str>::new()));
+    /// graph.connect(b, a);
+    /// assert_eq!(graph.topological_order().unwrap(), vec![b, a]);
+    /// graph.connect(a, b);
+    /// assert!(graph.topological_order().is_err());
+    /// ```
+    pub fn topological_order(&self) -> io::Result<Vec<NodeId>> {
+        let mut in_degree = vec![0; self.processes.len()];
+        for &(_, to) in &self.edges {
+            in_degree[to] += 1;
+        }
+        let mut ready: Vec<_> = (0..self.processes.len())
+            .rev()
+            .filter(|&node| in_degree[node] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.processes.len());
+        while let Some(node) = ready.pop() {
+            order.push(NodeId(node));
+            for to in self.consumers(node) {
+                in_degree[to] -= 1;
+                if in_degree[to] == 0 {
+                    ready.push(to);
+                }
+            }
+        }
+        if order.len() < self.processes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Process graph contains a cycle",
+            ));
+        }
+        Ok(order)
+    }
+
+    /// Executes all processes in topological order, and returns their exit statuses in the
+    /// order in which they were added. Fails if the graph contains a cycle or any of the
+    /// processes fails to spawn, in which case the ones that have already started are killed.
+    pub fn execute(&self) -> io::Result<Vec<ExitStatus>> {
+        let order = self.topological_order()?;
+        let mut cmds: Vec<Option<Command>> = self
+            .processes
+            .iter()
+            .map(|p| p.try_command().map(Some))
+            .collect::<io::Result<_>>()?;
+        let mut inputs: Vec<Option<Arc<Mutex<PipeWriter>>>> = vec![None; cmds.len()];
+        for node in 0..cmds.len() {
+            if self.producers(node).next().is_some() {
+                let (reader, writer) = pipe()?;
+                if let Some(cmd) = &mut cmds[node] {
+                    cmd.stdin(reader);
+                }
+                inputs[node] = Some(Arc::new(Mutex::new(writer)));
+            }
+        }
+        let mut relays = Vec::new();
+        for (node, process) in self.processes.iter().enumerate() {
+            let outputs: Vec<_> = self
+                .consumers(node)
+                .filter_map(|to| inputs[to].clone())
+                .collect();
+            if outputs.is_empty() {
+                continue;
+            }
+            let (reader, writer) = pipe()?;
+            if let Some(cmd) = &mut cmds[node] {
+                process.connect_stdout(cmd, writer)?;
+            }
+            relays.push((reader, outputs));
+        }
+        drop(inputs);
+        let ordered = order
+            .iter()
+            .filter_map(|node| {
+                let description = format!(
+                    "Process {} ({})",
+                    node.0,
+                    self.processes[node.0].display(Verbosity::Brief(3))
+                );
+                cmds[node.0].take().map(|cmd| (description, cmd))
+            })
+            .collect();
+        let mut child = spawn_all(ordered)?;
+        let relays: Vec<_> = relays
+            .into_iter()
+            .map(|(reader, outputs)| std::thread::spawn(move || relay(reader, outputs)))
+            .collect();
+        let statuses = child.wait_all()?;
+        for relay in relays {
+            relay
+                .join()
+                .map_err(|_| io::Error::other("Relay thread panicked"))??;
+        }
+        let mut ordered = vec![None; statuses.len()];
+        for (node, status) in order.into_iter().zip(statuses) {
+            ordered[node.0] = Some(status);
+        }
+        Ok(ordered.into_iter().flatten().collect())
+    }
+
+    fn producers(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .filter(move |&&(_, to)| to == node)
+            .map(|&(from, _)| from)
+    }
+
+    fn consumers(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .filter(move |&&(from, _)| from == node)
+            .map(|&(_, to)| to)
+    }
+}
+
+/// Copies the input to all outputs line by line, so that lines from different producers
+/// sharing an output are never mixed. Outputs whose readers have exited are dropped.
+fn relay<R: io::Read>(reader: R, mut outputs: Vec<Arc<Mutex<PipeWriter>>>) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    while !outputs.is_empty() {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let mut idx = 0;
+        while idx < outputs.len() {
+            let result = outputs[idx]
+                .lock()
+                .map_err(|_| io::Error::other("Poisoned lock"))?
+                .write_all(&line);
+            match result {
+                Ok(()) => idx += 1,
+                Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                    outputs.swap_remove(idx);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Display for GraphDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (node, process) in self.graph.processes.iter().enumerate() {
+            if node > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "[{}] {}", node, process.display(self.verbosity))?;
+            let consumers: Vec<_> = self
+                .graph
+                .consumers(node)
+                .map(|to| to.to_string())
+                .collect();
+            if !consumers.is_empty() {
+                write!(f, " -> {}", consumers.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,476 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Reading and writing JSON documents.
+//!
+//! The reader accepts standard JSON (RFC 8259) and builds a [`Json`](enum.Json.html) value
+//! tree; there is no mapping onto user types. The writer indents with two spaces.
+//!
+//! # Examples
+//! ```
+//! # use experiment::json::Json;
+//! let json: Json = r#"{"name": "bm25", "scores": [0.25, 1e-3], "k": 10}"#.parse().unwrap();
+//! assert_eq!(json.get("name").and_then(Json::as_str), Some("bm25"));
+//! assert_eq!(json.get("k").and_then(Json::as_u64), Some(10));
+//! let scores = json.get("scores").and_then(Json::as_array).unwrap();
+//! assert_eq!(scores[1].as_f64(), Some(0.001));
+//! assert!(r#"{"name": }"#.parse::<Json>().is_err());
+//! ```
+
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// The maximum nesting of arrays and objects accepted by the reader.
+const MAX_DEPTH: usize = 512;
+
+/// A JSON value; objects keep the order of their members.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    /// `null`.
+    Null,
+    /// `true` or `false`.
+    Bool(bool),
+    /// A number without a fraction or an exponent that fits in `u64`.
+    Integer(u64),
+    /// Any other number.
+    Float(f64),
+    /// A string.
+    String(String),
+    /// An array.
+    Array(Vec<Json>),
+    /// An object; members are kept in the order they were given, duplicates included.
+    Object(Vec<(String, Json)>),
+}
+
+/// An error in a JSON document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    /// The offset of the error in the document, counted in characters.
+    pub offset: usize,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at character {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl Json {
+    /// The value of the first member of an object with the given key, or `None` if there is
+    /// no such member or this is not an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The string, if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The boolean, if this is a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The number, if this is an integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The number, if this is a number of either kind.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Integer(value) => Some(*value as f64),
+            Json::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The elements, if this is an array.
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Creates an object from its members.
+    pub fn object<I, K>(members: I) -> Json
+    where
+        I: IntoIterator<Item = (K, Json)>,
+        K: Into<String>,
+    {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+
+    /// A string, or `null` if missing.
+    #[cfg(feature = "experiment")]
+    pub(crate) fn optional<S: Into<String>>(value: Option<S>) -> Json {
+        value.map_or(Json::Null, |value| Json::String(value.into()))
+    }
+
+    /// Writes the value indented with two spaces per level, as the
+    /// [`Display`](#impl-Display) implementation does.
+    fn write(&self, out: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let pad = |out: &mut fmt::Formatter, level: usize| write!(out, "{:1$}", "", 2 * level);
+        match self {
+            Json::Null => out.write_str("null"),
+            Json::Bool(value) => write!(out, "{}", value),
+            Json::Integer(value) => write!(out, "{}", value),
+            Json::Float(value) if value.is_finite() => write!(out, "{}", value),
+            Json::Float(_) => out.write_str("null"),
+            Json::String(value) => write_string(out, value),
+            Json::Array(values) if values.is_empty() => out.write_str("[]"),
+            Json::Array(values) => {
+                out.write_str("[\n")?;
+                for (idx, value) in values.iter().enumerate() {
+                    pad(out, indent + 1)?;
+                    value.write(out, indent + 1)?;
+                    out.write_str(if idx + 1 < values.len() { ",\n" } else { "\n" })?;
+                }
+                pad(out, indent)?;
+                out.write_char(']')
+            }
+            Json::Object(members) if members.is_empty() => out.write_str("{}"),
+            Json::Object(members) => {
+                out.write_str("{\n")?;
+                for (idx, (key, value)) in members.iter().enumerate() {
+                    pad(out, indent + 1)?;
+                    write_string(out, key)?;
+                    out.write_str(": ")?;
+                    value.write(out, indent + 1)?;
+                    out.write_str(if idx + 1 < members.len() { ",\n" } else { "\n" })?;
+                }
+                pad(out, indent)?;
+                out.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(out: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::String(String::from(value))
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::String(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl FromStr for Json {
+    type Err = JsonError;
+
+    /// Parses a JSON document, which may be surrounded by whitespace.
+    fn from_str(text: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            offset: 0,
+        };
+        parser.skip_whitespace();
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(parser.error("Unexpected trailing characters")),
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    offset: usize,
+}
+
+impl Parser {
+    fn value(&mut self, depth: usize) -> Result<Json, JsonError> {
+        match self.peek() {
+            Some('{') | Some('[') if depth >= MAX_DEPTH => Err(self.error("Nested too deeply")),
+            Some('{') => self.object(depth + 1),
+            Some('[') => self.array(depth + 1),
+            Some('"') => self.string().map(Json::String),
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("Expected a value")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Json, JsonError> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            members.push((key, self.value(depth)?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Json::Object(members)),
+                _ => return Err(self.error_before("Expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Json, JsonError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(Json::Array(values));
+        }
+        loop {
+            self.skip_whitespace();
+            values.push(self.value(depth)?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Json::Array(values)),
+                _ => return Err(self.error_before("Expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("Unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error_before("Invalid escape sequence")),
+                    };
+                    value.push(escaped);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error_before("Control character in string"))
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// Reads the digits of a `\u` escape, combining a surrogate pair into one character.
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if self.next() != Some('\\') || self.next() != Some('u') {
+                    return Err(self.error_before("Unpaired surrogate in unicode escape"));
+                }
+                match self.hex()? {
+                    low @ 0xDC00..=0xDFFF => 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00),
+                    _ => return Err(self.error_before("Unpaired surrogate in unicode escape")),
+                }
+            }
+            code => code,
+        };
+        char::from_u32(code).ok_or_else(|| self.error_before("Invalid unicode escape"))
+    }
+
+    fn hex(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.next().and_then(|c| c.to_digit(16));
+            code = 16 * code + digit.ok_or_else(|| self.error_before("Invalid unicode escape"))?;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.offset;
+        let mut integral = true;
+        if self.peek() == Some('-') {
+            integral = false;
+            self.next();
+        }
+        match self.next() {
+            Some('0') => {}
+            Some(c) if c.is_ascii_digit() => self.digits(),
+            _ => return Err(self.error_before("Expected a digit")),
+        }
+        if self.peek() == Some('.') {
+            integral = false;
+            self.next();
+            self.expect_digits()?;
+        }
+        if let Some('e') | Some('E') = self.peek() {
+            integral = false;
+            self.next();
+            if let Some('+') | Some('-') = self.peek() {
+                self.next();
+            }
+            self.expect_digits()?;
+        }
+        let text: String = self.chars[start..self.offset].iter().collect();
+        match text.parse::<u64>() {
+            Ok(value) if integral => Ok(Json::Integer(value)),
+            _ => text.parse::<f64>().map(Json::Float).map_err(|_| JsonError {
+                offset: start,
+                message: String::from("Invalid number"),
+            }),
+        }
+    }
+
+    fn expect_digits(&mut self) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                self.digits();
+                Ok(())
+            }
+            _ => Err(self.error("Expected a digit")),
+        }
+    }
+
+    fn digits(&mut self) {
+        while self.peek().filter(char::is_ascii_digit).is_some() {
+            self.next();
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.error_before("Expected a value"));
+            }
+        }
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        if self.peek() == Some(expected) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected `{}`", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+            self.next();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += 1;
+        Some(c)
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> JsonError {
+        JsonError {
+            offset: self.offset,
+            message: message.into(),
+        }
+    }
+
+    /// An error at the character that was just read.
+    fn error_before<S: Into<String>>(&self, message: S) -> JsonError {
+        JsonError {
+            offset: self.offset.saturating_sub(1),
+            message: message.into(),
+        }
+    }
+}
@@ -23,8 +23,52 @@
 use std::io;
 use std::path::Path;
 
+#[cfg(feature = "experiment")]
+pub mod cache;
+#[cfg(all(target_os = "linux", feature = "services"))]
+pub mod cgroup;
+pub mod chain;
+pub mod checkpoint;
+pub mod checksum;
+pub mod compression;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod context;
+#[cfg(feature = "experiment")]
+pub mod experiment;
+#[cfg(feature = "sweep")]
+mod expr;
+pub mod fingerprint;
+pub mod graph;
+pub mod json;
+#[cfg(feature = "experiment")]
+pub mod lock;
+#[cfg(feature = "experiment")]
+pub mod manifest;
 #[macro_use]
 pub mod process;
+#[cfg(all(unix, feature = "services"))]
+pub mod pty;
+#[cfg(feature = "experiment")]
+pub mod queue;
+#[cfg(feature = "experiment")]
+mod rng;
+#[cfg(feature = "experiment")]
+pub mod rundir;
+pub mod script;
+#[cfg(feature = "experiment")]
+pub mod seeds;
+#[cfg(feature = "services")]
+pub mod service;
+mod shell;
+pub mod stage;
+#[cfg(feature = "services")]
+pub mod supervisor;
+#[cfg(feature = "sweep")]
+pub mod sweep;
+pub mod template;
+#[cfg(feature = "config")]
+mod toml;
 
 /// Indicator of whether the output should be verbose.
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -48,7 +92,7 @@ pub fn verbose_if(verbose: bool, max_args: usize) -> Verbosity {
 }
 
 /// Indicator of whether to overwrite or fail when writing to existing files.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum OverwritePolicy {
     Force,
     Fail,
@@ -84,13 +128,54 @@ pub fn force_if(force: bool) -> OverwritePolicy {
 /// ```
 pub fn safe_mkdir(dir: &Path, policy: OverwritePolicy) -> io::Result<()> {
     match (policy, dir.exists()) {
-        (OverwritePolicy::Fail, true) => Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!(
-                "{} exists! Use --force option to overwrite.",
-                dir.to_str().unwrap_or("<Invalid UTF-8>")
-            ),
-        )),
+        (OverwritePolicy::Fail, true) => Err(std::io::Error::other(format!(
+            "{} exists! Use --force option to overwrite.",
+            dir.to_str().unwrap_or("<Invalid UTF-8>")
+        ))),
         (_, _) => std::fs::create_dir_all(dir),
     }
 }
+
+/// Applies `f` to all items using up to `threads` threads, and returns the results in the
+/// order of the items.
+pub(crate) fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    parallel_map_indexed(items, threads, |_, item| f(item))
+}
+
+/// Same as [`parallel_map`](fn.parallel_map.html), but `f` also gets the index of the thread
+/// it runs on, which is less than `threads`.
+pub(crate) fn parallel_map_indexed<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &T) -> R + Sync,
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+    std::thread::scope(|scope| {
+        for thread in 0..threads.clamp(1, items.len().max(1)) {
+            let (next, results, f) = (&next, &results, &f);
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                if idx >= items.len() {
+                    break;
+                }
+                let result = f(thread, &items[idx]);
+                results.lock().expect("Poisoned lock")[idx] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("Poisoned lock")
+        .into_iter()
+        .map(|result| result.expect("All items processed"))
+        .collect()
+}
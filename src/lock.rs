@@ -0,0 +1,119 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Advisory locks preventing concurrent runs in the same output directory.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the lock file in the output directory of a run.
+pub const LOCK_FILE: &str = ".lock";
+
+/// An advisory lock on a run directory, held until dropped.
+///
+/// The lock is taken on the [`LOCK_FILE`](constant.LOCK_FILE.html) in the directory (with
+/// `flock` on Unix), and the ID of the locking process is written to it. An
+/// [`Experiment`](../experiment/struct.Experiment.html) holds the lock on its output directory
+/// while it runs, so that a second driver started on the same directory, e.g., a job
+/// accidentally submitted twice, fails right away instead of interleaving its writes. The
+/// lock is released by the operating system if the process dies, so it is never stale.
+///
+/// # Examples
+/// ```
+/// # use experiment::lock::RunLock;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("lock").unwrap();
+/// let lock = RunLock::acquire(tmp.path()).unwrap();
+/// let err = RunLock::acquire(tmp.path()).unwrap_err();
+/// assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+/// assert_eq!(
+///     err.to_string(),
+///     format!(
+///         "{} is locked by another run (process {})",
+///         tmp.path().display(),
+///         std::process::id()
+///     )
+/// );
+/// drop(lock);
+/// assert!(RunLock::acquire(tmp.path()).is_ok());
+/// ```
+#[derive(Debug)]
+pub struct RunLock {
+    /// Holding the open file keeps the lock.
+    _file: File,
+    path: PathBuf,
+}
+
+impl RunLock {
+    /// Locks the directory, which must exist, or fails with `WouldBlock` if another run holds
+    /// the lock.
+    pub fn acquire<P: AsRef<Path>>(dir: P) -> io::Result<RunLock> {
+        let dir = dir.as_ref();
+        let path = dir.join(LOCK_FILE);
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if let Err(err) = try_lock(&file) {
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(err);
+            }
+            let mut owner = String::new();
+            file.read_to_string(&mut owner)?;
+            let owner = match owner.trim() {
+                "" => String::from("unknown process"),
+                pid => format!("process {}", pid),
+            };
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} is locked by another run ({})", dir.display(), owner),
+            ));
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(RunLock { _file: file, path })
+    }
+
+    /// The path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,343 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Provenance manifests recording how and where an experiment was run.
+
+use super::checksum::Checksum;
+use super::json::Json;
+use super::process::Process;
+use super::rundir::iso8601;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The name of the manifest file written to the output directory of a run.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Provenance of a run of an experiment: the exact commands, the state of the repository of
+/// the driver, and the machine it ran on.
+///
+/// [`Experiment::run`](../experiment/struct.Experiment.html#method.run) writes the manifest
+/// of the run as [`manifest.json`](constant.MANIFEST_FILE.html) to its output directory.
+///
+/// # Examples
+/// ```
+/// # use experiment::manifest::Manifest;
+/// let mut manifest = Manifest::new("bm25", "run-0001");
+/// manifest.commands.push((String::from("index"), Some(String::from("build_index -c corpus"))));
+/// manifest.parameters.insert(String::from("k1"), String::from("0.9"));
+/// let json = manifest.to_json();
+/// assert!(json.starts_with("{\n  \"experiment\": \"bm25\",\n  \"run_id\": \"run-0001\",\n"));
+/// assert!(json.contains(
+///     "  \"commands\": [\n    {\n      \"stage\": \"index\",\n      \
+///      \"command\": \"build_index -c corpus\"\n    }\n  ],\n"
+/// ));
+/// assert!(json.contains(&format!("  \"os\": \"{}\",\n", std::env::consts::OS)));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    /// The name of the experiment.
+    pub experiment: String,
+    /// The ID of the run.
+    pub run_id: String,
+    /// When the run started, in UTC, formatted as ISO 8601.
+    pub date: String,
+    /// The commands of the stages, in order, in shell syntax; `None` for closures.
+    pub commands: Vec<(String, Option<String>)>,
+    /// The parameters of the experiment.
+    pub parameters: BTreeMap<String, String>,
+    /// The master seed, if [seeds](../seeds/struct.Seeds.html) are used.
+    pub master_seed: Option<u64>,
+    /// The seeds of the stages, by name.
+    pub seeds: BTreeMap<String, u64>,
+    /// The state of the git repository the driver ran in, if any.
+    pub git: Option<GitState>,
+    /// The name of the host.
+    pub hostname: Option<String>,
+    /// The operating system, e.g., `linux`.
+    pub os: String,
+    /// The release of the kernel.
+    pub kernel: Option<String>,
+    /// The model of the CPU.
+    pub cpu: Option<String>,
+    /// The recorded environment variables; unset variables are left out.
+    pub environment: BTreeMap<String, String>,
+    /// The outputs of version probes, by name.
+    pub versions: BTreeMap<String, String>,
+    /// The declared output files of the stages that ran, recorded at the end of the run.
+    pub artifacts: Vec<Artifact>,
+}
+
+/// An output file of a stage, verified after the stage succeeded.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Artifact {
+    /// The name of the stage that wrote the file.
+    pub stage: String,
+    /// The path to the file.
+    pub path: PathBuf,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The checksum of the contents of the file.
+    pub checksum: Checksum,
+}
+
+/// The state of a git repository.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GitState {
+    /// The hash of the checked out commit.
+    pub commit: String,
+    /// Whether there are uncommitted changes, including untracked files.
+    pub dirty: bool,
+}
+
+impl Manifest {
+    /// Creates a manifest of a run starting now, with the information about the host and
+    /// the git repository of the current directory, but no commands or parameters.
+    pub fn new(experiment: &str, run_id: &str) -> Manifest {
+        let (hostname, kernel) = uname();
+        Manifest {
+            experiment: String::from(experiment),
+            run_id: String::from(run_id),
+            date: iso8601(SystemTime::now()),
+            commands: Vec::new(),
+            parameters: BTreeMap::new(),
+            master_seed: None,
+            seeds: BTreeMap::new(),
+            git: GitState::probe("."),
+            hostname,
+            os: String::from(std::env::consts::OS),
+            kernel,
+            cpu: cpu_model(),
+            environment: BTreeMap::new(),
+            versions: BTreeMap::new(),
+            artifacts: Vec::new(),
+        }
+    }
+
+    /// Records the value of an environment variable, if set.
+    pub fn record_env(&mut self, name: &str) -> &mut Manifest {
+        if let Ok(value) = std::env::var(name) {
+            self.environment.insert(String::from(name), value);
+        }
+        self
+    }
+
+    /// Runs a process reporting the version of a tool, e.g., `rustc -V`, and records its
+    /// trimmed standard output, or standard error if the output is empty, under `name`.
+    /// Fails if the process cannot be started or exits unsuccessfully.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::manifest::Manifest;
+    /// # use experiment::process::Process;
+    /// let mut manifest = Manifest::new("bm25", "run-0001");
+    /// manifest
+    ///     .probe_version("echo", &Process::new("echo", &["echo 1.0"]))
+    ///     .unwrap()
+    ///     .probe_version("stderr", &Process::new("sh", &["-c", "echo 2.0 >&2"]))
+    ///     .unwrap();
+    /// assert_eq!(manifest.versions["echo"], "echo 1.0");
+    /// assert_eq!(manifest.versions["stderr"], "2.0");
+    /// assert!(manifest.to_json().contains("  \"versions\": {\n    \"echo\": \"echo 1.0\","));
+    ///
+    /// let err = manifest.probe_version("false", &Process::new("false", &[] as &[&str]));
+    /// assert_eq!(err.unwrap_err().to_string(), "Version probe `false` failed: exit status: 1");
+    /// ```
+    pub fn probe_version(&mut self, name: &str, process: &Process) -> io::Result<&mut Manifest> {
+        let output = process.capture().map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Version probe `{}` failed: {}", name, err),
+            )
+        })?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "Version probe `{}` failed: {}",
+                name, output.status
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let version = match stdout.trim() {
+            "" => String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            version => String::from(version),
+        };
+        self.versions.insert(String::from(name), version);
+        Ok(self)
+    }
+
+    /// Formats the manifest as a JSON object, indented with two spaces.
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+
+    /// Writes the manifest to [`manifest.json`](constant.MANIFEST_FILE.html) in `dir`, and
+    /// returns the path to the file.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::manifest::Manifest;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("manifest").unwrap();
+    /// let manifest = Manifest::new("bm25", "run-0001");
+    /// let path = manifest.write(tmp.path()).unwrap();
+    /// assert_eq!(path, tmp.path().join("manifest.json"));
+    /// assert_eq!(std::fs::read_to_string(&path).unwrap(), manifest.to_json() + "\n");
+    /// ```
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> io::Result<PathBuf> {
+        let path = dir.as_ref().join(MANIFEST_FILE);
+        std::fs::write(&path, self.to_json() + "\n")?;
+        Ok(path)
+    }
+
+    fn json(&self) -> Json {
+        let strings = |map: &BTreeMap<String, String>| {
+            Json::object(
+                map.iter()
+                    .map(|(key, value)| (key.as_str(), Json::from(value.as_str()))),
+            )
+        };
+        let commands = self
+            .commands
+            .iter()
+            .map(|(stage, command)| {
+                Json::object(vec![
+                    ("stage", Json::from(stage.as_str())),
+                    ("command", Json::optional(command.as_deref())),
+                ])
+            })
+            .collect();
+        let artifacts = self
+            .artifacts
+            .iter()
+            .map(|artifact| {
+                Json::object(vec![
+                    ("stage", Json::from(artifact.stage.as_str())),
+                    ("path", Json::from(artifact.path.to_string_lossy().as_ref())),
+                    ("size", Json::Integer(artifact.size)),
+                    (
+                        "checksum",
+                        Json::from(artifact.checksum.to_string().as_str()),
+                    ),
+                ])
+            })
+            .collect();
+        let git = self.git.as_ref().map_or(Json::Null, |git| {
+            Json::object(vec![
+                ("commit", Json::from(git.commit.as_str())),
+                ("dirty", Json::from(git.dirty)),
+            ])
+        });
+        Json::object(vec![
+            ("experiment", Json::from(self.experiment.as_str())),
+            ("run_id", Json::from(self.run_id.as_str())),
+            ("date", Json::from(self.date.as_str())),
+            ("commands", Json::Array(commands)),
+            ("parameters", strings(&self.parameters)),
+            (
+                "master_seed",
+                self.master_seed.map_or(Json::Null, Json::Integer),
+            ),
+            (
+                "seeds",
+                Json::object(
+                    self.seeds
+                        .iter()
+                        .map(|(stage, &seed)| (stage.as_str(), Json::Integer(seed))),
+                ),
+            ),
+            ("git", git),
+            ("hostname", Json::optional(self.hostname.as_deref())),
+            ("os", Json::from(self.os.as_str())),
+            ("kernel", Json::optional(self.kernel.as_deref())),
+            ("cpu", Json::optional(self.cpu.as_deref())),
+            ("environment", strings(&self.environment)),
+            ("versions", strings(&self.versions)),
+            ("artifacts", Json::Array(artifacts)),
+        ])
+    }
+}
+
+impl GitState {
+    /// Reads the state of the git repository containing `dir`; returns `None` if `dir` is
+    /// not in a repository or `git` is not available.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::manifest::GitState;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("git").unwrap();
+    /// assert_eq!(GitState::probe(tmp.path()), None);
+    /// ```
+    pub fn probe<P: AsRef<Path>>(dir: P) -> Option<GitState> {
+        let git = |args: &[&str]| -> Option<String> {
+            let mut process = Process::new("git", args);
+            process.current_dir(dir.as_ref());
+            let output = process.capture().ok()?;
+            if output.status.success() {
+                String::from_utf8(output.stdout).ok()
+            } else {
+                None
+            }
+        };
+        let commit = git(&["rev-parse", "HEAD"])?;
+        let status = git(&["status", "--porcelain"])?;
+        Some(GitState {
+            commit: String::from(commit.trim()),
+            dirty: !status.trim().is_empty(),
+        })
+    }
+}
+
+/// The host name and the kernel release.
+#[cfg(unix)]
+fn uname() -> (Option<String>, Option<String>) {
+    let field = |chars: &[libc::c_char]| {
+        let bytes: Vec<u8> = chars
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8)
+            .collect();
+        String::from_utf8(bytes).ok()
+    };
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } == 0 {
+        (field(&name.nodename), field(&name.release))
+    } else {
+        (None, None)
+    }
+}
+
+#[cfg(not(unix))]
+fn uname() -> (Option<String>, Option<String>) {
+    (std::env::var("COMPUTERNAME").ok(), None)
+}
+
+/// The CPU model reported by `/proc/cpuinfo`.
+fn cpu_model() -> Option<String> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim() == "model name")
+        .map(|(_, value)| String::from(value.trim()))
+}
@@ -22,11 +22,22 @@
 
 extern crate os_pipe;
 
+use super::checksum::{Checksum, ChecksumWriter};
+use super::context::ExecutionContext;
+use super::fingerprint::StableHasher;
+use super::shell::{shell_words, Dialect, Redirection, Token};
 use super::Verbosity::{Brief, Verbose};
 use super::*;
-use os_pipe::pipe;
+use os_pipe::{pipe, PipeReader, PipeWriter};
+use std::collections::BTreeMap;
 use std::fmt;
-use std::process::{Command, ExitStatus};
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::ops::BitOr;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
 
 /// A convenient text representation of a single shell program that provides easy printing and
 /// execution.
@@ -38,212 +49,3489 @@ use std::process::{Command, ExitStatus};
 /// let process = Process::new("cp", &["/path/to/source", "/path/to/target"]);
 /// process.execute().expect("Failed to execute");
 /// ```
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Process {
     program: String,
     args: Vec<String>,
+    env: BTreeMap<String, String>,
+    current_dir: Option<PathBuf>,
+    log_dir: Option<LogDir>,
+    context: Option<ExecutionContext>,
+    stdout: Redirect,
+    stderr: Redirect,
+    capture_limit: Option<(usize, OverflowPolicy)>,
+    cgroup: Option<PathBuf>,
+    cpus: Option<Vec<usize>>,
+    numa_node: Option<usize>,
+    label: Option<String>,
+    tags: BTreeMap<String, String>,
+}
+
+/// Indicator of what to do when captured output exceeds its limit.
+/// See [`Process::capture_limit`](struct.Process.html#method.capture_limit).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Keep the beginning of the output, and append a marker with the number of dropped bytes.
+    Truncate,
+    /// Kill the process and return an error.
+    Fail,
+}
+
+/// Output of a finished process, created by [`Process::capture`](struct.Process.html#method.capture).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedOutput {
+    /// The exit status of the process.
+    pub status: ExitStatus,
+    /// The captured standard output.
+    pub stdout: Vec<u8>,
+    /// The captured standard error.
+    pub stderr: Vec<u8>,
+}
+
+/// Output of a finished pipeline, created by
+/// [`ProcessPipeline::capture`](struct.ProcessPipeline.html#method.capture) and
+/// [`ProcessPipeline::capture_stderr`](struct.ProcessPipeline.html#method.capture_stderr).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineOutput {
+    /// The captured standard output of the last stage.
+    pub stdout: Vec<u8>,
+    /// Outputs of the stages, in pipeline order.
+    pub stages: Vec<StageOutput>,
+}
+
+/// Output of a single stage of a pipeline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageOutput {
+    /// The name of the stage.
+    /// See [`ProcessPipeline::stage_names`](struct.ProcessPipeline.html#method.stage_names).
+    pub name: String,
+    /// The exit status of the stage.
+    pub status: ExitStatus,
+    /// The time from starting the pipeline until the stage exited.
+    pub duration: Duration,
+    /// The time from starting the pipeline until the first byte of input reached the stage,
+    /// if measured; this requires [`PipeBuffering::Relay`](enum.PipeBuffering.html), and is
+    /// never measured for the first stage.
+    pub first_input: Option<Duration>,
+    /// The captured standard error of the stage.
+    pub stderr: Vec<u8>,
+    /// The number of bytes the stage passed on to the next one, if counted; see
+    /// [`PipeBuffering`](enum.PipeBuffering.html).
+    pub bytes_out: Option<u64>,
+}
+
+impl StageOutput {
+    /// The time from the first byte of input reaching the stage until it exited, which
+    /// excludes the time spent waiting for the previous stages to produce anything. Same as
+    /// [`duration`](#structfield.duration) if [`first_input`](#structfield.first_input) is
+    /// not measured.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{PipeBuffering, Process, ProcessPipeline};
+    /// # use std::time::Duration;
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("sh", &["-c", "sleep 0.3; echo a"]),
+    ///     Process::new("cat", &Vec::<&str>::new())
+    /// );
+    /// pipeline.buffering(PipeBuffering::Relay(4096));
+    /// let output = pipeline.capture().unwrap();
+    /// let (producer, consumer) = (&output.stages[0], &output.stages[1]);
+    /// assert_eq!(producer.first_input, None);
+    /// assert_eq!(producer.active_duration(), producer.duration);
+    /// assert!(consumer.first_input.unwrap() >= Duration::from_millis(300));
+    /// assert!(consumer.active_duration() < consumer.duration);
+    /// ```
+    pub fn active_duration(&self) -> Duration {
+        self.duration - self.first_input.unwrap_or_default().min(self.duration)
+    }
+
+    /// The average rate in bytes per second at which the stage passed data on to the next
+    /// one, over its entire [`duration`](#structfield.duration).
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.duration.as_secs_f64();
+        self.bytes_out
+            .map(|bytes| if secs > 0.0 { bytes as f64 / secs } else { 0.0 })
+    }
+}
+
+impl PipelineOutput {
+    /// The exit status of the last stage.
+    pub fn status(&self) -> Option<ExitStatus> {
+        self.stages.last().map(|stage| stage.status)
+    }
+
+    /// Checks if all stages succeeded.
+    pub fn success(&self) -> bool {
+        self.stages.iter().all(|stage| stage.status.success())
+    }
+
+    /// Finds the output of the stage with the given name.
+    pub fn stage(&self, name: &str) -> Option<&StageOutput> {
+        self.stages.iter().find(|stage| stage.name == name)
+    }
+}
+
+/// Result of [`Process::execute_to_file`](struct.Process.html#method.execute_to_file).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileOutput {
+    /// The exit status of the process.
+    pub status: ExitStatus,
+    /// The checksum of the written file.
+    pub checksum: Checksum,
+    /// The size of the written file in bytes.
+    pub size: u64,
+}
+
+/// Destination of an output stream of a [`Process`](struct.Process.html).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Redirect {
+    Inherit,
+    Null,
+    Stdout,
+    File(PathBuf),
+}
+
+/// A directory for log files, created according to its policy when first used, and shared
+/// by the clones of the process or pipeline that set it.
+#[derive(Clone, Debug)]
+struct LogDir {
+    path: PathBuf,
+    policy: OverwritePolicy,
+    created: std::sync::Arc<std::sync::Mutex<bool>>,
+}
+
+/// A [`Process`](Process.t.html) wrapper implementing `fmt::Display` trait.
+/// This indirection is created in order to explicitly set verbosity.
+///
+/// This object is created with [`Process::display`](struct.Process.html#method.display) method.
+pub struct ProcessDisplay<'a> {
+    process: &'a Process,
+    verbosity: Verbosity,
+}
+
+impl Process {
+    /// Creates a new [`Process`](Process.t.html).
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the arguments is not valid Unicode;
+    /// see [`try_new`](#method.try_new) for a fallible alternative.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let arguments = ["a", "b", "c"];
+    /// let process = Process::new("program_name", &arguments);
+    /// ```
+    pub fn new<I, S>(program: &str, args: I) -> Process
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        Process::try_new(program, args).expect("Invalid Unicode")
+    }
+
+    /// Creates a new [`Process`](Process.t.html), failing with `InvalidInput` if any of the
+    /// arguments is not valid Unicode.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # #[cfg(unix)]
+    /// # {
+    /// use std::ffi::OsStr;
+    /// use std::os::unix::ffi::OsStrExt;
+    /// assert!(Process::try_new("cat", &["input.txt"]).is_ok());
+    /// let invalid = OsStr::from_bytes(&[0x66, 0x6f, 0x80]);
+    /// assert!(Process::try_new("cat", &[invalid]).is_err());
+    /// # }
+    /// ```
+    pub fn try_new<I, S>(program: &str, args: I) -> std::io::Result<Process>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let mut process = Process {
+            program: String::from(program),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            current_dir: None,
+            log_dir: None,
+            context: None,
+            stdout: Redirect::Inherit,
+            stderr: Redirect::Inherit,
+            capture_limit: None,
+            cgroup: None,
+            cpus: None,
+            numa_node: None,
+            label: None,
+            tags: BTreeMap::new(),
+        };
+        process.try_args(args)?;
+        Ok(process)
+    }
+
+    /// Appends an argument to the process.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut process = Process::new("program", &["input"]);
+    /// process.arg("--threads").arg("8");
+    /// assert_eq!(
+    ///     format!("{}", process.display(Verbose)),
+    ///     "program input --threads 8".to_string()
+    /// );
+    /// ```
+    pub fn arg<S: AsRef<std::ffi::OsStr>>(&mut self, arg: S) -> &mut Process {
+        self.try_arg(arg).expect("Invalid Unicode")
+    }
+
+    /// Appends an argument to the process, failing with `InvalidInput` if it is not valid
+    /// Unicode, in which case the process is left unchanged.
+    pub fn try_arg<S: AsRef<std::ffi::OsStr>>(&mut self, arg: S) -> std::io::Result<&mut Process> {
+        let arg = arg.as_ref();
+        match arg.to_str() {
+            Some(arg) => {
+                self.args.push(String::from(arg));
+                Ok(self)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid Unicode in argument: {}", arg.to_string_lossy()),
+            )),
+        }
+    }
+
+    /// Appends multiple arguments to the process.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity::Verbose;
+    /// let quiet = true;
+    /// let mut process = Process::new("program", &["input"]);
+    /// if quiet {
+    ///     process.args(&["--log-level", "error"]);
+    /// }
+    /// assert_eq!(
+    ///     format!("{}", process.display(Verbose)),
+    ///     "program input --log-level error".to_string()
+    /// );
+    /// ```
+    pub fn args<I, S>(&mut self, args: I) -> &mut Process
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+
+    /// Appends multiple arguments to the process, failing with `InvalidInput` on the first
+    /// one that is not valid Unicode; the arguments preceding it are appended.
+    pub fn try_args<I, S>(&mut self, args: I) -> std::io::Result<&mut Process>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        for arg in args {
+            self.try_arg(arg)?;
+        }
+        Ok(self)
+    }
+
+    /// Sets an environment variable for the process.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use std::str::from_utf8;
+    /// let mut process = Process::new("printenv", &["GREETING"]);
+    /// process.env("GREETING", "Hello");
+    /// let output = process.command().output().expect("Failed to run process");
+    /// assert_eq!(from_utf8(&output.stdout).unwrap(), "Hello\n");
+    /// ```
+    pub fn env(&mut self, key: &str, value: &str) -> &mut Process {
+        self.env.insert(String::from(key), String::from(value));
+        self
+    }
+
+    /// Sets the working directory for the process.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use std::str::from_utf8;
+    /// let mut process = Process::new("pwd", &Vec::<&str>::new());
+    /// process.current_dir("/");
+    /// let output = process.command().output().expect("Failed to run process");
+    /// assert_eq!(from_utf8(&output.stdout).unwrap(), "/\n");
+    /// ```
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Process {
+        self.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets a human-readable label of the process, which is shown in its display, and used
+    /// in the names of its log files instead of the program name.
+    ///
+    /// Labels and tags do not affect the [`fingerprint`](#method.fingerprint).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut process = Process::new("evaluate", &["--k", "10"]);
+    /// process.label("eval-k10").tag("k", "10");
+    /// assert_eq!(process.display(Verbose).to_string(), "[eval-k10] evaluate --k 10");
+    /// assert_eq!(process.get_label(), Some("eval-k10"));
+    /// assert_eq!(process.get_tags().get("k").map(String::as_str), Some("10"));
+    /// assert_eq!(process.fingerprint(), Process::new("evaluate", &["--k", "10"]).fingerprint());
+    /// ```
+    pub fn label(&mut self, label: &str) -> &mut Process {
+        self.label = Some(String::from(label));
+        self
+    }
+
+    /// Attaches a key/value tag to the process, replacing any previous value of the key.
+    pub fn tag(&mut self, key: &str, value: &str) -> &mut Process {
+        self.tags.insert(String::from(key), String::from(value));
+        self
+    }
+
+    /// Returns the label of the process.
+    pub fn get_label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Returns the tags of the process.
+    pub fn get_tags(&self) -> &BTreeMap<String, String> {
+        &self.tags
+    }
+
+    /// Returns the working directory of the process, if set.
+    pub fn get_current_dir(&self) -> Option<&Path> {
+        self.current_dir.as_deref()
+    }
+
+    /// Discards the standard output of the process, as `> /dev/null` would.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut process = Process::new("echo", &["noise"]);
+    /// process.silence_stdout();
+    /// assert_eq!(format!("{}", process.display(Verbose)), "echo noise > /dev/null");
+    /// let output = process.command().output().unwrap();
+    /// assert!(output.stdout.is_empty());
+    /// ```
+    pub fn silence_stdout(&mut self) -> &mut Process {
+        self.stdout = Redirect::Null;
+        self
+    }
+
+    /// Discards the standard error of the process, as `2> /dev/null` would.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut process = Process::new("ls", &["/nonexistent"]);
+    /// process.silence_stderr();
+    /// assert_eq!(format!("{}", process.display(Verbose)), "ls /nonexistent 2> /dev/null");
+    /// let output = process.command().output().unwrap();
+    /// assert!(output.stderr.is_empty());
+    /// ```
+    pub fn silence_stderr(&mut self) -> &mut Process {
+        self.stderr = Redirect::Null;
+        self
+    }
+
+    /// Redirects the standard error of the process to wherever its standard output goes,
+    /// as `2>&1` would. This includes log files and pipes between pipeline stages.
+    ///
+    /// Note that [`command`](#method.command) can only merge the streams into the standard
+    /// output of the current process; if you redirect the output of the returned command
+    /// yourself, you have to redirect its error as well.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut process = Process::new("sh", &["-c", "echo error >&2"]);
+    /// process.merge_stderr_into_stdout();
+    /// assert_eq!(format!("{}", process.display(Verbose)), "sh -c echo error >&2 2>&1");
+    /// let pipeline = pipeline!(process, Process::new("cat", &Vec::<&str>::new()));
+    /// let output = pipeline.pipe().unwrap().output().unwrap();
+    /// assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "error\n");
+    /// ```
+    pub fn merge_stderr_into_stdout(&mut self) -> &mut Process {
+        self.stderr = Redirect::Stdout;
+        self
+    }
+
+    /// Appends the standard error of the process to a file, as `2>> path` would, creating
+    /// the file if it does not exist. Unlike log files, the sink is used by all execution
+    /// methods, including pipelines, whose stages may share a single file.
+    ///
+    /// If the file cannot be opened, spawning the process fails with the corresponding error.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("stderr").unwrap();
+    /// let sink = tmp.path().join("errors.txt");
+    /// let mut process = Process::new("sh", &["-c", "echo data; echo diagnostics >&2"]);
+    /// process.stderr_to_file(&sink);
+    /// assert_eq!(
+    ///     format!("{}", process.display(Verbose)),
+    ///     format!("sh -c echo data; echo diagnostics >&2 2>> {}", sink.display())
+    /// );
+    /// let pipeline = pipeline!(process, Process::new("cat", &Vec::<&str>::new()));
+    /// let output = pipeline.pipe().unwrap().output().unwrap();
+    /// assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "data\n");
+    /// assert_eq!(std::fs::read_to_string(&sink).unwrap(), "diagnostics\n");
+    /// ```
+    pub fn stderr_to_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Process {
+        self.stderr = Redirect::File(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Limits the number of bytes that [`capture`](#method.capture) collects from each of the
+    /// output streams.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::{OverflowPolicy, Process};
+    /// let mut process = Process::new("echo", &["Hello, World!"]);
+    /// process.capture_limit(5, OverflowPolicy::Truncate);
+    /// let output = process.capture().unwrap();
+    /// assert_eq!(output.stdout, b"Hello\n[truncated 9 bytes]\n".to_vec());
+    ///
+    /// let mut process = Process::new("yes", &Vec::<&str>::new());
+    /// process.capture_limit(1024, OverflowPolicy::Fail);
+    /// assert!(process.capture().is_err());
+    /// ```
+    pub fn capture_limit(&mut self, max_bytes: usize, policy: OverflowPolicy) -> &mut Process {
+        self.capture_limit = Some((max_bytes, policy));
+        self
+    }
+
+    /// Places the process in a control group right before it starts, so that it is subject to
+    /// the limits of the group, and its resources are accounted for by it.
+    /// See [`Cgroup`](../cgroup/struct.Cgroup.html) for details.
+    #[cfg(all(target_os = "linux", feature = "services"))]
+    pub fn cgroup(&mut self, group: &crate::cgroup::Cgroup) -> &mut Process {
+        self.cgroup = Some(group.path().to_path_buf());
+        self
+    }
+
+    /// Restricts the process (and its descendants) to run only on the given CPUs, as
+    /// `taskset` would.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let mut process = Process::new("grep", &["Cpus_allowed_list", "/proc/self/status"]);
+    /// process.pin_to_cpus(&[0]);
+    /// let output = process.capture().unwrap();
+    /// assert!(String::from_utf8(output.stdout).unwrap().trim().ends_with("\t0"));
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn pin_to_cpus(&mut self, cpus: &[usize]) -> &mut Process {
+        self.cpus = Some(cpus.to_vec());
+        self
+    }
+
+    /// Binds the process to the CPUs and memory of a NUMA node, as
+    /// `numactl --cpunodebind=<node> --membind=<node>` would. If combined with
+    /// [`pin_to_cpus`](#method.pin_to_cpus), the explicit CPU list takes precedence.
+    ///
+    /// The process fails to start if the node does not exist.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let mut process = Process::new("grep", &["Mems_allowed_list", "/proc/self/status"]);
+    /// process.numa_node(0);
+    /// let output = process.capture().unwrap();
+    /// assert!(String::from_utf8(output.stdout).unwrap().trim().ends_with("\t0"));
+    ///
+    /// let mut process = Process::new("true", &Vec::<&str>::new());
+    /// process.numa_node(4096);
+    /// assert!(process.execute().is_err());
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn numa_node(&mut self, node: usize) -> &mut Process {
+        self.numa_node = Some(node);
+        self
+    }
+
+    /// Sets the execution context, whose environment variables are passed to the process.
+    /// Without one, the process receives the context
+    /// [entered](../context/struct.ExecutionContext.html#method.enter) on the thread that
+    /// starts it, if any. See [`ExecutionContext`](../context/struct.ExecutionContext.html)
+    /// for details.
+    pub fn context(&mut self, context: &ExecutionContext) -> &mut Process {
+        self.context = Some(context.clone());
+        self
+    }
+
+    /// Returns a copy of the process with `{name}` placeholders replaced with the values
+    /// returned by `lookup` in the program, the arguments, the values of environment
+    /// variables, the working directory, and the paths of output files and log directories.
+    /// Placeholders are written as in a
+    /// [`CommandTemplate`](../template/struct.CommandTemplate.html), and other braces are left
+    /// as they are.
+    ///
+    /// Fails with `InvalidInput` if a placeholder is unknown, i.e., `lookup` returns `None`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let mut process = Process::new("evaluate", &["-k", "{k}", "{{literal}}"]);
+    /// process.stderr_to_file("{run_dir}/evaluate.err");
+    /// let lookup = |name: &str| match name {
+    ///     "k" => Some("10"),
+    ///     "run_dir" => Some("results"),
+    ///     _ => None,
+    /// };
+    /// let interpolated = process.interpolate(lookup).unwrap();
+    /// assert_eq!(
+    ///     interpolated.to_shell_string(),
+    ///     "evaluate -k 10 '{literal}' 2>> results/evaluate.err"
+    /// );
+    /// let err = process.interpolate(|_| None).unwrap_err();
+    /// assert_eq!(err.to_string(), "Unknown placeholder `{k}` in `{k}`");
+    /// ```
+    pub fn interpolate<'a, F>(&self, lookup: F) -> std::io::Result<Process>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        self.interpolated(&lookup)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+    }
+
+    fn interpolated<'a, F>(&self, lookup: &F) -> Result<Process, String>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let mut process = self.clone();
+        process.program = template::interpolate(&self.program, lookup)?;
+        for arg in &mut process.args {
+            *arg = template::interpolate(arg, lookup)?;
+        }
+        for value in process.env.values_mut() {
+            *value = template::interpolate(value, lookup)?;
+        }
+        if let Some(dir) = &mut process.current_dir {
+            *dir = interpolate_path(dir, lookup)?;
+        }
+        if let Some(dir) = &mut process.log_dir {
+            *dir = LogDir::new(interpolate_path(&dir.path, lookup)?, dir.policy);
+        }
+        for redirect in [&mut process.stdout, &mut process.stderr] {
+            if let Redirect::File(path) = redirect {
+                *path = interpolate_path(path, lookup)?;
+            }
+        }
+        Ok(process)
+    }
+
+    /// Makes [`execute`](#method.execute) write the standard output and error of the process
+    /// to timestamped files in `dir`, which is created with [`safe_mkdir`](../fn.safe_mkdir.html)
+    /// right before the first execution. Later executions, also of clones of the process,
+    /// add their files to the same directory.
+    ///
+    /// The files are named `<name>-<seconds>-<microseconds>.stdout.log` and
+    /// `<name>-<seconds>-<microseconds>.stderr.log`, where `<name>` is the
+    /// [label](#method.label) if set, and the program name otherwise.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tempdir::TempDir;
+    /// # use experiment::process::Process;
+    /// # use experiment::OverwritePolicy;
+    /// let tmp = TempDir::new("logs").unwrap();
+    /// let dir = tmp.path().join("echo");
+    /// let mut process = Process::new("echo", &["Hello,", "World!"]);
+    /// process.log_to(&dir, OverwritePolicy::Fail);
+    /// process.execute().expect("Failed to run process");
+    /// let stdout_log = std::fs::read_dir(&dir)
+    ///     .unwrap()
+    ///     .map(|entry| entry.unwrap().path())
+    ///     .find(|path| path.to_str().unwrap().ends_with(".stdout.log"))
+    ///     .unwrap();
+    /// assert_eq!(std::fs::read_to_string(stdout_log).unwrap(), "Hello, World!\n");
+    ///
+    /// // The second execution logs to the directory created by the first one.
+    /// std::thread::sleep(std::time::Duration::from_millis(1));
+    /// process.execute().expect("Failed to run process again");
+    /// assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 4);
+    ///
+    /// // Another process does not log to an existing directory.
+    /// let mut other = Process::new("echo", &["Hello,", "World!"]);
+    /// other.log_to(&dir, OverwritePolicy::Fail);
+    /// assert!(other.execute().is_err());
+    /// ```
+    pub fn log_to<P: AsRef<Path>>(&mut self, dir: P, policy: OverwritePolicy) -> &mut Process {
+        self.log_dir = Some(LogDir::new(dir.as_ref().to_path_buf(), policy));
+        self
+    }
+
+    /// Finds the executable that would be run, looking it up in `PATH` (like `which`) if the
+    /// program name contains no path separator, and returns its absolute path.
+    ///
+    /// Fails if the program cannot be found or is not executable. Calling this before running
+    /// any long computation reveals missing binaries early.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let path = Process::new("sh", &["-c", "true"]).resolve().unwrap();
+    /// assert!(path.is_absolute());
+    /// assert!(path.ends_with("sh"));
+    /// assert!(Process::new("unknown_process", &Vec::<&str>::new()).resolve().is_err());
+    /// ```
+    pub fn resolve(&self) -> std::io::Result<PathBuf> {
+        let program = Path::new(&self.program);
+        let base_dir = match &self.current_dir {
+            Some(dir) => std::env::current_dir()?.join(dir),
+            None => std::env::current_dir()?,
+        };
+        let candidates: Vec<PathBuf> = if program.components().count() > 1 {
+            vec![base_dir.join(program)]
+        } else {
+            let path = match self.env.get("PATH") {
+                Some(path) => Some(std::ffi::OsString::from(path)),
+                None => std::env::var_os("PATH"),
+            };
+            path.map(|path| {
+                std::env::split_paths(&path)
+                    .map(|dir| base_dir.join(dir).join(program))
+                    .collect()
+            })
+            .unwrap_or_default()
+        };
+        candidates
+            .into_iter()
+            .find(|candidate| is_executable(candidate))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{}: executable not found", self.program),
+                )
+            })
+    }
+
+    /// Returns a hash of the program, arguments, environment, and working directory that is
+    /// stable across runs, and therefore can be used as a cache key.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let process = Process::new("ls", &["-l"]);
+    /// assert_eq!(process.fingerprint(), Process::new("ls", &["-l"]).fingerprint());
+    /// assert_ne!(process.fingerprint(), Process::new("ls", &["-a"]).fingerprint());
+    /// let mut with_env = process.clone();
+    /// with_env.env("LC_ALL", "C");
+    /// assert_ne!(process.fingerprint(), with_env.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write_str(&self.program);
+        hasher.write_usize(self.args.len());
+        for arg in &self.args {
+            hasher.write_str(arg);
+        }
+        hasher.write_usize(self.env.len());
+        for (key, value) in &self.env {
+            hasher.write_str(key);
+            hasher.write_str(value);
+        }
+        match &self.current_dir {
+            Some(dir) => {
+                hasher.write_u8(1);
+                hasher.write_str(&dir.to_string_lossy());
+            }
+            None => hasher.write_u8(0),
+        }
+        hasher.finish()
+    }
+
+    /// Creates a [`ProcessDisplay`](ProcessDisplay.t.html) object with the desired verbosity.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::Verbosity::{Verbose, Brief};
+    /// let process = Process::new("ls", &["-l", "/path/to/dir"]);
+    /// assert_eq!(format!("{}", process.display(Verbose)), "ls -l /path/to/dir".to_string());
+    /// assert_eq!(format!("{}", process.display(Brief(2))), "ls -l /path/to/dir".to_string());
+    /// assert_eq!(format!("{}", process.display(Brief(1))), "ls -l ...".to_string());
+    /// ```
+    pub fn display(&self, verbosity: Verbosity) -> ProcessDisplay<'_> {
+        ProcessDisplay {
+            process: self,
+            verbosity,
+        }
+    }
+
+    /// Renders the process as a single line that can be pasted into a POSIX shell, with all
+    /// arguments quoted where necessary and output redirections included. Environment
+    /// variables are set with `env`, and the working directory with `cd` in a subshell.
+    /// Labels, tags, and other settings that have no shell equivalent are omitted.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let mut process = Process::new("grep", &["-e", "it's here", "input file.txt"]);
+    /// process.silence_stderr();
+    /// assert_eq!(
+    ///     process.to_shell_string(),
+    ///     "grep -e 'it'\\''s here' 'input file.txt' 2> /dev/null"
+    /// );
+    ///
+    /// let mut process = Process::new("sort", &["-k2"]);
+    /// process.env("LC_ALL", "C").env("TMPDIR", "/scratch space").current_dir("data");
+    /// assert_eq!(
+    ///     process.to_shell_string(),
+    ///     "(cd data && env LC_ALL=C 'TMPDIR=/scratch space' sort -k2)"
+    /// );
+    /// ```
+    pub fn to_shell_string(&self) -> String {
+        self.shell_line(Dialect::Posix)
+    }
+
+    /// Renders the process like [`to_shell_string`](#method.to_shell_string), but for the
+    /// Windows command interpreter, `cmd.exe`. Arguments are quoted following the rules
+    /// programs use to split their command line, environment variables are set with `set`,
+    /// and the working directory with `cd /d`; the latter two within parentheses.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let mut process = Process::new("findstr", &["say \"hi\"", "C:\\My Data\\"]);
+    /// process.silence_stderr();
+    /// assert_eq!(
+    ///     process.to_cmd_string(),
+    ///     r#"findstr "say \"hi\"" "C:\My Data\\" 2> NUL"#
+    /// );
+    ///
+    /// let mut process = Process::new("sort", &Vec::<&str>::new());
+    /// process.env("LC_ALL", "C").current_dir("D:\\data");
+    /// assert_eq!(
+    ///     process.to_cmd_string(),
+    ///     r#"(cd /d D:\data && set "LC_ALL=C" && sort)"#
+    /// );
+    /// ```
+    pub fn to_cmd_string(&self) -> String {
+        self.shell_line(Dialect::Cmd)
+    }
+
+    fn shell_line(&self, dialect: Dialect) -> String {
+        let grouped =
+            self.current_dir.is_some() || (dialect == Dialect::Cmd && !self.env.is_empty());
+        let mut line = String::new();
+        if grouped {
+            line.push('(');
+        }
+        if let Some(dir) = &self.current_dir {
+            line.push_str(match dialect {
+                Dialect::Posix => "cd ",
+                Dialect::Cmd => "cd /d ",
+            });
+            line.push_str(&dialect.quote(&dir.to_string_lossy()));
+            line.push_str(" && ");
+        }
+        match dialect {
+            Dialect::Posix if !self.env.is_empty() => {
+                line.push_str("env ");
+                for (key, value) in &self.env {
+                    line.push_str(&dialect.quote(&format!("{}={}", key, value)));
+                    line.push(' ');
+                }
+            }
+            Dialect::Posix => {}
+            Dialect::Cmd => {
+                for (key, value) in &self.env {
+                    line.push_str(&format!("set \"{}={}\" && ", key, value));
+                }
+            }
+        }
+        line.push_str(&dialect.quote(&self.program));
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&dialect.quote(arg));
+        }
+        if grouped {
+            line.push(')');
+        }
+        if self.stdout == Redirect::Null {
+            line.push_str(" > ");
+            line.push_str(dialect.null_device());
+        }
+        match &self.stderr {
+            Redirect::Null => {
+                line.push_str(" 2> ");
+                line.push_str(dialect.null_device());
+            }
+            Redirect::Stdout => line.push_str(" 2>&1"),
+            Redirect::File(path) => {
+                line.push_str(" 2>> ");
+                line.push_str(&dialect.quote(&path.to_string_lossy()));
+            }
+            Redirect::Inherit => {}
+        }
+        line
+    }
+
+    /// Generates a [`Command`](https://doc.rust-lang.org/std/process/struct.Command.html) object.
+    ///
+    /// If the file that standard error is [redirected to](#method.stderr_to_file) cannot be
+    /// opened, the command fails to spawn on Unix, while elsewhere it inherits standard error;
+    /// [`try_command`](#method.try_command) reports the error on all platforms.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use std::str::from_utf8;
+    /// let process = Process::new("echo", &["Hello,", "World!"]);
+    /// let output = process.command().output().expect("Failed to run process");
+    /// assert_eq!(from_utf8(&output.stdout).unwrap(), "Hello, World!\n");
+    /// ```
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+        cmd.envs(&self.env);
+        match &self.context {
+            Some(context) => context.apply(&mut cmd),
+            None => {
+                if let Some(context) = ExecutionContext::current() {
+                    context.apply(&mut cmd);
+                }
+            }
+        }
+        if let Some(dir) = &self.current_dir {
+            cmd.current_dir(dir);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(group) = &self.cgroup {
+                join_cgroup(&mut cmd, group);
+            }
+            if let Some(node) = self.numa_node {
+                bind_numa_node(&mut cmd, node);
+            }
+            if let Some(cpus) = &self.cpus {
+                set_affinity(&mut cmd, cpus.clone());
+            }
+        }
+        if self.stdout == Redirect::Null {
+            cmd.stdout(Stdio::null());
+        }
+        match (&self.stderr, &self.stdout) {
+            (Redirect::Null, _) | (Redirect::Stdout, Redirect::Null) => {
+                cmd.stderr(Stdio::null());
+            }
+            (Redirect::Stdout, _) => {
+                cmd.stderr(std::io::stdout());
+            }
+            (Redirect::File(path), _) => match open_stderr_file(path) {
+                Ok(file) => {
+                    cmd.stderr(file);
+                }
+                #[cfg(unix)]
+                Err(err) => fail_spawn(&mut cmd, err),
+                #[cfg(not(unix))]
+                Err(_) => {}
+            },
+            (Redirect::Inherit, _) => {}
+        }
+        cmd
+    }
+
+    /// Same as [`command`](#method.command), but fails if the file that standard error is
+    /// [redirected to](#method.stderr_to_file) cannot be opened.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let mut process = Process::new("echo", &["a"]);
+    /// process.stderr_to_file("/nonexistent/stderr.log");
+    /// let err = process.try_command().unwrap_err();
+    /// assert!(err.to_string().starts_with("/nonexistent/stderr.log: "));
+    /// assert!(process.execute().is_err());
+    /// ```
+    pub fn try_command(&self) -> std::io::Result<Command> {
+        if let Redirect::File(path) = &self.stderr {
+            open_stderr_file(path).map_err(|err| {
+                std::io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+            })?;
+        }
+        Ok(self.command())
+    }
+
+    /// Executes the command, ignoring the generated output.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let process = Process::new("echo", &["Hello,", "World!"]);
+    /// process.execute().expect("Failed to run process");
+    ///
+    /// let process = Process::new("unknown_process", &Vec::<&str>::new());
+    /// assert!(process.execute().is_err());
+    /// ```
+    pub fn execute(&self) -> std::io::Result<ExitStatus> {
+        self.logged_command()?.status()
+    }
+
+    /// Executes the command and collects its standard output and error, subject to the
+    /// [`capture_limit`](#method.capture_limit) if one is set.
+    ///
+    /// Streams that are silenced are not captured, and the standard error merged into the
+    /// standard output is captured as a part of the latter. Log files set up with
+    /// [`log_to`](#method.log_to) are not written.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let output = Process::new("sh", &["-c", "echo out; echo err >&2"]).capture().unwrap();
+    /// assert!(output.status.success());
+    /// assert_eq!(output.stdout, b"out\n".to_vec());
+    /// assert_eq!(output.stderr, b"err\n".to_vec());
+    /// ```
+    pub fn capture(&self) -> std::io::Result<CapturedOutput> {
+        let mut cmd = self.try_command()?;
+        let mut readers = Vec::new();
+        if self.stdout != Redirect::Null {
+            let (reader, writer) = pipe()?;
+            self.connect_stdout(&mut cmd, writer)?;
+            readers.push((0, reader));
+        }
+        if self.stderr == Redirect::Inherit {
+            let (reader, writer) = pipe()?;
+            cmd.stderr(writer);
+            readers.push((1, reader));
+        }
+        let mut child = cmd.spawn()?;
+        drop(cmd);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for (idx, reader) in readers {
+            let sender = sender.clone();
+            let limit = self.capture_limit;
+            std::thread::spawn(move || {
+                let _ = sender.send((idx, read_limited(reader, limit)));
+            });
+        }
+        drop(sender);
+        let mut outputs = [Vec::new(), Vec::new()];
+        let mut error = None;
+        for (idx, result) in receiver {
+            match result {
+                Ok(output) => outputs[idx] = output,
+                Err(err) => {
+                    if error.is_none() {
+                        let _ = child.kill();
+                        error = Some(err);
+                    }
+                }
+            }
+        }
+        let status = child.wait()?;
+        if let Some(err) = error {
+            return Err(err);
+        }
+        let [stdout, stderr] = outputs;
+        Ok(CapturedOutput {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// Executes the command like [`execute`](#method.execute), but prefixes each line of its
+    /// standard output and error with `[label] `, so that the output of processes running
+    /// concurrently can be told apart. Streams that are silenced or written to files are left
+    /// as they are.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let status = Process::new("echo", &["Hello"]).execute_labeled("greet").unwrap();
+    /// assert!(status.success());
+    /// ```
+    pub fn execute_labeled(&self, label: &str) -> std::io::Result<ExitStatus> {
+        self.execute_within(Some(label), None)
+    }
+
+    /// Executes the command, with its output [labeled](#method.execute_labeled) if `label` is
+    /// set. If the `(deadline, grace)` pair is set, the process is
+    /// [terminated](struct.PipelineChild.html#method.terminate) once the deadline passes,
+    /// which fails with `TimedOut`.
+    pub(crate) fn execute_within(
+        &self,
+        label: Option<&str>,
+        deadline: Option<(Instant, Duration)>,
+    ) -> std::io::Result<ExitStatus> {
+        let (mut child, relays) = match label {
+            Some(label) if self.log_dir.is_none() => self.spawn_labeled(label)?,
+            _ => (self.logged_command()?.spawn()?, Vec::new()),
+        };
+        let status = match deadline {
+            None => child.wait(),
+            Some((deadline, grace)) => wait_until(&mut child, deadline, grace),
+        };
+        for relay in relays {
+            relay
+                .join()
+                .map_err(|_| std::io::Error::other("Relay thread panicked"))??;
+        }
+        status
+    }
+
+    /// Spawns the command with its output streams relayed by threads that label each line.
+    #[allow(clippy::type_complexity)]
+    fn spawn_labeled(
+        &self,
+        label: &str,
+    ) -> std::io::Result<(Child, Vec<std::thread::JoinHandle<std::io::Result<()>>>)> {
+        let mut cmd = self.try_command()?;
+        let mut readers = Vec::new();
+        if self.stdout != Redirect::Null {
+            let (reader, writer) = pipe()?;
+            self.connect_stdout(&mut cmd, writer)?;
+            readers.push((false, reader));
+        }
+        if self.stderr == Redirect::Inherit {
+            let (reader, writer) = pipe()?;
+            cmd.stderr(writer);
+            readers.push((true, reader));
+        }
+        let child = cmd.spawn()?;
+        drop(cmd);
+        let relays = readers
+            .into_iter()
+            .map(|(stderr, reader)| {
+                let prefix = format!("[{}] ", label);
+                std::thread::spawn(move || relay_labeled(reader, &prefix, stderr))
+            })
+            .collect();
+        Ok((child, relays))
+    }
+
+    /// Executes the command, checks that it succeeded, and returns the lines of its standard
+    /// output.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let lines = Process::new("printf", &["a\\nb\\n"]).execute_lines().unwrap();
+    /// assert_eq!(lines.collect::<Vec<_>>(), vec!["a".to_string(), "b".to_string()]);
+    /// assert!(Process::new("false", &Vec::<&str>::new()).execute_lines().is_err());
+    /// ```
+    pub fn execute_lines(&self) -> std::io::Result<impl Iterator<Item = String>> {
+        let stdout = self.checked_stdout()?;
+        Ok(stdout
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>()
+            .into_iter())
+    }
+
+    /// Executes the command, checks that it succeeded, and parses its entire standard output
+    /// (with surrounding whitespace trimmed) into `T`.
+    ///
+    /// For JSON output, see [`execute_json`](#method.execute_json); for other structured
+    /// formats, implement `FromStr` for the result type using the parser of your choice.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let sum: u32 = Process::new("expr", &["2", "+", "3"]).execute_parsed().unwrap();
+    /// assert_eq!(sum, 5);
+    /// let result = Process::new("echo", &["five"]).execute_parsed::<u32>();
+    /// assert!(result.is_err());
+    /// ```
+    pub fn execute_parsed<T>(&self) -> std::io::Result<T>
+    where
+        T: std::str::FromStr,
+        T::Err: fmt::Display,
+    {
+        self.checked_stdout()?
+            .trim()
+            .parse()
+            .map_err(|err: T::Err| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}: failed to parse output: {}", self.program, err),
+                )
+            })
+    }
+
+    /// Executes the command, checks that it succeeded, and parses its standard output as a
+    /// JSON document into a [`Json`](../json/enum.Json.html) value tree.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::json::Json;
+    /// # use experiment::process::Process;
+    /// let json = Process::new("echo", &[r#"{"map": 0.3125, "queries": 50}"#])
+    ///     .execute_json()
+    ///     .unwrap();
+    /// assert_eq!(json.get("map").and_then(Json::as_f64), Some(0.3125));
+    /// assert_eq!(json.get("queries").and_then(Json::as_u64), Some(50));
+    /// let err = Process::new("echo", &["map: 0.3125"]).execute_json().unwrap_err();
+    /// assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    /// ```
+    pub fn execute_json(&self) -> std::io::Result<crate::json::Json> {
+        self.execute_parsed()
+    }
+
+    /// Captures the output, and returns the standard output if the process succeeded.
+    fn checked_stdout(&self) -> std::io::Result<String> {
+        let output = self.capture()?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "{} failed ({}): {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+
+    /// Executes the command, writing its standard output to a file, and computing the
+    /// checksum of the output on the fly, so that the file does not need to be read again.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::checksum::Checksum;
+    /// # use experiment::process::Process;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("output").unwrap();
+    /// let path = tmp.path().join("output.txt");
+    /// let output = Process::new("echo", &["abc"]).execute_to_file(&path).unwrap();
+    /// assert!(output.status.success());
+    /// assert_eq!(output.size, 4);
+    /// assert_eq!(output.checksum, Checksum::of_file(&path).unwrap());
+    /// ```
+    pub fn execute_to_file<P: AsRef<Path>>(&self, path: P) -> std::io::Result<FileOutput> {
+        let mut writer = ChecksumWriter::new(std::io::BufWriter::new(std::fs::File::create(path)?));
+        let mut cmd = self.try_command()?;
+        let (mut reader, pipe_writer) = pipe()?;
+        self.connect_stdout(&mut cmd, pipe_writer)?;
+        let mut child = cmd.spawn()?;
+        drop(cmd);
+        let copied = std::io::copy(&mut reader, &mut writer).and_then(|_| writer.flush());
+        if let Err(err) = copied {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+        let status = child.wait()?;
+        let (_, checksum, size) = writer.finish();
+        Ok(FileOutput {
+            status,
+            checksum,
+            size,
+        })
+    }
+
+    /// Executes the command, writing the items of `input` to its standard input, each
+    /// followed by a newline, as they are produced.
+    ///
+    /// The input is never collected in memory. If the process exits before consuming all
+    /// of it, the remaining items are not generated.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("input").unwrap();
+    /// let output = tmp.path().join("queries.txt");
+    /// let process = Process::new("sh", &["-c", &format!("cat > {}", output.display())]);
+    /// let queries = (0..3).map(|n| format!("query {}", n));
+    /// assert!(process.execute_with_input(queries).unwrap().success());
+    /// assert_eq!(
+    ///     std::fs::read_to_string(&output).unwrap(),
+    ///     "query 0\nquery 1\nquery 2\n"
+    /// );
+    /// ```
+    pub fn execute_with_input<I>(&self, input: I) -> std::io::Result<ExitStatus>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut cmd = self.logged_command()?;
+        cmd.stdin(Stdio::piped());
+        let mut child = cmd.spawn()?;
+        let stdin = child.stdin.take().expect("Standard input is piped");
+        let mut writer = std::io::BufWriter::new(stdin);
+        let written = input
+            .into_iter()
+            .try_for_each(|line| writeln!(writer, "{}", line))
+            .and_then(|_| writer.flush());
+        drop(writer);
+        match written {
+            Err(ref err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+            Err(err) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(err);
+            }
+            Ok(()) => {}
+        }
+        child.wait()
+    }
+
+    /// Starts the process in a new session, so that it keeps running after the current
+    /// process exits, and writes its PID to `pid_file`. Returns the PID.
+    ///
+    /// The standard input of the detached process is closed. Its output goes to log files if
+    /// set up with [`log_to`](#method.log_to), and is discarded otherwise.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("detach").unwrap();
+    /// let pid_file = tmp.path().join("server.pid");
+    /// let pid = Process::new("sleep", &["0.1"]).detach(&pid_file).unwrap();
+    /// assert_eq!(std::fs::read_to_string(&pid_file).unwrap(), format!("{}\n", pid));
+    /// ```
+    #[cfg(unix)]
+    pub fn detach<P: AsRef<Path>>(&self, pid_file: P) -> std::io::Result<u32> {
+        use std::os::unix::process::CommandExt;
+        let mut cmd = self.logged_command()?;
+        cmd.stdin(Stdio::null());
+        if self.log_dir.is_none() {
+            cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = cmd.spawn()?;
+        let pid = child.id();
+        // Reap the process if it finishes before the current one does.
+        std::thread::spawn(move || child.wait());
+        std::fs::write(pid_file, format!("{}\n", pid))?;
+        Ok(pid)
+    }
+
+    /// Executes the command `n` times in a row, measuring the wall-clock time of each run.
+    ///
+    /// Returns an error if `n` is zero or any of the runs fails to start. Exit statuses are not
+    /// checked, but they are recorded in the returned summary.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let process = Process::new("true", &Vec::<&str>::new());
+    /// let runs = process.execute_repeated(5).expect("Failed to run process");
+    /// assert_eq!(runs.durations().len(), 5);
+    /// assert!(runs.statuses().iter().all(|status| status.success()));
+    /// assert!(runs.min() <= runs.median() && runs.median() <= runs.max());
+    /// assert!(process.execute_repeated(0).is_err());
+    /// ```
+    pub fn execute_repeated(&self, n: usize) -> std::io::Result<RepeatedExecution> {
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Number of repetitions must be positive",
+            ));
+        }
+        let mut durations = Vec::with_capacity(n);
+        let mut statuses = Vec::with_capacity(n);
+        for _ in 0..n {
+            let start = Instant::now();
+            statuses.push(self.execute()?);
+            durations.push(start.elapsed());
+        }
+        Ok(RepeatedExecution {
+            durations,
+            statuses,
+        })
+    }
+
+    /// Generates a command that has its output redirected to log files if requested.
+    fn logged_command(&self) -> std::io::Result<Command> {
+        match &self.log_dir {
+            None => self.try_command(),
+            Some(dir) => {
+                dir.create()?;
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let name = match &self.label {
+                    Some(label) => label.clone(),
+                    None => Path::new(&self.program)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| self.program.clone()),
+                };
+                let prefix = format!(
+                    "{}-{}-{:06}",
+                    name,
+                    timestamp.as_secs(),
+                    timestamp.subsec_micros()
+                );
+                self.redirected_command(
+                    &dir.path.join(format!("{}.stdout.log", prefix)),
+                    &dir.path.join(format!("{}.stderr.log", prefix)),
+                )
+            }
+        }
+    }
+
+    /// Generates a command writing its output streams to the given files, unless they are
+    /// discarded or merged.
+    pub(crate) fn redirected_command(
+        &self,
+        stdout: &Path,
+        stderr: &Path,
+    ) -> std::io::Result<Command> {
+        let mut cmd = self.try_command()?;
+        if self.stdout == Redirect::Inherit {
+            let stdout = std::fs::File::create(stdout)?;
+            if self.stderr == Redirect::Stdout {
+                cmd.stderr(stdout.try_clone()?);
+            }
+            cmd.stdout(stdout);
+        }
+        if self.stderr == Redirect::Inherit {
+            cmd.stderr(std::fs::File::create(stderr)?);
+        }
+        Ok(cmd)
+    }
+
+    /// Connects the standard output of the command to a pipe, unless it is discarded.
+    /// The standard error is connected as well if it is to be merged.
+    pub(crate) fn connect_stdout<W: OutputSink>(
+        &self,
+        cmd: &mut Command,
+        writer: W,
+    ) -> std::io::Result<()> {
+        if self.stdout != Redirect::Null {
+            if self.stderr == Redirect::Stdout {
+                cmd.stderr(writer.try_clone()?);
+            }
+            cmd.stdout(writer);
+        }
+        Ok(())
+    }
+}
+
+/// Appends arguments to the process.
+///
+/// # Examples
+/// ```
+/// # use experiment::process::Process;
+/// # use experiment::Verbosity::Verbose;
+/// let mut process = Process::new("program", &Vec::<&str>::new());
+/// process.extend((1..=3).map(|n| n.to_string()));
+/// assert_eq!(format!("{}", process.display(Verbose)), "program 1 2 3".to_string());
+/// ```
+impl Extend<String> for Process {
+    fn extend<I: IntoIterator<Item = String>>(&mut self, iter: I) {
+        self.args.extend(iter);
+    }
+}
+
+/// Creates a pipeline consisting of a single process.
+impl From<Process> for ProcessPipeline {
+    fn from(process: Process) -> Self {
+        ProcessPipeline::new(vec![process])
+    }
+}
+
+impl std::iter::FromIterator<Process> for ProcessPipeline {
+    fn from_iter<I: IntoIterator<Item = Process>>(iter: I) -> Self {
+        ProcessPipeline::new(iter)
+    }
+}
+
+/// Appends processes at the end of the pipeline.
+impl Extend<Process> for ProcessPipeline {
+    fn extend<I: IntoIterator<Item = Process>>(&mut self, iter: I) {
+        self.processes.extend(iter);
+    }
+}
+
+/// Connects two processes into a pipeline, as `a | b` would in a shell.
+///
+/// # Examples
+/// ```
+/// # use experiment::process::Process;
+/// # use experiment::Verbosity::Verbose;
+/// let decompress = Process::new("zstd", &["-d", "-c", "corpus.zst"]);
+/// let parse = Process::new("parse", &Vec::<&str>::new());
+/// let index = Process::new("index", &["--output", "idx"]);
+/// let pipeline = decompress | parse | index;
+/// assert_eq!(
+///     pipeline.display(Verbose).to_string(),
+///     "zstd -d -c corpus.zst\n\t| parse\n\t| index --output idx"
+/// );
+/// ```
+impl BitOr for Process {
+    type Output = ProcessPipeline;
+
+    fn bitor(self, rhs: Process) -> ProcessPipeline {
+        ProcessPipeline::new(vec![self, rhs])
+    }
+}
+
+/// Appends a process at the end of the pipeline.
+impl BitOr<Process> for ProcessPipeline {
+    type Output = ProcessPipeline;
+
+    fn bitor(mut self, rhs: Process) -> ProcessPipeline {
+        self.push(rhs);
+        self
+    }
+}
+
+/// Prepends a process at the beginning of the pipeline.
+impl BitOr<ProcessPipeline> for Process {
+    type Output = ProcessPipeline;
+
+    fn bitor(self, rhs: ProcessPipeline) -> ProcessPipeline {
+        let mut pipeline = ProcessPipeline::new(vec![self]);
+        pipeline.concat(rhs);
+        pipeline
+    }
+}
+
+/// Concatenates two pipelines.
+/// See [`ProcessPipeline::concat`](struct.ProcessPipeline.html#method.concat).
+impl BitOr for ProcessPipeline {
+    type Output = ProcessPipeline;
+
+    fn bitor(mut self, rhs: ProcessPipeline) -> ProcessPipeline {
+        self.concat(rhs);
+        self
+    }
+}
+
+/// Durations and exit statuses of repeated executions of a process, together with summary
+/// statistics. Created by [`Process::execute_repeated`](struct.Process.html#method.execute_repeated).
+///
+/// # Examples
+/// ```
+/// # use experiment::process::Process;
+/// let runs = Process::new("sleep", &["0.01"]).execute_repeated(3).unwrap();
+/// println!(
+///     "mean: {:?}, std dev: {:?}",
+///     runs.mean(),
+///     runs.std_dev()
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct RepeatedExecution {
+    durations: Vec<Duration>,
+    statuses: Vec<ExitStatus>,
+}
+
+impl RepeatedExecution {
+    /// Durations of the consecutive runs.
+    pub fn durations(&self) -> &[Duration] {
+        &self.durations
+    }
+
+    /// Exit statuses of the consecutive runs.
+    pub fn statuses(&self) -> &[ExitStatus] {
+        &self.statuses
+    }
+
+    /// Mean duration.
+    pub fn mean(&self) -> Duration {
+        Duration::from_secs_f64(self.mean_secs())
+    }
+
+    /// Median duration; for an even number of runs, the mean of the two middle ones.
+    pub fn median(&self) -> Duration {
+        let mut sorted = self.durations.clone();
+        sorted.sort();
+        let mid = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2
+        } else {
+            sorted[mid]
+        }
+    }
+
+    /// Shortest duration.
+    pub fn min(&self) -> Duration {
+        *self.durations.iter().min().expect("At least one run")
+    }
+
+    /// Longest duration.
+    pub fn max(&self) -> Duration {
+        *self.durations.iter().max().expect("At least one run")
+    }
+
+    /// Population standard deviation of the durations.
+    pub fn std_dev(&self) -> Duration {
+        let mean = self.mean_secs();
+        let variance = self
+            .durations
+            .iter()
+            .map(|d| (d.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / self.durations.len() as f64;
+        Duration::from_secs_f64(variance.sqrt())
+    }
+
+    fn mean_secs(&self) -> f64 {
+        self.durations
+            .iter()
+            .map(Duration::as_secs_f64)
+            .sum::<f64>()
+            / self.durations.len() as f64
+    }
+}
+
+impl<'a> fmt::Display for ProcessDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let display_count = match self.verbosity {
+            Verbose => self.process.args.len(),
+            Brief(max_args) => max_args,
+        };
+        if let Some(label) = &self.process.label {
+            write!(f, "[{}] ", label)?;
+        }
+        if let Some(dir) = &self.process.current_dir {
+            write!(f, "(cd {} && ", dir.display())?;
+        }
+        if !self.process.env.is_empty() {
+            write!(f, "env ")?;
+            for (key, value) in &self.process.env {
+                write!(f, "{}={} ", key, value)?;
+            }
+        }
+        write!(f, "{}", &self.process.program)?;
+        for arg in self.process.args.iter().take(display_count) {
+            write!(f, " {}", arg)?;
+        }
+        if self.verbosity != Verbosity::Verbose && display_count < self.process.args.len() {
+            write!(f, " ...")?;
+        }
+        if self.process.current_dir.is_some() {
+            write!(f, ")")?;
+        }
+        if self.process.stdout == Redirect::Null {
+            write!(f, " > /dev/null")?;
+        }
+        match &self.process.stderr {
+            Redirect::Null => write!(f, " 2> /dev/null")?,
+            Redirect::Stdout => write!(f, " 2>&1")?,
+            Redirect::File(path) => write!(f, " 2>> {}", path.display())?,
+            Redirect::Inherit => {}
+        }
+        Ok(())
+    }
+}
+
+/// A representation of a set of processes interacting through standard input/output.
+///
+/// A pipeline of a single process behaves like the process itself, while running an empty
+/// pipeline results in an error.
+///
+/// # Examples
+/// ```
+/// # use experiment::pipeline;
+/// # use experiment::process::{Process, ProcessPipeline};
+/// let pipeline = pipeline!(
+///     Process::new("echo", &["-e", "a\\nb\\nc"]),
+///     Process::new("grep", &["b"])
+/// );
+/// assert_eq!(
+///     std::str::from_utf8(&pipeline.pipe().unwrap().output().unwrap().stdout).unwrap(),
+///     "b\n"
+/// );
+///
+/// let single = pipeline!(Process::new("echo", &["a"]));
+/// assert_eq!(single.pipe().unwrap().output().unwrap().stdout, b"a\n".to_vec());
+/// assert_eq!(single.execute_all().unwrap().len(), 1);
+///
+/// let empty = ProcessPipeline::new(vec![]);
+/// assert!(empty.pipe().is_err());
+/// assert!(empty.execute().is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ProcessPipeline {
+    processes: Vec<Process>,
+    stdin_file: Option<PathBuf>,
+    stdout_file: Option<PathBuf>,
+    stderr_log_dir: Option<LogDir>,
+    fail_fast: bool,
+    buffering: PipeBuffering,
+}
+
+impl ProcessPipeline {
+    /// Creates a process pipeline. Typically, it is better to use [`pipeline`](../macro.pipeline.html) macro,
+    /// or to [`collect`](https://doc.rust-lang.org/std/iter/trait.Iterator.html#method.collect)
+    /// processes from an iterator.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// let pipeline = ProcessPipeline::new(vec![
+    ///     Process::new("cat", &["input"]),
+    ///     Process::new("sort", &Vec::<&str>::new()),
+    /// ]);
+    /// let filters: ProcessPipeline = ["a", "b"]
+    ///     .iter()
+    ///     .map(|pattern| Process::new("grep", &["-v", pattern]))
+    ///     .collect();
+    /// assert_eq!(filters.display(Verbose).to_string(), "grep -v a\n\t| grep -v b");
+    /// ```
+    pub fn new<I: IntoIterator<Item = Process>>(processes: I) -> ProcessPipeline {
+        ProcessPipeline {
+            processes: processes.into_iter().collect(),
+            stdin_file: None,
+            stdout_file: None,
+            stderr_log_dir: None,
+            fail_fast: false,
+            buffering: PipeBuffering::Direct,
+        }
+    }
+
+    /// Parses a pipeline from a shell command line, such as `zcat corpus.gz | parse | sort -k2`.
+    ///
+    /// The line is split into stages on unquoted `|`, and each stage into words following the
+    /// quoting rules of a POSIX shell. The redirections `< path` in the first stage,
+    /// `> path` in the last one, and `> /dev/null`, `2> /dev/null`, `2>&1`, and `2>> path` in
+    /// any stage are supported. Anything that would require a shell to interpret, such as
+    /// variables, globs, or command lists, is either rejected or passed verbatim.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::ProcessPipeline;
+    /// # use experiment::Verbosity::Verbose;
+    /// let pipeline = ProcessPipeline::from_shell(
+    ///     "zcat corpus.gz | parse --field 'title text' 2>&1 | sort -k2 > sorted.txt",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     pipeline.display(Verbose).to_string(),
+    ///     "zcat corpus.gz\n\t| parse --field title text 2>&1\n\t| sort -k2 > sorted.txt"
+    /// );
+    /// assert_eq!(
+    ///     pipeline.to_shell_string(),
+    ///     "zcat corpus.gz | parse --field 'title text' 2>&1 | sort -k2 > sorted.txt"
+    /// );
+    /// assert!(ProcessPipeline::from_shell("cat 'input").is_err());
+    /// assert!(ProcessPipeline::from_shell("cat input | ").is_err());
+    /// assert!(ProcessPipeline::from_shell("echo $HOME").is_err());
+    /// ```
+    pub fn from_shell(line: &str) -> Result<ProcessPipeline, String> {
+        let tokens = shell_words(line)?;
+        let segments: Vec<_> = tokens.split(|token| *token == Token::Pipe).collect();
+        let last = segments.len() - 1;
+        let mut pipeline = ProcessPipeline::new(Vec::new());
+        for (idx, segment) in segments.into_iter().enumerate() {
+            let mut words = Vec::new();
+            let mut redirections = Vec::new();
+            let mut tokens = segment.iter();
+            while let Some(token) = tokens.next() {
+                match token {
+                    Token::Word(word) => words.push(word.as_str()),
+                    Token::Redirect(Redirection::StderrToStdout) => {
+                        redirections.push((Redirection::StderrToStdout, ""))
+                    }
+                    Token::Redirect(redirection) => match tokens.next() {
+                        Some(Token::Word(target)) => redirections.push((*redirection, target)),
+                        _ => return Err(String::from("Missing redirection target")),
+                    },
+                    Token::Pipe => unreachable!(),
+                }
+            }
+            let (program, args) = match words.split_first() {
+                Some((program, args)) => (program, args),
+                None => return Err(format!("Empty stage in pipeline: {}", line)),
+            };
+            let mut process = Process::new(program, args);
+            for (redirection, target) in redirections {
+                match (redirection, target) {
+                    (Redirection::Stdin, path) if idx == 0 => {
+                        pipeline.stdin_file(path);
+                    }
+                    (Redirection::Stdout, "/dev/null") => {
+                        process.silence_stdout();
+                    }
+                    (Redirection::Stdout, path) if idx == last => {
+                        pipeline.stdout_file(path);
+                    }
+                    (Redirection::Stderr, "/dev/null") => {
+                        process.silence_stderr();
+                    }
+                    (Redirection::StderrToStdout, _) => {
+                        process.merge_stderr_into_stdout();
+                    }
+                    (Redirection::StderrAppend, path) => {
+                        process.stderr_to_file(path);
+                    }
+                    (Redirection::Stderr, _) => {
+                        return Err(String::from(
+                            "Redirecting stderr to a file other than /dev/null requires 2>>",
+                        ))
+                    }
+                    (_, path) => {
+                        return Err(format!("Redirection in the middle of a pipeline: {}", path))
+                    }
+                }
+            }
+            pipeline.push(process);
+        }
+        Ok(pipeline)
+    }
+
+    /// Appends a process at the end of the pipeline.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// let compressed = true;
+    /// let mut pipeline = pipeline!(Process::new("cat", &["corpus"]));
+    /// if compressed {
+    ///     pipeline.push(Process::new("zstd", &["-d"]));
+    /// }
+    /// pipeline.extend(vec![Process::new("sort", &Vec::<&str>::new())]);
+    /// assert_eq!(
+    ///     pipeline.display(Verbose).to_string(),
+    ///     "cat corpus\n\t| zstd -d\n\t| sort"
+    /// );
+    /// ```
+    pub fn push(&mut self, process: Process) -> &mut ProcessPipeline {
+        self.processes.push(process);
+        self
+    }
+
+    /// Appends all processes of another pipeline at the end of this one.
+    ///
+    /// The pipeline keeps its own input file, and takes over the output file of the other one,
+    /// if set; the remaining file redirections would fall in the middle of the pipeline and are
+    /// discarded. An empty pipeline takes over the input file of the other one as well.
+    ///
+    /// The settings of both pipelines are merged: the result [fails fast](#method.fail_fast)
+    /// if either of them does, and the [buffering](#method.buffering) and the
+    /// [stderr log directory](#method.stderr_log_dir) of the other pipeline are taken over
+    /// only if this one uses the defaults.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut pipeline = pipeline!(Process::new("cat", &["corpus"]));
+    /// let mut tail = pipeline!(Process::new("sort", &Vec::<&str>::new()));
+    /// tail.stdout_file("sorted");
+    /// pipeline.concat(tail);
+    /// assert_eq!(pipeline.display(Verbose).to_string(), "cat corpus\n\t| sort > sorted");
+    /// ```
+    ///
+    /// Settings of the other pipeline carry over:
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use std::time::{Duration, Instant};
+    /// let mut pipeline = pipeline!(Process::new("sh", &["-c", "exec 1>&-; exit 3"]));
+    /// let mut tail = pipeline!(Process::new("sleep", &["10"]));
+    /// tail.fail_fast();
+    /// pipeline.concat(tail);
+    /// let start = Instant::now();
+    /// let statuses = pipeline.execute_all().unwrap();
+    /// assert_eq!(statuses[0].code(), Some(3));
+    /// assert!(start.elapsed() < Duration::from_secs(5));
+    /// ```
+    pub fn concat(&mut self, other: ProcessPipeline) -> &mut ProcessPipeline {
+        self.fail_fast |= other.fail_fast;
+        if self.buffering == PipeBuffering::Direct {
+            self.buffering = other.buffering;
+        }
+        if self.stderr_log_dir.is_none() {
+            self.stderr_log_dir = other.stderr_log_dir;
+        }
+        if other.processes.is_empty() {
+            return self;
+        }
+        if self.processes.is_empty() {
+            self.stdin_file = other.stdin_file;
+        }
+        self.processes.extend(other.processes);
+        self.stdout_file = other.stdout_file;
+        self
+    }
+
+    /// Appends a `tee` stage, which copies the stream flowing out of the current last stage
+    /// into the file while passing it along to the stages appended later.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("pipeline").unwrap();
+    /// let sample = tmp.path().join("sample.txt");
+    /// let mut pipeline = pipeline!(Process::new("echo", &["-e", "b\\na"]));
+    /// pipeline.tee(&sample).push(Process::new("sort", &Vec::<&str>::new()));
+    /// assert_eq!(
+    ///     pipeline.display(Verbose).to_string(),
+    ///     format!("echo -e b\\na\n\t| tee {}\n\t| sort", sample.display())
+    /// );
+    /// let output = pipeline.pipe().unwrap().output().unwrap();
+    /// assert_eq!(output.stdout, b"a\nb\n".to_vec());
+    /// assert_eq!(std::fs::read_to_string(&sample).unwrap(), "b\na\n");
+    /// ```
+    pub fn tee<P: AsRef<Path>>(&mut self, path: P) -> &mut ProcessPipeline {
+        self.push(tee_process(path.as_ref()))
+    }
+
+    /// Inserts a `tee` stage right after the stage at index `stage`, which copies the stream
+    /// between it and the following stage into the file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stage` is out of bounds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("parse", &Vec::<&str>::new()),
+    ///     Process::new("index", &Vec::<&str>::new())
+    /// );
+    /// pipeline.tee_after(0, "parsed.txt");
+    /// assert_eq!(
+    ///     pipeline.display(Verbose).to_string(),
+    ///     "parse\n\t| tee parsed.txt\n\t| index"
+    /// );
+    /// ```
+    pub fn tee_after<P: AsRef<Path>>(&mut self, stage: usize, path: P) -> &mut ProcessPipeline {
+        self.try_tee_after(stage, path)
+            .expect("Stage index out of bounds")
+    }
+
+    /// Same as [`tee_after`](#method.tee_after), but fails with `InvalidInput` if `stage` is
+    /// out of bounds, in which case the pipeline is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use std::io::ErrorKind;
+    /// let mut pipeline = pipeline!(Process::new("parse", &Vec::<&str>::new()));
+    /// assert!(pipeline.try_tee_after(0, "parsed.txt").is_ok());
+    /// let err = pipeline.try_tee_after(2, "tee.txt").unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    /// assert_eq!(pipeline.processes().len(), 2);
+    /// ```
+    pub fn try_tee_after<P: AsRef<Path>>(
+        &mut self,
+        stage: usize,
+        path: P,
+    ) -> std::io::Result<&mut ProcessPipeline> {
+        if stage >= self.processes.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Stage index {} out of bounds for a pipeline of {} processes",
+                    stage,
+                    self.processes.len()
+                ),
+            ));
+        }
+        self.processes.insert(stage + 1, tee_process(path.as_ref()));
+        Ok(self)
+    }
+
+    /// Makes the execution methods kill all remaining processes as soon as any of them exits
+    /// unsuccessfully, instead of letting the downstream ones run on truncated data.
+    /// The processes that are killed report being terminated by a signal.
+    ///
+    /// This does not apply to [`pipe`](#method.pipe), whose last process is run by the caller.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use std::time::{Duration, Instant};
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("sh", &["-c", "exec 1>&-; exit 3"]),
+    ///     Process::new("sleep", &["10"])
+    /// );
+    /// pipeline.fail_fast();
+    /// let start = Instant::now();
+    /// let statuses = pipeline.execute_all().unwrap();
+    /// assert_eq!(statuses[0].code(), Some(3));
+    /// assert!(!statuses[1].success());
+    /// assert!(start.elapsed() < Duration::from_secs(5));
+    /// ```
+    pub fn fail_fast(&mut self) -> &mut ProcessPipeline {
+        self.fail_fast = true;
+        self
+    }
+
+    /// Sets how data flows between consecutive stages; see
+    /// [`PipeBuffering`](enum.PipeBuffering.html). Applies to all ways of running the pipeline
+    /// except [`pipe`](#method.pipe), which always connects the stages directly.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{PipeBuffering, Process, ProcessPipeline};
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("head", &["-c", "100000", "/dev/zero"]),
+    ///     Process::new("wc", &["-c"])
+    /// );
+    /// let output = pipeline.capture().unwrap();
+    /// assert_eq!(output.stages[0].bytes_out, None);
+    ///
+    /// pipeline.buffering(PipeBuffering::Relay(4096));
+    /// let output = pipeline.capture().unwrap();
+    /// assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "100000");
+    /// assert_eq!(output.stages[0].bytes_out, Some(100000));
+    /// assert!(output.stages[0].throughput().unwrap() > 0.0);
+    /// assert_eq!(output.stages[1].bytes_out, None);
+    /// assert!(pipeline.execute().unwrap().success());
+    /// ```
+    pub fn buffering(&mut self, buffering: PipeBuffering) -> &mut ProcessPipeline {
+        self.buffering = buffering;
+        self
+    }
+
+    /// Names of the stages: the [label](struct.Process.html#method.label) of each process if
+    /// set, and otherwise its position in the pipeline followed by the program name.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let mut parse = Process::new("parse", &Vec::<&str>::new());
+    /// parse.label("parse-corpus");
+    /// let pipeline = pipeline!(Process::new("/usr/bin/zstd", &["-d"]), parse);
+    /// assert_eq!(pipeline.stage_names(), vec!["0-zstd", "parse-corpus"]);
+    /// ```
+    pub fn stage_names(&self) -> Vec<String> {
+        self.processes
+            .iter()
+            .enumerate()
+            .map(|(idx, process)| match process.get_label() {
+                Some(label) => label.to_string(),
+                None => {
+                    let program = Path::new(&process.program).file_name().map_or_else(
+                        || process.program.clone(),
+                        |name| name.to_string_lossy().into_owned(),
+                    );
+                    format!("{}-{}", idx, program)
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the standard error of each stage to `<dir>/<stage-name>.stderr.log`, where the
+    /// names are given by [`stage_names`](#method.stage_names). The directory is created
+    /// when the pipeline first starts, and treated according to `policy` if it already
+    /// exists; later executions overwrite the logs. Stages whose standard error is silenced, merged, or redirected otherwise are not logged.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("pipeline").unwrap();
+    /// let mut filter = Process::new("sh", &["-c", "echo filtering >&2; cat"]);
+    /// filter.label("filter");
+    /// let mut pipeline = pipeline!(Process::new("echo", &["a"]), filter);
+    /// pipeline.log_stderr_to(tmp.path().join("logs"), OverwritePolicy::Fail);
+    /// assert!(pipeline.execute().unwrap().success());
+    /// let log = tmp.path().join("logs").join("filter.stderr.log");
+    /// assert_eq!(std::fs::read_to_string(log).unwrap(), "filtering\n");
+    /// assert!(tmp.path().join("logs").join("0-echo.stderr.log").exists());
+    /// ```
+    pub fn log_stderr_to<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        policy: OverwritePolicy,
+    ) -> &mut ProcessPipeline {
+        self.stderr_log_dir = Some(LogDir::new(dir.as_ref().to_path_buf(), policy));
+        self
+    }
+
+    /// Makes the first process read its standard input from the file, as `< path` would.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("pipeline").unwrap();
+    /// let input = tmp.path().join("input.txt");
+    /// let output = tmp.path().join("output.txt");
+    /// std::fs::write(&input, "a\nb\nc\n").unwrap();
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("grep", &["-v", "b"]),
+    ///     Process::new("wc", &["-l"])
+    /// );
+    /// pipeline.stdin_file(&input).stdout_file(&output);
+    /// assert_eq!(
+    ///     pipeline.display(Verbose).to_string(),
+    ///     format!("grep -v b < {}\n\t| wc -l > {}", input.display(), output.display())
+    /// );
+    /// assert!(pipeline.execute().unwrap().success());
+    /// assert_eq!(std::fs::read_to_string(&output).unwrap().trim(), "2");
+    /// ```
+    pub fn stdin_file<P: AsRef<Path>>(&mut self, path: P) -> &mut ProcessPipeline {
+        self.stdin_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Makes the last process write its standard output to the file, as `> path` would.
+    /// The file is created when the pipeline starts, or truncated if it exists.
+    pub fn stdout_file<P: AsRef<Path>>(&mut self, path: P) -> &mut ProcessPipeline {
+        self.stdout_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the execution context of all processes in the pipeline.
+    /// See [`ExecutionContext`](../context/struct.ExecutionContext.html) for details.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::context::ExecutionContext;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("printenv", &["EXPERIMENT_STAGE"]),
+    ///     Process::new("cat", &Vec::<&str>::new())
+    /// );
+    /// let mut context = ExecutionContext::new("run-0001");
+    /// context.stage("count");
+    /// pipeline.context(&context);
+    /// assert_eq!(
+    ///     std::str::from_utf8(&pipeline.pipe().unwrap().output().unwrap().stdout).unwrap(),
+    ///     "count\n"
+    /// );
+    /// ```
+    pub fn context(&mut self, context: &ExecutionContext) -> &mut ProcessPipeline {
+        for process in &mut self.processes {
+            process.context(context);
+        }
+        self
+    }
+
+    /// Sets an environment variable for all processes.
+    /// See [`Process::env`](struct.Process.html#method.env).
+    pub fn env(&mut self, key: &str, value: &str) -> &mut ProcessPipeline {
+        for process in &mut self.processes {
+            process.env(key, value);
+        }
+        self
+    }
+
+    /// Sets the working directory for all processes.
+    /// See [`Process::current_dir`](struct.Process.html#method.current_dir).
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut ProcessPipeline {
+        for process in &mut self.processes {
+            process.current_dir(dir.as_ref());
+        }
+        self
+    }
+
+    /// Returns a copy of the pipeline with `{name}` placeholders replaced in all processes,
+    /// as well as in the paths of its input and output files and the directory of its
+    /// standard error logs. See [`Process::interpolate`](struct.Process.html#method.interpolate).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::ProcessPipeline;
+    /// let pipeline = ProcessPipeline::from_shell("zcat {input} | parse --k {k} > {run_dir}/out")
+    ///     .unwrap();
+    /// let lookup = |name: &str| match name {
+    ///     "input" => Some("topics.gz"),
+    ///     "k" => Some("10"),
+    ///     "run_dir" => Some("results"),
+    ///     _ => None,
+    /// };
+    /// assert_eq!(
+    ///     pipeline.interpolate(lookup).unwrap().to_shell_string(),
+    ///     "zcat topics.gz | parse --k 10 > results/out"
+    /// );
+    /// ```
+    pub fn interpolate<'a, F>(&self, lookup: F) -> std::io::Result<ProcessPipeline>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let interpolated = || -> Result<ProcessPipeline, String> {
+            let mut pipeline = self.clone();
+            for process in &mut pipeline.processes {
+                *process = process.interpolated(&lookup)?;
+            }
+            for path in pipeline
+                .stdin_file
+                .iter_mut()
+                .chain(pipeline.stdout_file.iter_mut())
+            {
+                *path = interpolate_path(path, &lookup)?;
+            }
+            if let Some(dir) = &mut pipeline.stderr_log_dir {
+                *dir = LogDir::new(interpolate_path(&dir.path, &lookup)?, dir.policy);
+            }
+            Ok(pipeline)
+        };
+        interpolated().map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+    }
+
+    /// Returns a hash of the [fingerprints](struct.Process.html#method.fingerprint) of the
+    /// stages in order, their output redirections, and the files the pipeline reads from and
+    /// writes to. Like the fingerprints of processes, it is stable across runs.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let sort = || Process::new("sort", &Vec::<&str>::new());
+    /// let uniq = || Process::new("uniq", &Vec::<&str>::new());
+    /// let mut pipeline = pipeline!(sort(), uniq());
+    /// assert_eq!(pipeline.fingerprint(), pipeline!(sort(), uniq()).fingerprint());
+    /// assert_ne!(pipeline.fingerprint(), pipeline!(uniq(), sort()).fingerprint());
+    /// let before = pipeline.fingerprint();
+    /// pipeline.stdout_file("sorted.txt");
+    /// assert_ne!(pipeline.fingerprint(), before);
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        let write_path = |hasher: &mut StableHasher, path: Option<&Path>| match path {
+            Some(path) => {
+                hasher.write_u8(1);
+                hasher.write_str(&path.to_string_lossy());
+            }
+            None => hasher.write_u8(0),
+        };
+        hasher.write_usize(self.processes.len());
+        for process in &self.processes {
+            hasher.write_u64(process.fingerprint());
+            hasher.write_u8((process.stdout == Redirect::Null) as u8);
+            match &process.stderr {
+                Redirect::Inherit => hasher.write_u8(0),
+                Redirect::Null => hasher.write_u8(1),
+                Redirect::Stdout => hasher.write_u8(2),
+                Redirect::File(path) => {
+                    hasher.write_u8(3);
+                    hasher.write_str(&path.to_string_lossy());
+                }
+            }
+        }
+        write_path(&mut hasher, self.stdin_file.as_deref());
+        write_path(&mut hasher, self.stdout_file.as_deref());
+        hasher.finish()
+    }
+
+    /// Renders the pipeline as a single `a | b | c` line that can be pasted into a POSIX shell.
+    /// See [`Process::to_shell_string`](struct.Process.html#method.to_shell_string).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("grep", &["a b"]),
+    ///     Process::new("sort", &["-u"])
+    /// );
+    /// pipeline.stdin_file("in.txt").stdout_file("out dir/out.txt");
+    /// assert_eq!(
+    ///     pipeline.to_shell_string(),
+    ///     "grep 'a b' < in.txt | sort -u > 'out dir/out.txt'"
+    /// );
+    /// ```
+    pub fn to_shell_string(&self) -> String {
+        self.shell_line(Dialect::Posix)
+    }
+
+    /// Renders the pipeline as a single line for the Windows command interpreter, `cmd.exe`.
+    /// See [`Process::to_cmd_string`](struct.Process.html#method.to_cmd_string).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("findstr", &["a b"]),
+    ///     Process::new("sort", &Vec::<&str>::new())
+    /// );
+    /// pipeline.stdin_file("in.txt").stdout_file("out dir\\out.txt");
+    /// assert_eq!(
+    ///     pipeline.to_cmd_string(),
+    ///     r#"findstr "a b" < in.txt | sort > "out dir\out.txt""#
+    /// );
+    /// ```
+    pub fn to_cmd_string(&self) -> String {
+        self.shell_line(Dialect::Cmd)
+    }
+
+    fn shell_line(&self, dialect: Dialect) -> String {
+        let last = self.processes.len().saturating_sub(1);
+        let mut stages = Vec::with_capacity(self.processes.len());
+        for (idx, process) in self.processes.iter().enumerate() {
+            let mut stage = process.shell_line(dialect);
+            match &self.stdin_file {
+                Some(path) if idx == 0 => {
+                    stage.push_str(" < ");
+                    stage.push_str(&dialect.quote(&path.to_string_lossy()));
+                }
+                _ => {}
+            }
+            match &self.stdout_file {
+                Some(path) if idx == last => {
+                    stage.push_str(" > ");
+                    stage.push_str(&dialect.quote(&path.to_string_lossy()));
+                }
+                _ => {}
+            }
+            stages.push(stage);
+        }
+        stages.join(" | ")
+    }
+
+    /// Creates a [`PipelineDisplay`](PipelineDisplay.t.html) object with the desired verbosity.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::{Verbose, Brief};
+    /// let pipeline = pipeline!(
+    ///     Process::new("echo", &["-e", "a\\nb\\nc"]),
+    ///     Process::new("grep", &["b"])
+    /// );
+    /// assert_eq!(
+    ///     format!("{}", pipeline.display(Verbose)),
+    ///     "echo -e a\\nb\\nc\n\t| grep b".to_string()
+    /// );
+    /// assert_eq!(
+    ///     format!("{}", pipeline.display(Brief(2))),
+    ///     "echo -e a\\nb\\nc\n\t| grep b".to_string()
+    /// );
+    /// assert_eq!(
+    ///     format!("{}", pipeline.display(Brief(1))),
+    ///     "echo -e ...\n\t| grep b".to_string()
+    /// );
+    /// ```
+    ///
+    /// The environment and working directory of each stage are shown where set:
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::Verbosity::Verbose;
+    /// let mut pwd = Process::new("sh", &["-c", "pwd; echo $STAGE"]);
+    /// pwd.current_dir("/").env("STAGE", "first");
+    /// let mut grep = Process::new("sh", &["-c", "cat; echo $STAGE"]);
+    /// grep.env("STAGE", "second");
+    /// let pipeline = pipeline!(pwd, grep);
+    /// assert_eq!(
+    ///     pipeline.display(Verbose).to_string(),
+    ///     "(cd / && env STAGE=first sh -c pwd; echo $STAGE)\n\t| env STAGE=second sh -c cat; echo $STAGE"
+    /// );
+    /// let output = pipeline.pipe().unwrap().output().unwrap();
+    /// assert_eq!(output.stdout, b"/\nfirst\nsecond\n".to_vec());
+    /// ```
+    pub fn display(&self, verbosity: Verbosity) -> PipelineDisplay<'_> {
+        PipelineDisplay {
+            pipeline: self,
+            verbosity,
+        }
+    }
+
+    /// Generates a pipeline of
+    /// [`Command`](https://doc.rust-lang.org/std/process/struct.Command.html)s and returns the last
+    /// one.
+    ///
+    /// All but the last command are spawned immediately. Their handles are handed over to a
+    /// background thread that waits on them, so they do not linger as zombie processes once they
+    /// finish. Use [`spawn`](#method.spawn) to keep control over all stages.
+    ///
+    /// Fails if the pipeline is empty, or if any of the processes but the last fails to spawn,
+    /// in which case the error identifies the stage, and the processes that have already
+    /// started are killed and reaped.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let pipeline = pipeline!(
+    ///     Process::new("echo", &["-e", "a\\nb\\nc"]),
+    ///     Process::new("grep", &["b"])
+    /// );
+    /// assert_eq!(
+    ///     std::str::from_utf8(&pipeline.pipe().unwrap().output().unwrap().stdout).unwrap(),
+    ///     "b\n"
+    /// );
+    ///
+    /// let pipeline = pipeline!(
+    ///     Process::new("echo", &["a"]),
+    ///     Process::new("unknown_process", &Vec::<&str>::new()),
+    ///     Process::new("cat", &Vec::<&str>::new())
+    /// );
+    /// let err = pipeline.pipe().unwrap_err();
+    /// assert!(err.to_string().starts_with("Stage 1 (unknown_process) failed to start"));
+    /// ```
+    pub fn pipe(&self) -> std::io::Result<Command> {
+        let mut cmds = self.connected_commands()?;
+        let last = cmds.pop().expect("At least one command");
+        let handle = spawn_all(self.describe_stages(cmds))?;
+        if !handle.children.is_empty() {
+            std::thread::spawn(move || drop(handle));
+        }
+        Ok(last)
+    }
+
+    /// Spawns all processes of the pipeline and returns a handle to all of them.
+    ///
+    /// If any of the processes fails to spawn, the ones that have already started are killed
+    /// and reaped before the error is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let pipeline = pipeline!(
+    ///     Process::new("echo", &["-e", "a\\nb\\nc"]),
+    ///     Process::new("grep", &["b"])
+    /// );
+    /// let mut child = pipeline.spawn().expect("Failed to spawn");
+    /// assert!(child.wait().unwrap().success());
+    ///
+    /// let pipeline = pipeline!(
+    ///     Process::new("echo", &["a"]),
+    ///     Process::new("unknown_process", &Vec::<&str>::new())
+    /// );
+    /// assert!(pipeline.spawn().is_err());
+    /// ```
+    pub fn spawn(&self) -> std::io::Result<PipelineChild> {
+        let mut child = match self.buffering {
+            PipeBuffering::Direct => spawn_all(self.describe_stages(self.connected_commands()?))?,
+            PipeBuffering::Relay(window) => {
+                let (cmds, links) = self.wired_commands(true, true)?;
+                let child = spawn_all(self.describe_stages(cmds))?;
+                // The relays finish on their own once either of the stages they connect exits.
+                drop(spawn_relays(links, window, None));
+                child
+            }
+        };
+        child.set_fail_fast(self.fail_fast);
+        Ok(child)
+    }
+
+    /// Executes the entire pipeline and collects the standard error of each stage separately,
+    /// subject to the [`capture_limit`](struct.Process.html#method.capture_limit) of its
+    /// process. The standard output of the last stage is not captured.
+    ///
+    /// Stages whose standard error is silenced, merged, or redirected to a file are not
+    /// captured, and the logs set up with [`log_stderr_to`](#method.log_stderr_to) are not
+    /// written.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let mut filter = Process::new("sh", &["-c", "echo warning >&2; cat"]);
+    /// filter.label("filter");
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("sh", &["-c", "echo a; echo starting >&2"]),
+    ///     filter,
+    ///     Process::new("wc", &["-l"])
+    /// );
+    /// pipeline.stdout_file("/dev/null");
+    /// let output = pipeline.capture_stderr().unwrap();
+    /// assert_eq!(output.stages[0].stderr, b"starting\n".to_vec());
+    /// assert_eq!(output.stage("filter").unwrap().stderr, b"warning\n".to_vec());
+    /// assert!(output.stages[2].stderr.is_empty());
+    /// assert!(output.stages.iter().all(|stage| stage.status.success()));
+    /// ```
+    ///
+    /// Logs written by an earlier [`execute`](#method.execute) are left intact:
+    /// ```
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("logs").unwrap();
+    /// let mut pipeline = ProcessPipeline::new(vec![Process::new("sh", &["-c", "echo err >&2"])]);
+    /// pipeline.log_stderr_to(tmp.path().join("logs"), OverwritePolicy::Fail);
+    /// pipeline.execute().unwrap();
+    /// let log = tmp.path().join("logs").join("0-sh.stderr.log");
+    /// assert_eq!(std::fs::read_to_string(&log).unwrap(), "err\n");
+    /// assert_eq!(pipeline.capture().unwrap().stages[0].stderr, b"err\n".to_vec());
+    /// assert_eq!(std::fs::read_to_string(&log).unwrap(), "err\n");
+    /// ```
+    pub fn capture_stderr(&self) -> std::io::Result<PipelineOutput> {
+        self.run_captured(false)
+    }
+
+    /// Executes the entire pipeline and collects the standard output of the last stage,
+    /// together with the exit status, duration, and standard error of each stage.
+    /// See [`capture_stderr`](#method.capture_stderr) for the details of capturing errors.
+    ///
+    /// The standard output is subject to the
+    /// [`capture_limit`](struct.Process.html#method.capture_limit) of the last process, and is
+    /// not captured if it is silenced or redirected to a file with
+    /// [`stdout_file`](#method.stdout_file). Durations are measured with a resolution of about
+    /// 10 milliseconds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use std::time::Duration;
+    /// let pipeline = pipeline!(
+    ///     Process::new("sh", &["-c", "echo b; echo a; echo sorting >&2"]),
+    ///     Process::new("sort", &Vec::<&str>::new())
+    /// );
+    /// let output = pipeline.capture().unwrap();
+    /// assert!(output.success());
+    /// assert_eq!(output.stdout, b"a\nb\n".to_vec());
+    /// assert_eq!(output.stages[0].stderr, b"sorting\n".to_vec());
+    /// assert!(output.stages[1].duration < Duration::from_secs(5));
+    /// ```
+    pub fn capture(&self) -> std::io::Result<PipelineOutput> {
+        self.run_captured(true)
+    }
+
+    /// Places all processes of the pipeline in the control group.
+    /// See [`Process::cgroup`](struct.Process.html#method.cgroup).
+    #[cfg(all(target_os = "linux", feature = "services"))]
+    pub fn cgroup(&mut self, group: &crate::cgroup::Cgroup) -> &mut ProcessPipeline {
+        for process in &mut self.processes {
+            process.cgroup(group);
+        }
+        self
+    }
+
+    /// Resolves executables of all processes, failing on the first missing one.
+    /// See [`Process::resolve`](struct.Process.html#method.resolve).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let pipeline = pipeline!(
+    ///     Process::new("echo", &["a"]),
+    ///     Process::new("unknown_process", &Vec::<&str>::new())
+    /// );
+    /// assert!(pipeline.resolve().is_err());
+    /// ```
+    pub fn resolve(&self) -> std::io::Result<Vec<PathBuf>> {
+        self.processes.iter().map(Process::resolve).collect()
+    }
+
+    /// Returns the processes of the pipeline, in order.
+    pub fn processes(&self) -> &[Process] {
+        &self.processes
+    }
+
+    /// Executes the entire pipeline disregarding the output.
+    ///
+    /// All processes are waited on, and the exit status of the last one is returned.
+    pub fn execute(&self) -> std::io::Result<ExitStatus> {
+        self.spawn()?.wait()
+    }
+
+    /// Executes the entire pipeline and reports its progress to `on_event`: when each stage
+    /// starts and finishes, and how many bytes have flowed between stages. Returns the exit
+    /// statuses of all processes, in order.
+    ///
+    /// To count the bytes, the data between stages is relayed through the current process
+    /// rather than flowing directly through OS pipes, in chunks of the window size set with
+    /// [`buffering`](#method.buffering), or 64KiB by default. Transfers are reported at most
+    /// every 100 milliseconds per stage, and once more when the stage closes its output.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{PipelineEvent, Process, ProcessPipeline};
+    /// let mut pipeline = pipeline!(
+    ///     Process::new("head", &["-c", "100000", "/dev/zero"]),
+    ///     Process::new("wc", &["-c"])
+    /// );
+    /// pipeline.stdout_file("/dev/null");
+    /// let mut events = Vec::new();
+    /// let statuses = pipeline
+    ///     .execute_with_progress(|event| events.push(event.clone()))
+    ///     .unwrap();
+    /// assert!(statuses.iter().all(|status| status.success()));
+    /// assert!(matches!(events[0], PipelineEvent::Started { stage: 0, .. }));
+    /// let transferred = events.iter().filter_map(|event| match event {
+    ///     PipelineEvent::Transferred { stage: 0, bytes } => Some(*bytes),
+    ///     _ => None,
+    /// });
+    /// assert_eq!(transferred.max(), Some(100000));
+    /// assert!(matches!(events.last(), Some(PipelineEvent::Finished { .. })));
+    /// ```
+    pub fn execute_with_progress<F>(&self, mut on_event: F) -> std::io::Result<Vec<ExitStatus>>
+    where
+        F: FnMut(&PipelineEvent),
+    {
+        let (cmds, links) = self.wired_commands(true, true)?;
+        let mut child = spawn_all(self.describe_stages(cmds))?;
+        child
+            .set_reap_policy(ReapPolicy::Kill)
+            .set_fail_fast(self.fail_fast);
+        for (stage, process) in child.children.iter().enumerate() {
+            on_event(&PipelineEvent::Started {
+                stage,
+                pid: process.id(),
+            });
+        }
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let window = match self.buffering {
+            PipeBuffering::Direct => RELAY_BUFFER_SIZE,
+            PipeBuffering::Relay(window) => window,
+        };
+        let relays = spawn_relays(links, window, Some(sender));
+        let mut statuses = vec![None; child.children.len()];
+        let mut relaying = true;
+        while relaying || statuses.iter().any(Option::is_none) {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(event) => on_event(&event),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => relaying = false,
+            }
+            let mut finished = Vec::new();
+            for (stage, process) in child.children.iter_mut().enumerate() {
+                if statuses[stage].is_none() {
+                    if let Some(status) = process.try_wait()? {
+                        statuses[stage] = Some(status);
+                        finished.push((stage, status));
+                    }
+                }
+            }
+            // Transfers that led to the processes finishing are reported first.
+            while let Ok(event) = receiver.try_recv() {
+                on_event(&event);
+            }
+            for &(stage, status) in &finished {
+                on_event(&PipelineEvent::Finished { stage, status });
+            }
+            if child.fail_fast && finished.iter().any(|(_, status)| !status.success()) {
+                child.kill()?;
+            }
+        }
+        for relay in relays {
+            relay
+                .join()
+                .map_err(|_| std::io::Error::other("Relay thread panicked"))??;
+        }
+        Ok(statuses.into_iter().flatten().collect())
+    }
+
+    /// Executes the entire pipeline like [`execute`](#method.execute), but kills all of its
+    /// processes and fails with `TimedOut` if they do not finish within `timeout`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// # use std::time::{Duration, Instant};
+    /// let pipeline = pipeline!(
+    ///     Process::new("echo", &["a"]),
+    ///     Process::new("sleep", &["10"]),
+    ///     Process::new("cat", &Vec::<&str>::new())
+    /// );
+    /// let start = Instant::now();
+    /// let err = pipeline.execute_with_timeout(Duration::from_millis(100)).unwrap_err();
+    /// assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    /// assert!(start.elapsed() < Duration::from_secs(5));
+    /// ```
+    pub fn execute_with_timeout(&self, timeout: Duration) -> std::io::Result<ExitStatus> {
+        let mut child = self.spawn()?;
+        child.set_reap_policy(ReapPolicy::Kill);
+        if let Some(mut statuses) = child.wait_timeout(timeout)? {
+            return statuses
+                .pop()
+                .ok_or_else(|| std::io::Error::other("Empty pipeline"));
+        }
+        let mut running = Vec::new();
+        for (child, name) in child.children.iter_mut().zip(self.stage_names()) {
+            if child.try_wait()?.is_none() {
+                running.push(name);
+            }
+        }
+        child.kill()?;
+        child.wait_all()?;
+        Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!(
+                "Pipeline timed out after {:?}; killed stages: {}",
+                timeout,
+                running.join(", ")
+            ),
+        ))
+    }
+
+    /// Executes the entire pipeline and returns exit statuses of all processes, in order.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let pipeline = pipeline!(
+    ///     Process::new("false", &Vec::<&str>::new()),
+    ///     Process::new("cat", &Vec::<&str>::new())
+    /// );
+    /// let statuses = pipeline.execute_all().unwrap();
+    /// assert!(!statuses[0].success());
+    /// assert!(statuses[1].success());
+    /// ```
+    pub fn execute_all(&self) -> std::io::Result<Vec<ExitStatus>> {
+        self.spawn()?.wait_all()
+    }
+
+    /// Executes the entire pipeline like `set -o pipefail` would: returns an error if any of
+    /// the processes fails, not only the last one, and all exit statuses otherwise.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::pipeline;
+    /// # use experiment::process::{Process, ProcessPipeline};
+    /// let pipeline = pipeline!(
+    ///     Process::new("false", &Vec::<&str>::new()),
+    ///     Process::new("cat", &Vec::<&str>::new())
+    /// );
+    /// assert!(pipeline.execute().unwrap().success());
+    /// assert!(pipeline.pipefail().is_err());
+    /// ```
+    pub fn pipefail(&self) -> std::io::Result<Vec<ExitStatus>> {
+        self.check_statuses(self.execute_all()?)
+    }
+
+    /// Executes the pipeline like [`pipefail`](#method.pipefail), but
+    /// [terminates](struct.PipelineChild.html#method.terminate) it once the deadline passes,
+    /// which fails with `TimedOut`.
+    #[cfg(feature = "experiment")]
+    pub(crate) fn pipefail_until(
+        &self,
+        deadline: Instant,
+        grace: Duration,
+    ) -> std::io::Result<Vec<ExitStatus>> {
+        let mut child = self.spawn()?;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match child.wait_timeout(timeout)? {
+            Some(statuses) => self.check_statuses(statuses),
+            None => {
+                child.terminate(grace)?;
+                Err(timed_out())
+            }
+        }
+    }
+
+    /// Fails if any of the processes failed.
+    fn check_statuses(&self, statuses: Vec<ExitStatus>) -> std::io::Result<Vec<ExitStatus>> {
+        if let Some((idx, status)) = statuses
+            .iter()
+            .enumerate()
+            .find(|(_, status)| !status.success())
+        {
+            return Err(std::io::Error::other(format!(
+                "{} failed: {}",
+                self.describe_stage(idx),
+                status
+            )));
+        }
+        Ok(statuses)
+    }
+
+    /// Executes the pipeline with the standard error of each stage, and optionally the standard
+    /// output of the last one, captured.
+    fn run_captured(&self, capture_stdout: bool) -> std::io::Result<PipelineOutput> {
+        let (mut cmds, links) = match self.buffering {
+            PipeBuffering::Direct => self.wired_commands(false, false)?,
+            PipeBuffering::Relay(_) => self.wired_commands(true, false)?,
+        };
+        let mut readers = Vec::with_capacity(cmds.len());
+        for (process, cmd) in self.processes.iter().zip(&mut cmds) {
+            if process.stderr == Redirect::Inherit {
+                let (reader, writer) = pipe()?;
+                cmd.stderr(writer);
+                readers.push(Some((reader, process.capture_limit)));
+            } else {
+                readers.push(None);
+            }
+        }
+        let mut stdout = None;
+        if let (Some(process), Some(cmd)) = (self.processes.last(), cmds.last_mut()) {
+            if capture_stdout && self.stdout_file.is_none() && process.stdout != Redirect::Null {
+                let (reader, writer) = pipe()?;
+                process.connect_stdout(cmd, writer)?;
+                stdout = Some((reader, process.capture_limit));
+            }
+        }
+        let start = Instant::now();
+        let mut child = spawn_all(self.describe_stages(cmds))?;
+        child
+            .set_reap_policy(ReapPolicy::Kill)
+            .set_fail_fast(self.fail_fast);
+        let read = |(reader, limit): (PipeReader, Option<(usize, OverflowPolicy)>)| {
+            std::thread::spawn(move || read_limited(reader, limit))
+        };
+        let join = |reader: Option<std::thread::JoinHandle<std::io::Result<Vec<u8>>>>| match reader
+        {
+            Some(reader) => reader
+                .join()
+                .map_err(|_| std::io::Error::other("Reading thread panicked"))?,
+            None => Ok(Vec::new()),
+        };
+        let stdout = stdout.map(read);
+        let readers: Vec<_> = readers.into_iter().map(|reader| reader.map(read)).collect();
+        let window = match self.buffering {
+            PipeBuffering::Direct => RELAY_BUFFER_SIZE,
+            PipeBuffering::Relay(window) => window,
+        };
+        let relays = spawn_relays(links, window, None);
+        let finished = child.poll(None)?.expect("Finished without deadline");
+        let relays = relays
+            .into_iter()
+            .map(|relay| {
+                relay
+                    .join()
+                    .map_err(|_| std::io::Error::other("Relay thread panicked"))?
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut stages = Vec::with_capacity(finished.len());
+        for (idx, (((status, end), reader), name)) in finished
+            .into_iter()
+            .zip(readers)
+            .zip(self.stage_names())
+            .enumerate()
+        {
+            let input = idx.checked_sub(1).and_then(|prev| relays.get(prev));
+            stages.push(StageOutput {
+                name,
+                status,
+                duration: end.duration_since(start),
+                first_input: input
+                    .and_then(|relayed| relayed.first_byte)
+                    .map(|first_byte| first_byte.saturating_duration_since(start)),
+                stderr: join(reader)?,
+                bytes_out: relays.get(idx).map(|relayed| relayed.bytes),
+            });
+        }
+        Ok(PipelineOutput {
+            stdout: join(stdout)?,
+            stages,
+        })
+    }
+
+    /// Generates the commands of all processes, connected with pipes and redirected to and
+    /// from the files at the ends of the pipeline. Fails if the pipeline is empty.
+    fn connected_commands(&self) -> std::io::Result<Vec<Command>> {
+        Ok(self.wired_commands(false, true)?.0)
+    }
+
+    /// Same as [`connected_commands`](#method.connected_commands), but if `relayed` is set, the
+    /// stages are not connected directly; instead, the pipes that have to be relayed between
+    /// consecutive stages are returned along with the commands. Unless `log_stderr` is set,
+    /// the logs set up with [`log_stderr_to`](#method.log_stderr_to) are neither created nor
+    /// truncated.
+    #[allow(clippy::type_complexity)]
+    fn wired_commands(
+        &self,
+        relayed: bool,
+        log_stderr: bool,
+    ) -> std::io::Result<(Vec<Command>, Vec<(PipeReader, PipeWriter)>)> {
+        let open_failed = |path: &Path, err: std::io::Error| {
+            std::io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+        };
+        if self.processes.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Pipeline has no processes",
+            ));
+        }
+        let mut cmds = self
+            .processes
+            .iter()
+            .map(Process::try_command)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut links = Vec::new();
+        let pipe = |idx: usize| {
+            pipe().map_err(|err| {
+                std::io::Error::new(
+                    err.kind(),
+                    format!("Failed to open a pipe before stage {}: {}", idx, err),
+                )
+            })
+        };
+        for idx in 1..cmds.len() {
+            let (reader, writer) = pipe(idx)?;
+            self.processes[idx - 1].connect_stdout(&mut cmds[idx - 1], writer)?;
+            if relayed {
+                let (next_reader, next_writer) = pipe(idx)?;
+                cmds[idx].stdin(next_reader);
+                links.push((reader, next_writer));
+            } else {
+                cmds[idx].stdin(reader);
+            }
+        }
+        if let (Some(dir), true) = (&self.stderr_log_dir, log_stderr) {
+            dir.create()?;
+            for ((process, cmd), name) in
+                self.processes.iter().zip(&mut cmds).zip(self.stage_names())
+            {
+                if process.stderr == Redirect::Inherit {
+                    let path = dir.path.join(format!("{}.stderr.log", name));
+                    cmd.stderr(File::create(&path).map_err(|err| open_failed(&path, err))?);
+                }
+            }
+        }
+        if let (Some(path), Some(cmd)) = (&self.stdin_file, cmds.first_mut()) {
+            cmd.stdin(File::open(path).map_err(|err| open_failed(path, err))?);
+        }
+        if let (Some(path), Some(cmd), Some(process)) =
+            (&self.stdout_file, cmds.last_mut(), self.processes.last())
+        {
+            process.connect_stdout(
+                cmd,
+                File::create(path).map_err(|err| open_failed(path, err))?,
+            )?;
+        }
+        Ok((cmds, links))
+    }
+
+    /// Identifies the stage in error messages.
+    fn describe_stage(&self, idx: usize) -> String {
+        format!("Stage {} ({})", idx, self.processes[idx].display(Brief(3)))
+    }
+
+    /// Pairs the commands with the descriptions of their stages.
+    fn describe_stages(&self, cmds: Vec<Command>) -> Vec<(String, Command)> {
+        (0..cmds.len())
+            .map(|idx| self.describe_stage(idx))
+            .zip(cmds)
+            .collect()
+    }
 }
 
-/// A [`Process`](Process.t.html) wrapper implementing `fmt::Display` trait.
-/// This indirection is created in order to explicitly set verbosity.
-///
-/// This object is created with [`Process::display`](struct.Process.html#method.display) method.
-pub struct ProcessDisplay<'a> {
-    process: &'a Process,
-    verbosity: Verbosity,
+/// A process copying its standard input to both the file and its standard output.
+fn tee_process(path: &Path) -> Process {
+    Process::new("tee", [path])
 }
 
-impl Process {
-    /// Creates a new [`Process`](Process.t.html).
-    ///
-    /// # Examples
-    /// ```
-    /// # use experiment::process::Process;
-    /// let arguments = ["a", "b", "c"];
-    /// let process = Process::new("program_name", &arguments);
-    /// ```
-    pub fn new<I, S>(program: &str, args: I) -> Process
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<std::ffi::OsStr>,
-    {
-        Process {
-            program: String::from(program),
-            args: args
-                .into_iter()
-                .map(|s| String::from(s.as_ref().to_str().expect("Invalid Unicode")))
-                .collect(),
+/// Spawns all commands, killing and reaping the ones that have started if any of them fails,
+/// in which case the error is prefixed with the description of the command.
+/// The commands are dropped afterwards, closing the pipe ends they hold.
+pub(crate) fn spawn_all(cmds: Vec<(String, Command)>) -> std::io::Result<PipelineChild> {
+    let mut handle = PipelineChild {
+        children: Vec::with_capacity(cmds.len()),
+        policy: ReapPolicy::Kill,
+        fail_fast: false,
+    };
+    for (description, mut cmd) in cmds {
+        let child = cmd.spawn().map_err(|err| {
+            std::io::Error::new(
+                err.kind(),
+                format!("{} failed to start: {}", description, err),
+            )
+        })?;
+        handle.children.push(child);
+    }
+    handle.policy = ReapPolicy::Wait;
+    Ok(handle)
+}
+
+/// Starts a thread relaying data for each pair of connected stages, in order.
+#[allow(clippy::type_complexity)]
+fn spawn_relays(
+    links: Vec<(PipeReader, PipeWriter)>,
+    window: usize,
+    sender: Option<std::sync::mpsc::Sender<PipelineEvent>>,
+) -> Vec<std::thread::JoinHandle<std::io::Result<Relayed>>> {
+    links
+        .into_iter()
+        .enumerate()
+        .map(|(stage, (reader, writer))| {
+            let sender = sender.clone();
+            std::thread::spawn(move || relay_counted(stage, reader, writer, window, sender))
+        })
+        .collect()
+}
+
+/// Copies data from the output of a stage to the input of the next one in chunks of at most
+/// `window` bytes, reports the number of bytes copied to `sender`, and returns the total.
+/// Stops early if the next stage exits.
+fn relay_counted(
+    stage: usize,
+    mut reader: PipeReader,
+    mut writer: PipeWriter,
+    window: usize,
+    sender: Option<std::sync::mpsc::Sender<PipelineEvent>>,
+) -> std::io::Result<Relayed> {
+    let report = |bytes| {
+        if let Some(sender) = &sender {
+            let _ = sender.send(PipelineEvent::Transferred { stage, bytes });
+        }
+    };
+    let mut buffer = vec![0; window.max(1)];
+    let mut bytes = 0;
+    let mut first_byte = None;
+    let mut last_report = Instant::now();
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        first_byte.get_or_insert_with(Instant::now);
+        match writer.write_all(&buffer[..len]) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(err) => return Err(err),
+        }
+        bytes += len as u64;
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            report(bytes);
+            last_report = Instant::now();
         }
     }
+    report(bytes);
+    Ok(Relayed { bytes, first_byte })
+}
 
-    /// Creates a [`ProcessDisplay`](ProcessDisplay.t.html) object with the desired verbosity.
-    ///
-    /// # Examples
-    /// ```
-    /// # use experiment::process::Process;
-    /// # use experiment::Verbosity::{Verbose, Brief};
-    /// let process = Process::new("ls", &["-l", "/path/to/dir"]);
-    /// assert_eq!(format!("{}", process.display(Verbose)), "ls -l /path/to/dir".to_string());
-    /// assert_eq!(format!("{}", process.display(Brief(2))), "ls -l /path/to/dir".to_string());
-    /// assert_eq!(format!("{}", process.display(Brief(1))), "ls -l ...".to_string());
-    /// ```
-    pub fn display(&self, verbosity: Verbosity) -> ProcessDisplay {
-        ProcessDisplay {
-            process: &self,
-            verbosity,
+/// Summary of the data relayed between two stages.
+struct Relayed {
+    /// The number of bytes copied.
+    bytes: u64,
+    /// When the first byte was passed on, if any.
+    first_byte: Option<Instant>,
+}
+
+/// The default size of the buffer used to relay data between stages.
+const RELAY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How often transfers between stages are reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often running processes are checked when waiting with a timeout.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A writable stream that the output of a command can be connected to.
+pub(crate) trait OutputSink: Into<Stdio> + Sized {
+    fn try_clone(&self) -> std::io::Result<Self>;
+}
+
+impl OutputSink for File {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        File::try_clone(self)
+    }
+}
+
+impl OutputSink for PipeWriter {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        PipeWriter::try_clone(self)
+    }
+}
+
+/// Waits for the process until the deadline, and then terminates it and fails with
+/// `TimedOut`.
+fn wait_until(
+    child: &mut Child,
+    deadline: Instant,
+    grace: Duration,
+) -> std::io::Result<ExitStatus> {
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
         }
+        std::thread::sleep(POLL_INTERVAL);
     }
+    terminate(std::slice::from_mut(child), grace)?;
+    child.wait()?;
+    Err(timed_out())
+}
 
-    /// Generates a [`Command`](https://doc.rust-lang.org/std/process/struct.Command.html) object.
-    ///
-    /// # Examples
-    /// ```
-    /// # use experiment::process::Process;
-    /// # use std::str::from_utf8;
-    /// let process = Process::new("echo", &["Hello,", "World!"]);
-    /// let output = process.command().output().expect("Failed to run process");
-    /// assert_eq!(from_utf8(&output.stdout).unwrap(), "Hello, World!\n");
-    /// ```
-    pub fn command(&self) -> Command {
-        let mut cmd = Command::new(&self.program);
-        cmd.args(&self.args);
-        cmd
+/// Asks the processes that are still running to terminate (with `SIGTERM` on Unix), and kills
+/// the ones that are still running after the grace period.
+pub(crate) fn terminate(children: &mut [Child], grace: Duration) -> std::io::Result<()> {
+    #[cfg(unix)]
+    for child in children.iter_mut() {
+        if child.try_wait()?.is_none() {
+            unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+        }
+    }
+    let deadline = Instant::now() + grace;
+    loop {
+        let mut running = false;
+        for child in children.iter_mut() {
+            running |= child.try_wait()?.is_none();
+        }
+        if !running {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    for child in children.iter_mut() {
+        if child.try_wait()?.is_none() {
+            child.kill()?;
+        }
     }
+    Ok(())
+}
 
-    /// Executes the command, ignoring the generated output.
-    ///
-    /// # Examples
-    /// ```
-    /// # use experiment::process::Process;
-    /// let process = Process::new("echo", &["Hello,", "World!"]);
-    /// process.execute().expect("Failed to run process");
-    ///
-    /// let process = Process::new("unknown_process", &Vec::<&str>::new());
-    /// assert!(process.execute().is_err());
-    /// ```
-    pub fn execute(&self) -> std::io::Result<ExitStatus> {
-        self.command().status()
+fn timed_out() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "Terminated after the deadline passed",
+    )
+}
+
+/// Writes each line of the stream to the standard output or error, after the prefix. Lines
+/// are written whole, so that they do not interleave with those of other streams.
+fn relay_labeled<R: Read>(reader: R, prefix: &str, stderr: bool) -> std::io::Result<()> {
+    use std::io::BufRead;
+    let mut reader = std::io::BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        line.extend(prefix.bytes());
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        if !line.ends_with(b"\n") {
+            line.push(b'\n');
+        }
+        if stderr {
+            std::io::stderr().lock().write_all(&line)?;
+        } else {
+            std::io::stdout().lock().write_all(&line)?;
+        }
     }
 }
 
-impl<'a> fmt::Display for ProcessDisplay<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let display_count = match self.verbosity {
-            Verbose => self.process.args.len(),
-            Brief(max_args) => max_args,
-        };
-        write!(f, "{}", &self.process.program)?;
-        for arg in self.process.args.iter().take(display_count) {
-            write!(f, " {}", arg)?;
+/// Reads the entire stream, applying the limit if provided.
+fn read_limited<R: Read>(
+    mut reader: R,
+    limit: Option<(usize, OverflowPolicy)>,
+) -> std::io::Result<Vec<u8>> {
+    let (max_bytes, policy) = match limit {
+        Some(limit) => limit,
+        None => {
+            let mut output = Vec::new();
+            reader.read_to_end(&mut output)?;
+            return Ok(output);
         }
-        if self.verbosity != Verbosity::Verbose && display_count < self.process.args.len() {
-            write!(f, " ...")?;
+    };
+    let mut output = Vec::new();
+    (&mut reader)
+        .take(max_bytes as u64)
+        .read_to_end(&mut output)?;
+    let mut overflow = [0_u8; 1];
+    if reader.read(&mut overflow)? == 0 {
+        return Ok(output);
+    }
+    match policy {
+        OverflowPolicy::Truncate => {
+            let dropped = 1 + std::io::copy(&mut reader, &mut std::io::sink())?;
+            output.extend(format!("\n[truncated {} bytes]\n", dropped).bytes());
+        }
+        OverflowPolicy::Fail => {
+            return Err(std::io::Error::other(format!(
+                "Captured output exceeded {} bytes",
+                max_bytes
+            )));
         }
-        Ok(())
     }
+    Ok(output)
 }
 
-/// A representation of a set of processes interacting through standard input/output.
+/// Opens the file standard error is redirected to for appending.
+fn open_stderr_file(path: &Path) -> std::io::Result<File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+/// Makes spawning the command fail with the given error.
+#[cfg(unix)]
+fn fail_spawn(cmd: &mut Command, err: std::io::Error) {
+    use std::os::unix::process::CommandExt;
+    let code = err.raw_os_error().unwrap_or(libc::EIO);
+    unsafe {
+        cmd.pre_exec(move || Err(std::io::Error::from_raw_os_error(code)));
+    }
+}
+
+/// Makes the command move itself to the cgroup before executing the program.
+#[cfg(target_os = "linux")]
+fn join_cgroup(cmd: &mut Command, group: &Path) {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    let procs = match std::ffi::CString::new(group.join("cgroup.procs").as_os_str().as_bytes()) {
+        Ok(procs) => procs,
+        Err(_) => {
+            return fail_spawn(cmd, std::io::Error::from_raw_os_error(libc::EINVAL));
+        }
+    };
+    unsafe {
+        cmd.pre_exec(move || {
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+            let error = std::io::Error::last_os_error();
+            libc::close(fd);
+            if written == -1 {
+                return Err(error);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Makes the command restrict itself to the CPUs before executing the program.
+#[cfg(target_os = "linux")]
+fn set_affinity(cmd: &mut Command, cpus: Vec<usize>) {
+    use std::os::unix::process::CommandExt;
+    unsafe {
+        cmd.pre_exec(move || {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for &cpu in &cpus {
+                if cpu >= libc::CPU_SETSIZE as usize {
+                    return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+                }
+                libc::CPU_SET(cpu, &mut set);
+            }
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Makes the command bind itself to the CPUs and memory of the NUMA node before executing the
+/// program.
+#[cfg(target_os = "linux")]
+fn bind_numa_node(cmd: &mut Command, node: usize) {
+    use std::os::unix::process::CommandExt;
+    const MPOL_BIND: libc::c_int = 2;
+    const MAX_NODES: usize = 1024;
+    const WORD_BITS: usize = 8 * std::mem::size_of::<libc::c_ulong>();
+    let cpus = std::fs::read_to_string(format!("/sys/devices/system/node/node{}/cpulist", node))
+        .ok()
+        .and_then(|cpulist| parse_cpu_list(cpulist.trim()));
+    let cpus = match cpus {
+        Some(cpus) if node < MAX_NODES => cpus,
+        _ => {
+            unsafe {
+                cmd.pre_exec(|| Err(std::io::Error::from_raw_os_error(libc::EINVAL)));
+            }
+            return;
+        }
+    };
+    set_affinity(cmd, cpus);
+    unsafe {
+        cmd.pre_exec(move || {
+            let mut mask = [0 as libc::c_ulong; MAX_NODES / WORD_BITS];
+            mask[node / WORD_BITS] |= 1 << (node % WORD_BITS);
+            let result = libc::syscall(
+                libc::SYS_set_mempolicy,
+                MPOL_BIND,
+                mask.as_ptr(),
+                MAX_NODES + 1,
+            );
+            if result == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Parses a list of CPUs in the format of `/sys`, such as `0-3,8,10-11`.
+#[cfg(target_os = "linux")]
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let first: usize = bounds.next()?.parse().ok()?;
+        let last: usize = match bounds.next() {
+            Some(last) => last.parse().ok()?,
+            None => first,
+        };
+        cpus.extend(first..=last);
+    }
+    Some(cpus)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// A progress event of a pipeline executed with
+/// [`ProcessPipeline::execute_with_progress`](struct.ProcessPipeline.html#method.execute_with_progress).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PipelineEvent {
+    /// The process of a stage started.
+    Started {
+        /// The index of the stage.
+        stage: usize,
+        /// The process ID.
+        pid: u32,
+    },
+    /// Data flowed from a stage to the next one.
+    Transferred {
+        /// The index of the stage producing the data.
+        stage: usize,
+        /// The total number of bytes transferred so far.
+        bytes: u64,
+    },
+    /// The process of a stage exited.
+    Finished {
+        /// The index of the stage.
+        stage: usize,
+        /// The exit status of the process.
+        status: ExitStatus,
+    },
+}
+
+/// Determines how data flows between consecutive stages of a
+/// [`ProcessPipeline`](struct.ProcessPipeline.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum PipeBuffering {
+    /// The stages are connected with OS pipes, and the data never passes through the current
+    /// process. This is the default and the most efficient option.
+    #[default]
+    Direct,
+    /// The data is relayed through the current process in chunks of at most the given number
+    /// of bytes, which makes it possible to count the bytes passed between stages, report
+    /// their [`throughput`](struct.StageOutput.html#method.throughput), and measure when each
+    /// stage [receives its first input](struct.StageOutput.html#structfield.first_input).
+    Relay(usize),
+}
+
+/// Determines what happens to the processes of a [`PipelineChild`](struct.PipelineChild.html)
+/// that is dropped before being waited on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReapPolicy {
+    /// Wait for all processes to finish.
+    Wait,
+    /// Kill all processes that are still running, and then reap them.
+    Kill,
+}
+
+/// A handle to running processes of a pipeline, created by
+/// [`ProcessPipeline::spawn`](struct.ProcessPipeline.html#method.spawn).
+///
+/// When dropped, all processes are reaped according to the [`ReapPolicy`](enum.ReapPolicy.html)
+/// (`Wait` by default), so no zombie processes are left behind.
 ///
 /// # Examples
 /// ```
 /// # use experiment::pipeline;
-/// # use experiment::process::{Process, ProcessPipeline};
+/// # use experiment::process::{Process, ProcessPipeline, ReapPolicy};
 /// let pipeline = pipeline!(
-///     Process::new("echo", &["-e", "a\\nb\\nc"]),
-///     Process::new("grep", &["b"])
-/// );
-/// assert_eq!(
-///     std::str::from_utf8(&pipeline.pipe().output().unwrap().stdout).unwrap(),
-///     "b\n"
+///     Process::new("sleep", &["10"]),
+///     Process::new("cat", &Vec::<&str>::new())
 /// );
+/// let mut child = pipeline.spawn().expect("Failed to spawn");
+/// child.set_reap_policy(ReapPolicy::Kill);
+/// drop(child); // Returns immediately.
 /// ```
-pub struct ProcessPipeline {
-    processes: Vec<Process>,
+#[derive(Debug)]
+pub struct PipelineChild {
+    children: Vec<Child>,
+    policy: ReapPolicy,
+    fail_fast: bool,
 }
 
-impl ProcessPipeline {
-    /// Creates a process pipeline. Typically, it is better to use [`pipeline`](../macro.pipeline.html) macro.
-    pub fn new(processes: Vec<Process>) -> ProcessPipeline {
-        ProcessPipeline { processes }
+impl PipelineChild {
+    /// Sets the policy used when the handle is dropped.
+    pub fn set_reap_policy(&mut self, policy: ReapPolicy) -> &mut PipelineChild {
+        self.policy = policy;
+        self
     }
 
-    /// Creates a [`PipelineDisplay`](PipelineDisplay.t.html) object with the desired verbosity.
+    /// Handles of the processes, in pipeline order.
     ///
     /// # Examples
     /// ```
     /// # use experiment::pipeline;
     /// # use experiment::process::{Process, ProcessPipeline};
-    /// # use experiment::Verbosity::{Verbose, Brief};
     /// let pipeline = pipeline!(
-    ///     Process::new("echo", &["-e", "a\\nb\\nc"]),
-    ///     Process::new("grep", &["b"])
-    /// );
-    /// assert_eq!(
-    ///     format!("{}", pipeline.display(Verbose)),
-    ///     "echo -e a\\nb\\nc\n\t| grep b".to_string()
-    /// );
-    /// assert_eq!(
-    ///     format!("{}", pipeline.display(Brief(2))),
-    ///     "echo -e a\\nb\\nc\n\t| grep b".to_string()
-    /// );
-    /// assert_eq!(
-    ///     format!("{}", pipeline.display(Brief(1))),
-    ///     "echo -e ...\n\t| grep b".to_string()
+    ///     Process::new("echo", &["a"]),
+    ///     Process::new("cat", &Vec::<&str>::new())
     /// );
+    /// let mut child = pipeline.spawn().unwrap();
+    /// assert_eq!(child.children().len(), 2);
+    /// let pid = child.children()[0].id();
+    /// assert!(pid > 0);
+    /// child.wait_all().unwrap();
     /// ```
-    pub fn display(&self, verbosity: Verbosity) -> PipelineDisplay {
-        PipelineDisplay {
-            pipeline: &self,
-            verbosity,
+    pub fn children(&self) -> &[Child] {
+        &self.children
+    }
+
+    /// Mutable handles of the processes, in pipeline order.
+    pub fn children_mut(&mut self) -> &mut [Child] {
+        &mut self.children
+    }
+
+    /// Releases the handles of the processes, which are then no longer reaped when this object
+    /// is dropped; waiting on them becomes the responsibility of the caller.
+    pub fn into_children(mut self) -> Vec<Child> {
+        std::mem::take(&mut self.children)
+    }
+
+    /// Sets whether all processes are killed as soon as any of them fails while waiting.
+    /// See [`ProcessPipeline::fail_fast`](struct.ProcessPipeline.html#method.fail_fast).
+    pub fn set_fail_fast(&mut self, fail_fast: bool) -> &mut PipelineChild {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Waits for all processes to finish and returns the exit status of the last one.
+    pub fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        self.wait_all()?
+            .pop()
+            .ok_or_else(|| std::io::Error::other("Empty pipeline"))
+    }
+
+    /// Waits for all processes to finish and returns their exit statuses, in order.
+    pub fn wait_all(&mut self) -> std::io::Result<Vec<ExitStatus>> {
+        if self.fail_fast {
+            let finished = self.poll(None)?.expect("Finished without deadline");
+            Ok(finished.into_iter().map(|(status, _)| status).collect())
+        } else {
+            self.children.iter_mut().map(Child::wait).collect()
         }
     }
 
-    /// Generates a pipeline of
-    /// [`Command`](https://doc.rust-lang.org/std/process/struct.Command.html)s and returns the last
-    /// one.
+    /// Waits for all processes to finish for at most `timeout`, and returns their exit statuses,
+    /// or `None` if some of them are still running once the timeout passes.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> std::io::Result<Option<Vec<ExitStatus>>> {
+        let finished = self.poll(Some(Instant::now() + timeout))?;
+        Ok(finished.map(|finished| finished.into_iter().map(|(status, _)| status).collect()))
+    }
+
+    /// Repeatedly checks the processes until all of them finish or the deadline passes,
+    /// killing the remaining ones once any of them fails in fail-fast mode. Returns the exit
+    /// status of each process together with the time it was found to have exited.
+    fn poll(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> std::io::Result<Option<Vec<(ExitStatus, Instant)>>> {
+        let mut finished = vec![None; self.children.len()];
+        loop {
+            for (child, finished) in self.children.iter_mut().zip(&mut finished) {
+                if finished.is_none() {
+                    *finished = child.try_wait()?.map(|status| (status, Instant::now()));
+                }
+            }
+            if finished.iter().all(Option::is_some) {
+                return Ok(finished.into_iter().collect());
+            }
+            let failed = finished
+                .iter()
+                .any(|finished| finished.is_some_and(|(status, _)| !status.success()));
+            if self.fail_fast && failed {
+                self.kill()?;
+                let finished = finished
+                    .into_iter()
+                    .zip(&mut self.children)
+                    .map(|(finished, child)| match finished {
+                        Some(finished) => Ok(finished),
+                        None => Ok((child.wait()?, Instant::now())),
+                    })
+                    .collect::<std::io::Result<_>>()?;
+                return Ok(Some(finished));
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(None);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Asks all processes that are still running to terminate (with `SIGTERM` on Unix), kills
+    /// the ones that have not exited once the grace period passes, and returns the exit
+    /// statuses of all of them, in order.
     ///
     /// # Examples
     /// ```
     /// # use experiment::pipeline;
     /// # use experiment::process::{Process, ProcessPipeline};
-    /// let pipeline = pipeline!(
-    ///     Process::new("echo", &["-e", "a\\nb\\nc"]),
-    ///     Process::new("grep", &["b"])
-    /// );
-    /// assert_eq!(
-    ///     std::str::from_utf8(&pipeline.pipe().output().unwrap().stdout).unwrap(),
-    ///     "b\n"
-    /// );
+    /// # use std::time::{Duration, Instant};
+    /// let pipeline = pipeline!(Process::new("sleep", &["10"]), Process::new("cat", &[] as &[&str]));
+    /// let mut child = pipeline.spawn().unwrap();
+    /// let start = Instant::now();
+    /// let statuses = child.terminate(Duration::from_secs(5)).unwrap();
+    /// assert!(!statuses[0].success());
+    /// assert!(start.elapsed() < Duration::from_secs(5));
     /// ```
-    pub fn pipe(&self) -> Command {
-        assert!(self.processes.len() > 1);
-        let mut cmds = self
-            .processes
-            .iter()
-            .map(|ref p| {
-                let mut cmd = Command::new(&p.program);
-                cmd.args(&p.args);
-                cmd
-            })
-            .collect::<Vec<_>>();
-        for window in (0..cmds.len()).collect::<Vec<_>>().windows(2) {
-            match *window {
-                [first, second] => {
-                    let (reader, writer) = pipe().expect("Failed opening a pipe");
-                    cmds[first].stdout(writer);
-                    cmds[second].stdin(reader);
-                    cmds[first].spawn().expect("Failed to spawn");
-                }
-                _ => panic!("Programming error"),
+    pub fn terminate(&mut self, grace: Duration) -> std::io::Result<Vec<ExitStatus>> {
+        terminate(&mut self.children, grace)?;
+        self.children.iter_mut().map(Child::wait).collect()
+    }
+
+    /// Kills all processes that are still running.
+    pub fn kill(&mut self) -> std::io::Result<()> {
+        for child in &mut self.children {
+            if child.try_wait()?.is_none() {
+                child.kill()?;
             }
         }
-        cmds.pop().expect("No last element")
+        Ok(())
     }
+}
 
-    /// Executes the entire pipeline disregarding the output.
-    pub fn execute(&self) -> std::io::Result<ExitStatus> {
-        self.pipe().status()
+impl Drop for PipelineChild {
+    fn drop(&mut self) {
+        if self.policy == ReapPolicy::Kill {
+            let _ = self.kill();
+        }
+        for child in &mut self.children {
+            let _ = child.wait();
+        }
     }
 }
 
@@ -259,10 +3547,19 @@ pub struct PipelineDisplay<'a> {
 
 impl<'a> fmt::Display for PipelineDisplay<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if !self.pipeline.processes.is_empty() {
-            write!(f, "{}", self.pipeline.processes[0].display(self.verbosity))?;
-            for cmd in &self.pipeline.processes[1..] {
-                write!(f, "\n\t| {}", cmd.display(self.verbosity))?;
+        let last = self.pipeline.processes.len().saturating_sub(1);
+        for (idx, cmd) in self.pipeline.processes.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "\n\t| ")?;
+            }
+            write!(f, "{}", cmd.display(self.verbosity))?;
+            match &self.pipeline.stdin_file {
+                Some(path) if idx == 0 => write!(f, " < {}", path.display())?,
+                _ => {}
+            }
+            match &self.pipeline.stdout_file {
+                Some(path) if idx == last => write!(f, " > {}", path.display())?,
+                _ => {}
             }
         }
         Ok(())
@@ -271,20 +3568,99 @@ impl<'a> fmt::Display for PipelineDisplay<'a> {
 
 /// Creates a [`ProcessPipeline`](ProcessPipeline.t.html) from provided processes.
 ///
+/// Besides processes, the stages can be existing pipelines, whose processes are spliced in
+/// (see [`ProcessPipeline::concat`](process/struct.ProcessPipeline.html#method.concat)),
+/// and stages of the form `if condition => stage`, which are only included if the condition
+/// holds.
+///
 /// # Examples
 /// ```
 /// # use experiment::pipeline;
 /// # use experiment::process::{Process, ProcessPipeline};
+/// # use experiment::Verbosity::Verbose;
 /// let pipeline = pipeline!(
 ///     Process::new("echo", &["-e", "a\\nb\\nc"]),
 ///     Process::new("grep", &["b"])
 /// );
+///
+/// let compressed = false;
+/// let filters = pipeline!(Process::new("grep", &["-v", "a"]), Process::new("uniq", &["-c"]));
+/// let pipeline = pipeline!(
+///     Process::new("cat", &["corpus"]),
+///     if compressed => Process::new("zstd", &["-d"]),
+///     filters,
+///     if !compressed => Process::new("sort", &["-n"]),
+/// );
+/// assert_eq!(
+///     pipeline.display(Verbose).to_string(),
+///     "cat corpus\n\t| grep -v a\n\t| uniq -c\n\t| sort -n"
+/// );
 /// ```
 #[macro_export]
 macro_rules! pipeline {
-    ($($cmd:expr), *) => {{
-        let mut vec: Vec<Process> = Vec::new();
-        $(vec.push($cmd);)*
-        ProcessPipeline::new(vec)
+    (@stages $pipeline:ident;) => {};
+    (@stages $pipeline:ident; if $cond:expr => $stage:expr $(, $($rest:tt)*)?) => {
+        if $cond {
+            $pipeline.concat($crate::process::ProcessPipeline::from($stage));
+        }
+        $crate::pipeline!(@stages $pipeline; $($($rest)*)?);
+    };
+    (@stages $pipeline:ident; $stage:expr $(, $($rest:tt)*)?) => {
+        $pipeline.concat($crate::process::ProcessPipeline::from($stage));
+        $crate::pipeline!(@stages $pipeline; $($($rest)*)?);
+    };
+    ($($stages:tt)*) => {{
+        let mut pipeline = $crate::process::ProcessPipeline::new(Vec::new());
+        $crate::pipeline!(@stages pipeline; $($stages)*);
+        pipeline
     }};
 }
+
+impl LogDir {
+    fn new(path: PathBuf, policy: OverwritePolicy) -> LogDir {
+        LogDir {
+            path,
+            policy,
+            created: Default::default(),
+        }
+    }
+
+    /// Creates the directory with [`safe_mkdir`](../fn.safe_mkdir.html) the first time, and
+    /// only makes sure that it exists afterwards.
+    fn create(&self) -> std::io::Result<()> {
+        let mut created = self.created.lock().expect("Poisoned lock");
+        if *created {
+            std::fs::create_dir_all(&self.path)
+        } else {
+            safe_mkdir(&self.path, self.policy)?;
+            *created = true;
+            Ok(())
+        }
+    }
+}
+
+impl PartialEq for LogDir {
+    fn eq(&self, other: &LogDir) -> bool {
+        self.path == other.path && self.policy == other.policy
+    }
+}
+
+impl Eq for LogDir {}
+
+impl std::hash::Hash for LogDir {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+        self.policy.hash(state);
+    }
+}
+
+/// Replaces placeholders in a path; paths that are not valid UTF-8 are left as they are.
+fn interpolate_path<'a, F>(path: &Path, lookup: &F) -> Result<PathBuf, String>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    match path.to_str() {
+        Some(text) => template::interpolate(text, lookup).map(PathBuf::from),
+        None => Ok(path.to_path_buf()),
+    }
+}
@@ -0,0 +1,158 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Execution of processes attached to a pseudo-terminal.
+
+use super::process::Process;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ExitStatus, Stdio};
+
+/// A process running with a pseudo-terminal as its standard input, output, and error.
+///
+/// Created by [`Process::spawn_pty`](../process/struct.Process.html#method.spawn_pty).
+/// The process can be driven by writing to and reading from the [`master`](#method.master)
+/// side of the terminal.
+///
+/// # Examples
+/// ```
+/// # use experiment::process::Process;
+/// # use std::io::{BufRead, BufReader, Write};
+/// let mut child = Process::new("head", &["-n", "1"]).spawn_pty().unwrap();
+/// child.master().write_all(b"input\n").unwrap();
+/// assert!(child.wait().unwrap().success());
+/// ```
+#[derive(Debug)]
+pub struct PtyChild {
+    child: Child,
+    master: File,
+}
+
+impl PtyChild {
+    /// The master side of the pseudo-terminal.
+    pub fn master(&mut self) -> &mut File {
+        &mut self.master
+    }
+
+    /// The underlying child process.
+    pub fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Reads all output until the process closes the terminal, and then waits for it to exit.
+    pub fn output(mut self) -> io::Result<(ExitStatus, Vec<u8>)> {
+        let mut output = Vec::new();
+        let mut buf = [0_u8; 4096];
+        loop {
+            match self.master.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => output.extend_from_slice(&buf[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                // Linux reports EIO on the master once all slave descriptors are closed.
+                Err(ref err) if err.raw_os_error() == Some(libc::EIO) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok((self.child.wait()?, output))
+    }
+
+    /// Waits for the process to exit.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.child.wait()
+    }
+}
+
+impl Process {
+    /// Spawns the process attached to a new pseudo-terminal, which becomes its controlling
+    /// terminal. Useful for tools that change their behavior when not run in a terminal.
+    pub fn spawn_pty(&self) -> io::Result<PtyChild> {
+        let (master, slave) = open_pty()?;
+        let mut cmd = self.try_command()?;
+        cmd.stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                #[allow(clippy::useless_conversion)]
+                if libc::ioctl(0, libc::TIOCSCTTY.into(), 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn()?;
+        Ok(PtyChild { child, master })
+    }
+
+    /// Executes the process attached to a new pseudo-terminal, and returns its exit status
+    /// together with everything it has written to the terminal.
+    ///
+    /// Note that terminals translate line endings, so `\n` is typically captured as `\r\n`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// let process = Process::new("sh", &["-c", "test -t 1 && echo terminal"]);
+    /// let (status, output) = process.execute_pty().expect("Failed to run process");
+    /// assert!(status.success());
+    /// assert_eq!(output, b"terminal\r\n");
+    /// ```
+    pub fn execute_pty(&self) -> io::Result<(ExitStatus, Vec<u8>)> {
+        self.spawn_pty()?.output()
+    }
+}
+
+/// Opens a new pseudo-terminal and returns its master and slave sides.
+fn open_pty() -> io::Result<(File, File)> {
+    let mut master: RawFd = -1;
+    let mut slave: RawFd = -1;
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+    for file in &[&master, &slave] {
+        set_cloexec(std::os::unix::io::AsRawFd::as_raw_fd(*file))?;
+    }
+    Ok((master, slave))
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Queues of experiments run back to back.
+
+use super::experiment::{Experiment, ExperimentReport, StageOutcome};
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A queue of experiments, run one after another, e.g., overnight.
+///
+/// A failing experiment does not stop the queue: each one is run regardless of the outcomes
+/// of the earlier ones, and the [`QueueReport`](struct.QueueReport.html) records the outcome
+/// of every one of them.
+///
+/// # Examples
+/// ```
+/// # use experiment::experiment::Experiment;
+/// # use experiment::process::Process;
+/// # use experiment::queue::ExperimentQueue;
+/// let no_args: &[&str] = &[];
+/// let mut bm25 = Experiment::new("bm25");
+/// bm25.process("index", Process::new("true", no_args))
+///     .process("search", Process::new("false", no_args));
+/// let mut ql = Experiment::new("ql");
+/// ql.process("index", Process::new("true", no_args));
+/// let mut queue = ExperimentQueue::new();
+/// queue.push(bm25).push(ql);
+/// let report = queue.run();
+/// assert!(!report.success());
+/// assert!(report.runs[1].result.as_ref().unwrap().success());
+/// let summary = report.to_string();
+/// let lines: Vec<_> = summary.lines().collect();
+/// assert!(lines[0].starts_with("bm25: failed at `search`: false failed: exit status: 1 ("));
+/// assert!(lines[1].starts_with("ql: succeeded ("));
+/// assert_eq!(lines[2], "1 of 2 experiments succeeded");
+/// ```
+#[derive(Default)]
+pub struct ExperimentQueue {
+    experiments: Vec<Experiment>,
+    start_at: Option<SystemTime>,
+}
+
+/// The outcome of a single experiment in an [`ExperimentQueue`](struct.ExperimentQueue.html).
+#[derive(Debug)]
+pub struct QueuedRun {
+    /// The name of the experiment.
+    pub name: String,
+    /// The report of the experiment, or the error that prevented it from running.
+    pub result: io::Result<ExperimentReport>,
+    /// How long the experiment ran.
+    pub duration: Duration,
+}
+
+/// The outcomes of all experiments of an [`ExperimentQueue`](struct.ExperimentQueue.html), in
+/// order. Its `Display` implementation prints a summary with one line per experiment.
+#[derive(Debug)]
+pub struct QueueReport {
+    /// The outcomes of the experiments, in order.
+    pub runs: Vec<QueuedRun>,
+}
+
+impl ExperimentQueue {
+    /// Creates an empty queue.
+    pub fn new() -> ExperimentQueue {
+        ExperimentQueue::default()
+    }
+
+    /// Appends an experiment to the queue.
+    pub fn push(&mut self, experiment: Experiment) -> &mut ExperimentQueue {
+        self.experiments.push(experiment);
+        self
+    }
+
+    /// Appends the experiment [defined](../config/fn.from_str.html) in a configuration file.
+    #[cfg(feature = "config")]
+    pub fn load<P: AsRef<std::path::Path>>(&mut self, path: P) -> io::Result<&mut ExperimentQueue> {
+        self.experiments.push(crate::config::load(path)?);
+        Ok(self)
+    }
+
+    /// Delays the first experiment until the given time, e.g., to run the queue overnight.
+    pub fn start_at(&mut self, time: SystemTime) -> &mut ExperimentQueue {
+        self.start_at = Some(time);
+        self
+    }
+
+    /// The number of queued experiments.
+    pub fn len(&self) -> usize {
+        self.experiments.len()
+    }
+
+    /// Checks if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.experiments.is_empty()
+    }
+
+    /// Runs all experiments in order, after waiting for the start time if set.
+    pub fn run(&self) -> QueueReport {
+        if let Some(time) = self.start_at {
+            if let Ok(delay) = time.duration_since(SystemTime::now()) {
+                std::thread::sleep(delay);
+            }
+        }
+        let runs = self
+            .experiments
+            .iter()
+            .map(|experiment| {
+                let start = Instant::now();
+                let result = experiment.run();
+                QueuedRun {
+                    name: String::from(experiment.name()),
+                    result,
+                    duration: start.elapsed(),
+                }
+            })
+            .collect();
+        QueueReport { runs }
+    }
+}
+
+impl QueueReport {
+    /// Checks if all experiments ran and succeeded.
+    pub fn success(&self) -> bool {
+        self.runs
+            .iter()
+            .all(|run| run.result.as_ref().is_ok_and(ExperimentReport::success))
+    }
+}
+
+impl fmt::Display for QueueReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut succeeded = 0;
+        for run in &self.runs {
+            write!(f, "{}: ", run.name)?;
+            match &run.result {
+                Ok(report) if report.success() => {
+                    succeeded += 1;
+                    write!(f, "succeeded")?;
+                }
+                Ok(report) => {
+                    let failed = report.stages.iter().find_map(|stage| match &stage.outcome {
+                        StageOutcome::Failed(err) => Some((&stage.name, err)),
+                        _ => None,
+                    });
+                    match failed {
+                        Some((stage, err)) => write!(f, "failed at `{}`: {}", stage, err)?,
+                        None => write!(f, "incomplete")?,
+                    }
+                    if report.partial {
+                        write!(f, " (out of time)")?;
+                    }
+                }
+                Err(err) => write!(f, "could not run: {}", err)?,
+            }
+            writeln!(f, " ({:.1?})", run.duration)?;
+        }
+        write!(
+            f,
+            "{} of {} experiments succeeded",
+            succeeded,
+            self.runs.len()
+        )
+    }
+}
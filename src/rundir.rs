@@ -0,0 +1,359 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Directories of experiment runs, named after their run IDs.
+
+use super::rng::SplitMix64;
+use super::{safe_mkdir, OverwritePolicy};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The name of the link to the most recently created run directory.
+pub const LATEST: &str = "latest";
+
+/// The directory of a single run of an experiment, `<base>/<experiment>/<run-id>/`, with
+/// a subdirectory per stage. By default, the run ID is a timestamp; see
+/// [`RunIdScheme`](enum.RunIdScheme.html) for the alternatives.
+///
+/// Creating a run directory points the `<base>/<experiment>/latest` symbolic link to it
+/// (on Unix), so that the results of the last run are always at the same path.
+///
+/// # Examples
+/// ```
+/// # use experiment::rundir::RunDir;
+/// # use experiment::OverwritePolicy;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("runs").unwrap();
+/// let run = RunDir::create_at(tmp.path(), "bm25", "2019-06-01_12-00-00", OverwritePolicy::Fail)
+///     .unwrap();
+/// assert_eq!(run.path(), tmp.path().join("bm25/2019-06-01_12-00-00"));
+/// assert_eq!(run.id(), "2019-06-01_12-00-00");
+/// let index = run.stage("index").unwrap();
+/// assert!(index.is_dir());
+/// assert_eq!(index, run.path().join("index"));
+///
+/// let latest = RunDir::latest(tmp.path(), "bm25").unwrap();
+/// assert_eq!(latest.id(), "2019-06-01_12-00-00");
+///
+/// // The directory already exists.
+/// assert!(RunDir::create_at(tmp.path(), "bm25", "2019-06-01_12-00-00", OverwritePolicy::Fail)
+///     .is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RunDir {
+    path: PathBuf,
+}
+
+/// How the run IDs, and thus the names of [run directories](struct.RunDir.html), are chosen.
+///
+/// Sequential IDs and ULIDs are allocated by atomically creating the directory, and trying
+/// the next candidate if it already exists, so that concurrent runs, even on different hosts
+/// sharing a file system, never get the same ID.
+///
+/// # Examples
+/// ```
+/// # use experiment::rundir::{RunDir, RunIdScheme};
+/// # use experiment::OverwritePolicy;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("runs").unwrap();
+/// let allocate = |scheme: &RunIdScheme| {
+///     RunDir::allocate(tmp.path(), "bm25", scheme, OverwritePolicy::Fail).unwrap()
+/// };
+/// assert_eq!(allocate(&RunIdScheme::Sequential).id(), "run-0001");
+/// assert_eq!(allocate(&RunIdScheme::Sequential).id(), "run-0002");
+/// assert_eq!(allocate(&RunIdScheme::Given(String::from("baseline"))).id(), "baseline");
+/// let ulid = allocate(&RunIdScheme::Ulid);
+/// assert_eq!(ulid.id().len(), 26);
+/// assert_ne!(allocate(&RunIdScheme::Ulid), ulid);
+/// assert_eq!(RunDir::latest(tmp.path(), "bm25").unwrap().id().len(), 26);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum RunIdScheme {
+    /// The UTC time of the start of the run, `YYYY-MM-DD_HH-MM-SS`.
+    #[default]
+    Timestamp,
+    /// `run-0001`, `run-0002`, and so on, following the highest existing number.
+    Sequential,
+    /// A [ULID](https://github.com/ulid/spec): 26 characters that sort by the time of
+    /// creation, with 80 random bits.
+    Ulid,
+    /// The given ID.
+    Given(String),
+}
+
+impl RunDir {
+    /// Creates the directory of a run of the experiment started now, named after the current
+    /// UTC time as `YYYY-MM-DD_HH-MM-SS`, and updates the `latest` link. With
+    /// `OverwritePolicy::Fail`, fails if a run started within the same second.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::rundir::RunDir;
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("runs").unwrap();
+    /// let run = RunDir::create(tmp.path(), "bm25", OverwritePolicy::Force).unwrap();
+    /// assert!(run.path().is_dir());
+    /// assert_eq!(run.id().len(), "YYYY-MM-DD_HH-MM-SS".len());
+    /// assert_eq!(RunDir::latest(tmp.path(), "bm25").unwrap(), run);
+    /// ```
+    pub fn create<P: AsRef<Path>>(
+        base: P,
+        experiment: &str,
+        policy: OverwritePolicy,
+    ) -> io::Result<RunDir> {
+        RunDir::allocate(base, experiment, &RunIdScheme::Timestamp, policy)
+    }
+
+    /// Creates the directory of a run with an ID chosen by the scheme, and updates the
+    /// `latest` link. The policy applies to timestamps and given IDs; sequential IDs and
+    /// ULIDs always get a new directory.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::rundir::{RunDir, RunIdScheme};
+    /// # use experiment::OverwritePolicy;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("runs").unwrap();
+    /// let mut ids: Vec<_> = std::thread::scope(|scope| {
+    ///     let threads: Vec<_> = (0..8)
+    ///         .map(|_| {
+    ///             scope.spawn(|| {
+    ///                 RunDir::allocate(tmp.path(), "bm25", &RunIdScheme::Ulid, OverwritePolicy::Fail)
+    ///                     .unwrap()
+    ///             })
+    ///         })
+    ///         .collect();
+    ///     threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+    /// });
+    /// ids.sort_by(|a, b| a.id().cmp(b.id()));
+    /// ids.dedup();
+    /// assert_eq!(ids.len(), 8);
+    /// ```
+    pub fn allocate<P: AsRef<Path>>(
+        base: P,
+        experiment: &str,
+        scheme: &RunIdScheme,
+        policy: OverwritePolicy,
+    ) -> io::Result<RunDir> {
+        let parent = base.as_ref().join(experiment);
+        let id = match scheme {
+            RunIdScheme::Timestamp => timestamp(SystemTime::now()),
+            RunIdScheme::Given(id) => id.clone(),
+            RunIdScheme::Sequential => {
+                std::fs::create_dir_all(&parent)?;
+                let mut number = last_sequential(&parent)? + 1;
+                claim(&parent, || {
+                    number += 1;
+                    format!("run-{:04}", number - 1)
+                })?
+            }
+            RunIdScheme::Ulid => {
+                std::fs::create_dir_all(&parent)?;
+                claim(&parent, || ulid(SystemTime::now()))?
+            }
+        };
+        match scheme {
+            RunIdScheme::Timestamp | RunIdScheme::Given(_) => {
+                RunDir::create_at(base, experiment, &id, policy)
+            }
+            RunIdScheme::Sequential | RunIdScheme::Ulid => {
+                link_latest(&parent, &id)?;
+                Ok(RunDir {
+                    path: parent.join(id),
+                })
+            }
+        }
+    }
+
+    /// Creates the directory of a run with the given timestamp, or any other name, and
+    /// updates the `latest` link. The directory is created with
+    /// [`safe_mkdir`](../fn.safe_mkdir.html) and the given policy.
+    pub fn create_at<P: AsRef<Path>>(
+        base: P,
+        experiment: &str,
+        timestamp: &str,
+        policy: OverwritePolicy,
+    ) -> io::Result<RunDir> {
+        let parent = base.as_ref().join(experiment);
+        let path = parent.join(timestamp);
+        safe_mkdir(&path, policy)?;
+        link_latest(&parent, timestamp)?;
+        Ok(RunDir { path })
+    }
+
+    /// Refers to the run the `latest` link points to.
+    pub fn latest<P: AsRef<Path>>(base: P, experiment: &str) -> io::Result<RunDir> {
+        let parent = base.as_ref().join(experiment);
+        let target = std::fs::read_link(parent.join(LATEST))?;
+        Ok(RunDir {
+            path: parent.join(target),
+        })
+    }
+
+    /// The directory of the run.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The run ID, which is the name of the directory.
+    pub fn id(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+
+    /// Creates, if missing, and returns the subdirectory of a stage.
+    pub fn stage(&self, name: &str) -> io::Result<PathBuf> {
+        let dir = self.path.join(name);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+/// The highest number of the `run-NNNN` directories in `parent`, or 0 if there are none.
+fn last_sequential(parent: &Path) -> io::Result<u64> {
+    let mut last = 0;
+    for entry in std::fs::read_dir(parent)? {
+        let name = entry?.file_name();
+        let number = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("run-"))
+            .and_then(|number| number.parse().ok());
+        if let Some(number) = number {
+            last = last.max(number);
+        }
+    }
+    Ok(last)
+}
+
+/// Creates the directory `parent/<candidate>` for successive candidates until one does not
+/// exist yet, and returns its name.
+fn claim<F: FnMut() -> String>(parent: &Path, mut candidate: F) -> io::Result<String> {
+    loop {
+        let id = candidate();
+        match std::fs::create_dir(parent.join(&id)) {
+            Ok(()) => return Ok(id),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Generates a ULID: the milliseconds since the Unix epoch in 48 bits followed by 80 random
+/// bits, in Crockford's base 32.
+fn ulid(time: SystemTime) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seed = elapsed.as_nanos() as u64
+        ^ (u64::from(std::process::id()) << 32)
+        ^ COUNTER
+            .fetch_add(1, Ordering::SeqCst)
+            .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    let mut rng = SplitMix64::new(seed);
+    let random =
+        (u128::from(rng.next_u64()) << 16 | u128::from(rng.next_u64() >> 48)) & ((1 << 80) - 1);
+    let value = (elapsed.as_millis() & ((1 << 48) - 1)) << 80 | random;
+    (0..26)
+        .map(|idx| ALPHABET[((value >> (125 - 5 * idx)) & 31) as usize] as char)
+        .collect()
+}
+
+/// Atomically points `<parent>/latest` to `target`, relative to `parent`.
+///
+/// The temporary link is named after the target, the process, and the call, so that
+/// concurrent calls, from threads of one process or from hosts sharing the file system,
+/// never replace each other's temporary link.
+#[cfg(unix)]
+fn link_latest(parent: &Path, target: &str) -> io::Result<()> {
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    let temporary = parent.join(format!(
+        ".{}.{}.{}.{}",
+        LATEST,
+        target,
+        std::process::id(),
+        CALLS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_file(&temporary);
+    std::os::unix::fs::symlink(target, &temporary)?;
+    std::fs::rename(&temporary, parent.join(LATEST))
+}
+
+#[cfg(not(unix))]
+fn link_latest(_parent: &Path, _target: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Formats the time in UTC as `YYYY-MM-DD_HH-MM-SS`.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    utc(time, '_', '-')
+}
+
+/// Formats the time in UTC as ISO 8601, `YYYY-MM-DDTHH:MM:SSZ`.
+pub(crate) fn iso8601(time: SystemTime) -> String {
+    format!("{}Z", utc(time, 'T', ':'))
+}
+
+fn utc(time: SystemTime, date_separator: char, time_separator: char) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}{}{:02}{}{:02}{}{:02}",
+        year,
+        month,
+        day,
+        date_separator,
+        seconds / 3600,
+        time_separator,
+        seconds / 60 % 60,
+        time_separator,
+        seconds % 60
+    )
+}
+
+/// Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar, after
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
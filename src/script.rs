@@ -0,0 +1,168 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Exporting experiments to standalone shell scripts.
+
+use super::chain::CommandChain;
+use super::process::{Process, ProcessPipeline};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Anything that can be rendered as a single line of a POSIX shell script.
+pub trait ShellCommand {
+    /// Renders the command as a single line that can be pasted into a shell.
+    fn to_shell_string(&self) -> String;
+}
+
+impl ShellCommand for Process {
+    fn to_shell_string(&self) -> String {
+        Process::to_shell_string(self)
+    }
+}
+
+impl ShellCommand for ProcessPipeline {
+    fn to_shell_string(&self) -> String {
+        ProcessPipeline::to_shell_string(self)
+    }
+}
+
+impl ShellCommand for CommandChain {
+    fn to_shell_string(&self) -> String {
+        CommandChain::to_shell_string(self)
+    }
+}
+
+/// A standalone bash script running a sequence of commands, which stops at the first failure
+/// (`set -euo pipefail`).
+///
+/// Processes with a label are preceded by a comment with the label.
+///
+/// # Examples
+/// ```
+/// # use experiment::pipeline;
+/// # use experiment::process::{Process, ProcessPipeline};
+/// # use experiment::script::ShellScript;
+/// let mut parse = Process::new("parse", &["corpus"]);
+/// parse.label("parse");
+/// let mut script = ShellScript::new();
+/// script
+///     .comment("Builds the index of the corpus.")
+///     .process(&parse)
+///     .blank_line()
+///     .command(&pipeline!(
+///         Process::new("cat", &["queries file.txt"]),
+///         Process::new("evaluate", &["--index", "idx"])
+///     ));
+/// let lines: Vec<_> = script.to_string().lines().map(String::from).collect();
+/// assert_eq!(
+///     lines,
+///     vec![
+///         "#!/usr/bin/env bash",
+///         "set -euo pipefail",
+///         "",
+///         "# Builds the index of the corpus.",
+///         "# parse",
+///         "parse corpus",
+///         "",
+///         "cat 'queries file.txt' | evaluate --index idx",
+///     ]
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ShellScript {
+    lines: Vec<String>,
+}
+
+impl ShellScript {
+    /// Creates an empty script.
+    pub fn new() -> ShellScript {
+        ShellScript::default()
+    }
+
+    /// Appends a comment; each line of the text becomes a separate comment line.
+    pub fn comment(&mut self, text: &str) -> &mut ShellScript {
+        for line in text.lines() {
+            self.lines
+                .push(format!("# {}", line).trim_end().to_string());
+        }
+        self
+    }
+
+    /// Appends an empty line.
+    pub fn blank_line(&mut self) -> &mut ShellScript {
+        self.lines.push(String::new());
+        self
+    }
+
+    /// Appends a command.
+    pub fn command<C: ShellCommand + ?Sized>(&mut self, command: &C) -> &mut ShellScript {
+        self.lines.push(command.to_shell_string());
+        self
+    }
+
+    /// Appends a process, preceded by a comment with its label, if any.
+    pub fn process(&mut self, process: &Process) -> &mut ShellScript {
+        if let Some(label) = process.get_label() {
+            self.comment(label);
+        }
+        self.command(process)
+    }
+
+    /// Writes the script to a file, which is made executable on Unix systems.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::script::ShellScript;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("script").unwrap();
+    /// let path = tmp.path().join("run.sh");
+    /// let mut script = ShellScript::new();
+    /// script.command(&Process::new("echo", &["it's done"]));
+    /// script.write(&path).unwrap();
+    /// let output = std::process::Command::new(&path).output().unwrap();
+    /// assert_eq!(output.stdout, b"it's done\n".to_vec());
+    /// ```
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ShellScript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "#!/usr/bin/env bash")?;
+        writeln!(f, "set -euo pipefail")?;
+        writeln!(f)?;
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
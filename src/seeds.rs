@@ -0,0 +1,80 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Seeds of random number generators derived from a single master seed.
+
+use super::fingerprint::StableHasher;
+use super::rng::SplitMix64;
+use std::hash::Hasher;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A master seed from which a seed for every stage of an experiment is derived.
+///
+/// The derived seeds depend only on the master seed and the name of the stage, so recording
+/// the master seed is enough to reproduce a run, and adding or reordering stages does not
+/// change the seeds of the others. An [`Experiment`](../experiment/struct.Experiment.html)
+/// with seeds replaces `{seed}` placeholders in the commands of its stages with their seeds,
+/// and records all of them in its manifest.
+///
+/// # Examples
+/// ```
+/// # use experiment::seeds::Seeds;
+/// let seeds = Seeds::new(42);
+/// assert_eq!(seeds.master(), 42);
+/// assert_eq!(seeds.seed("shuffle"), Seeds::new(42).seed("shuffle"));
+/// assert_ne!(seeds.seed("shuffle"), seeds.seed("sample"));
+/// assert_ne!(seeds.seed("shuffle"), Seeds::new(43).seed("shuffle"));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Seeds {
+    master: u64,
+}
+
+impl Seeds {
+    /// Derives seeds from the given master seed.
+    pub fn new(master: u64) -> Seeds {
+        Seeds { master }
+    }
+
+    /// Derives seeds from a master seed based on the current time and process ID, to be
+    /// recorded and reused to reproduce the run.
+    pub fn from_time() -> Seeds {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        let mut rng = SplitMix64::new(nanos ^ u64::from(std::process::id()) << 32);
+        Seeds::new(rng.next_u64())
+    }
+
+    /// The master seed.
+    pub fn master(&self) -> u64 {
+        self.master
+    }
+
+    /// The seed of the named stage.
+    pub fn seed(&self, stage: &str) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write_str(stage);
+        SplitMix64::new(self.master ^ hasher.finish()).next_u64()
+    }
+}
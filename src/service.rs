@@ -0,0 +1,480 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Long-running processes, such as servers, that other stages depend on.
+
+use super::process::{terminate, Process, POLL_INTERVAL};
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ExitStatus};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A process running in the background while other stages execute.
+///
+/// The standard output and error of the service are written to `<name>.stdout.log` and
+/// `<name>.stderr.log` in the log directory. A service that is dropped without being
+/// [stopped](#method.stop) is killed.
+///
+/// # Examples
+/// ```
+/// # use experiment::process::Process;
+/// # use experiment::service::Service;
+/// # use tempdir::TempDir;
+/// # use std::time::Duration;
+/// let tmp = TempDir::new("service").unwrap();
+/// let ready_file = tmp.path().join("ready");
+/// let server = Process::new(
+///     "sh",
+///     &["-c", &format!("echo starting; touch {}; sleep 10", ready_file.display())],
+/// );
+/// let mut service = Service::start("server", &server, tmp.path()).unwrap();
+/// service
+///     .wait_until(|_| ready_file.exists(), Duration::from_secs(5))
+///     .unwrap();
+/// // Run stages depending on the server here.
+/// let logs = service.stop().unwrap();
+/// assert_eq!(logs.stdout, b"starting\n".to_vec());
+/// ```
+#[derive(Debug)]
+pub struct Service {
+    name: String,
+    child: Child,
+    stdout_log: PathBuf,
+    stderr_log: PathBuf,
+    grace_period: Duration,
+}
+
+/// Exit status and output of a stopped [`Service`](struct.Service.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceLogs {
+    /// The exit status of the service.
+    pub status: ExitStatus,
+    /// The contents of the standard output log.
+    pub stdout: Vec<u8>,
+    /// The contents of the standard error log.
+    pub stderr: Vec<u8>,
+}
+
+/// A readiness condition of a [`Service`](struct.Service.html).
+///
+/// # Examples
+/// ```
+/// # use experiment::process::Process;
+/// # use experiment::service::{Service, WaitFor};
+/// # use tempdir::TempDir;
+/// # use std::time::Duration;
+/// let tmp = TempDir::new("service").unwrap();
+/// let server = Process::new(
+///     "sh",
+///     &["-c", "echo loading; printf 'index ' >&2; sleep 0.1; echo loaded >&2; sleep 10"],
+/// );
+/// let mut service = Service::start("server", &server, tmp.path()).unwrap();
+/// service
+///     .wait_for(&WaitFor::Output("index loaded".into()), Duration::from_secs(5))
+///     .unwrap();
+/// assert!(service
+///     .wait_for(&WaitFor::FileExists(tmp.path().join("never")), Duration::from_millis(50))
+///     .is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WaitFor {
+    /// A TCP connection to the address can be established.
+    TcpPort(SocketAddr),
+    /// The file exists.
+    FileExists(PathBuf),
+    /// A line of the standard output or error of the service matches the pattern.
+    Output(Pattern),
+}
+
+/// A pattern matched against single lines of the output of a [`Service`](struct.Service.html).
+///
+/// There is no support for regular expressions, as the crate does not depend on a regular
+/// expression engine; [`Matches`](#variant.Matches) takes any predicate instead, e.g., one
+/// calling into a regular expression library.
+///
+/// # Examples
+/// ```
+/// # use experiment::service::Pattern;
+/// let pattern = Pattern::from("listening on");
+/// assert!(pattern.matches("server listening on port 8080"));
+/// assert!(!pattern.matches("server starting"));
+///
+/// assert!(Pattern::StartsWith(String::from("READY")).matches("READY 8080"));
+/// assert!(!Pattern::StartsWith(String::from("READY")).matches("NOT READY"));
+/// assert!(Pattern::Line(String::from("ok")).matches("ok"));
+/// assert!(!Pattern::Line(String::from("ok")).matches("not ok"));
+///
+/// let port = Pattern::matching(|line| {
+///     line.strip_prefix("port ")
+///         .is_some_and(|port| port.parse::<u16>().is_ok())
+/// });
+/// assert!(port.matches("port 8080"));
+/// assert!(!port.matches("port unknown"));
+/// ```
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum Pattern {
+    /// The line contains the text.
+    Contains(String),
+    /// The line starts with the text.
+    StartsWith(String),
+    /// The line is equal to the text.
+    Line(String),
+    /// The predicate returns `true` for the line. Two such patterns are only equal if they
+    /// share the same predicate.
+    Matches(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl Pattern {
+    /// A pattern matching the lines for which the predicate returns `true`.
+    pub fn matching<F>(predicate: F) -> Pattern
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        Pattern::Matches(Arc::new(predicate))
+    }
+
+    /// Checks if the line, without its line terminator, matches the pattern.
+    pub fn matches(&self, line: &str) -> bool {
+        match self {
+            Pattern::Contains(text) => line.contains(text.as_str()),
+            Pattern::StartsWith(text) => line.starts_with(text.as_str()),
+            Pattern::Line(text) => line == text,
+            Pattern::Matches(predicate) => predicate(line),
+        }
+    }
+
+    fn matches_any_line(&self, text: &[u8]) -> bool {
+        text.split(|&byte| byte == b'\n')
+            .any(|line| self.matches(&String::from_utf8_lossy(line)))
+    }
+}
+
+impl From<&str> for Pattern {
+    fn from(text: &str) -> Pattern {
+        Pattern::Contains(String::from(text))
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Contains(text) => f.debug_tuple("Contains").field(text).finish(),
+            Pattern::StartsWith(text) => f.debug_tuple("StartsWith").field(text).finish(),
+            Pattern::Line(text) => f.debug_tuple("Line").field(text).finish(),
+            Pattern::Matches(_) => f.write_str("Matches"),
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (Pattern::Contains(a), Pattern::Contains(b))
+            | (Pattern::StartsWith(a), Pattern::StartsWith(b))
+            | (Pattern::Line(a), Pattern::Line(b)) => a == b,
+            (Pattern::Matches(a), Pattern::Matches(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Pattern {}
+
+impl Hash for Pattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Pattern::Contains(text) | Pattern::StartsWith(text) | Pattern::Line(text) => {
+                text.hash(state)
+            }
+            Pattern::Matches(predicate) => Arc::as_ptr(predicate).cast::<()>().hash(state),
+        }
+    }
+}
+
+/// The part of a log not yet matched while waiting for a service: the lines past the offset,
+/// and the last line read so far, which may still be incomplete.
+struct LogTail {
+    path: PathBuf,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl LogTail {
+    fn new(path: &Path) -> LogTail {
+        LogTail {
+            path: path.to_path_buf(),
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Reads what was appended to the log since the last call, and checks if any new line,
+    /// or the incomplete last one, matches the pattern.
+    fn matches(&mut self, pattern: &Pattern) -> bool {
+        let mut appended = Vec::new();
+        let read = File::open(&self.path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(self.offset))?;
+            file.read_to_end(&mut appended)
+        });
+        if read.is_err() {
+            return false;
+        }
+        self.offset += appended.len() as u64;
+        self.partial.extend_from_slice(&appended);
+        let matched = pattern.matches_any_line(&self.partial);
+        if let Some(end) = self.partial.iter().rposition(|&byte| byte == b'\n') {
+            self.partial.drain(..=end);
+        }
+        matched
+    }
+}
+
+impl WaitFor {
+    /// A TCP port on the local host accepts connections.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::service::WaitFor;
+    /// let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    /// let port = listener.local_addr().unwrap().port();
+    /// assert!(WaitFor::local_port(port).check(None));
+    /// drop(listener);
+    /// assert!(!WaitFor::local_port(port).check(None));
+    /// ```
+    pub fn local_port(port: u16) -> WaitFor {
+        WaitFor::TcpPort(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    /// Checks if the condition is satisfied. Output conditions are never satisfied
+    /// without a service.
+    pub fn check(&self, service: Option<&Service>) -> bool {
+        match self {
+            WaitFor::TcpPort(addr) => {
+                TcpStream::connect_timeout(addr, Duration::from_millis(100)).is_ok()
+            }
+            WaitFor::FileExists(path) => path.exists(),
+            WaitFor::Output(pattern) => service.is_some_and(|service| {
+                [service.stdout_log(), service.stderr_log()]
+                    .iter()
+                    .filter_map(|log| read_log(log).ok())
+                    .any(|log| pattern.matches_any_line(&log))
+            }),
+        }
+    }
+
+    /// Waits until the condition is satisfied for a process started without a
+    /// [`Service`](struct.Service.html), whose output is written to the `logs`. Fails if the
+    /// process exits or the timeout passes first. Output conditions only read what was
+    /// appended to the logs since the previous check.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::service::{Pattern, WaitFor};
+    /// # use std::process::Command;
+    /// # use std::time::Duration;
+    /// # use tempdir::TempDir;
+    /// let tmp = TempDir::new("wait").unwrap();
+    /// let log = tmp.path().join("server.log");
+    /// let mut child = Command::new("sh")
+    ///     .args(&["-c", "sleep 0.1; echo READY; exec sleep 10"])
+    ///     .stdout(std::fs::File::create(&log).unwrap())
+    ///     .spawn()
+    ///     .unwrap();
+    /// let ready = WaitFor::Output(Pattern::StartsWith(String::from("READY")));
+    /// ready.wait(&mut child, &[&log], Duration::from_secs(5)).unwrap();
+    /// child.kill().unwrap();
+    /// child.wait().unwrap();
+    ///
+    /// let mut child = Command::new("true").spawn().unwrap();
+    /// let missing = tmp.path().join("missing.log");
+    /// let err = ready.wait(&mut child, &[&missing], Duration::from_secs(5)).unwrap_err();
+    /// assert!(err.to_string().ends_with("before becoming ready"));
+    /// ```
+    pub fn wait(&self, child: &mut Child, logs: &[&Path], timeout: Duration) -> io::Result<()> {
+        let what = format!("Process {}", child.id());
+        self.wait_on(child, logs, &what, timeout)
+    }
+
+    fn wait_on(
+        &self,
+        child: &mut Child,
+        logs: &[&Path],
+        what: &str,
+        timeout: Duration,
+    ) -> io::Result<()> {
+        match self {
+            WaitFor::Output(pattern) => {
+                let mut tails: Vec<_> = logs.iter().map(|log| LogTail::new(log)).collect();
+                let ready = |_: &Child| tails.iter_mut().any(|tail| tail.matches(pattern));
+                poll_ready(child, |child| child, what, ready, timeout)
+            }
+            _ => poll_ready(child, |child| child, what, |_| self.check(None), timeout),
+        }
+    }
+}
+
+impl Service {
+    /// Starts the process as a service, writing its logs to `log_dir`, which is created if
+    /// it does not exist.
+    pub fn start<P: AsRef<Path>>(name: &str, process: &Process, log_dir: P) -> io::Result<Service> {
+        let log_dir = log_dir.as_ref();
+        std::fs::create_dir_all(log_dir)?;
+        let stdout_log = log_dir.join(format!("{}.stdout.log", name));
+        let stderr_log = log_dir.join(format!("{}.stderr.log", name));
+        let child = process
+            .redirected_command(&stdout_log, &stderr_log)?
+            .spawn()?;
+        Ok(Service {
+            name: String::from(name),
+            child,
+            stdout_log,
+            stderr_log,
+            grace_period: Duration::from_secs(5),
+        })
+    }
+
+    /// Sets how long [`stop`](#method.stop) waits for the service to terminate before
+    /// killing it. Defaults to 5 seconds.
+    pub fn grace_period(&mut self, grace_period: Duration) -> &mut Service {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// The name of the service.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The PID of the service process.
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// The path to the standard output log.
+    pub fn stdout_log(&self) -> &Path {
+        &self.stdout_log
+    }
+
+    /// The path to the standard error log.
+    pub fn stderr_log(&self) -> &Path {
+        &self.stderr_log
+    }
+
+    /// Checks if the service is still running.
+    pub fn is_running(&mut self) -> io::Result<bool> {
+        Ok(self.child.try_wait()?.is_none())
+    }
+
+    /// Repeatedly checks `ready` until it returns `true`. Fails if the service exits or the
+    /// timeout passes first.
+    pub fn wait_until<F>(&mut self, ready: F, timeout: Duration) -> io::Result<()>
+    where
+        F: FnMut(&Service) -> bool,
+    {
+        let what = format!("Service {}", self.name);
+        poll_ready(self, |service| &mut service.child, &what, ready, timeout)
+    }
+
+    /// Waits until the condition is satisfied. Fails if the service exits or the timeout
+    /// passes first. Output conditions only read what was appended to the logs since the
+    /// previous check; see [`WaitFor::wait`](enum.WaitFor.html#method.wait).
+    pub fn wait_for(&mut self, condition: &WaitFor, timeout: Duration) -> io::Result<()> {
+        let what = format!("Service {}", self.name);
+        let logs = [self.stdout_log.as_path(), self.stderr_log.as_path()];
+        condition.wait_on(&mut self.child, &logs, &what, timeout)
+    }
+
+    /// Stops the service and returns its exit status and logs.
+    ///
+    /// The service is first asked to terminate (with `SIGTERM` on Unix), and killed if it is
+    /// still running after the grace period.
+    pub fn stop(mut self) -> io::Result<ServiceLogs> {
+        let status = self.terminate()?;
+        Ok(ServiceLogs {
+            status,
+            stdout: read_log(&self.stdout_log)?,
+            stderr: read_log(&self.stderr_log)?,
+        })
+    }
+
+    fn terminate(&mut self) -> io::Result<ExitStatus> {
+        terminate(std::slice::from_mut(&mut self.child), self.grace_period)?;
+        self.child.wait()
+    }
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// Repeatedly checks `ready` until it returns `true`. Fails if the process of `target`, named
+/// `what` in the errors, exits or the timeout passes first.
+fn poll_ready<T, F>(
+    target: &mut T,
+    child: fn(&mut T) -> &mut Child,
+    what: &str,
+    mut ready: F,
+    timeout: Duration,
+) -> io::Result<()>
+where
+    F: FnMut(&T) -> bool,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        if ready(target) {
+            return Ok(());
+        }
+        if let Some(status) = child(target).try_wait()? {
+            return Err(io::Error::other(format!(
+                "{} exited ({}) before becoming ready",
+                what, status
+            )));
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} did not become ready in {:?}", what, timeout),
+            ));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Reads a log file, treating a missing one (e.g., of a silenced stream) as empty.
+fn read_log(path: &Path) -> io::Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    }
+}
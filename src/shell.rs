@@ -0,0 +1,235 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Quoting and splitting words according to the rules of a POSIX shell, and quoting for the
+//! Windows command interpreter.
+
+use std::borrow::Cow;
+
+/// A token of a shell command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+    /// A word with all quoting removed.
+    Word(String),
+    /// The `|` operator.
+    Pipe,
+    /// A redirection operator.
+    Redirect(Redirection),
+}
+
+/// A redirection operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Redirection {
+    /// `<`
+    Stdin,
+    /// `>`
+    Stdout,
+    /// `2>`
+    Stderr,
+    /// `2>>`
+    StderrAppend,
+    /// `2>&1`
+    StderrToStdout,
+}
+
+/// A shell that command lines are rendered for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Dialect {
+    /// A POSIX shell, such as `sh` or `bash`.
+    Posix,
+    /// The Windows command interpreter, `cmd.exe`.
+    Cmd,
+}
+
+impl Dialect {
+    /// Quotes a word if necessary.
+    pub(crate) fn quote(self, word: &str) -> Cow<'_, str> {
+        match self {
+            Dialect::Posix => shell_quote(word),
+            Dialect::Cmd => cmd_quote(word),
+        }
+    }
+
+    /// The device discarding everything written to it.
+    pub(crate) fn null_device(self) -> &'static str {
+        match self {
+            Dialect::Posix => "/dev/null",
+            Dialect::Cmd => "NUL",
+        }
+    }
+}
+
+/// Quotes a word for a POSIX shell, unless it consists only of characters that are never
+/// interpreted by the shell.
+pub(crate) fn shell_quote(word: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%^".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(format!("'{}'", word.replace('\'', "'\\''")))
+    }
+}
+
+/// Quotes a word for the Windows command interpreter (`cmd.exe`), unless it consists only of
+/// characters that are never interpreted. Quoted words follow the rules programs use to split
+/// their command line into arguments: quotes are escaped with a backslash, and so are the
+/// backslashes preceding them. Note that `cmd.exe` expands `%VAR%` even within quotes.
+pub(crate) fn cmd_quote(word: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./\\=:,+@#$~[]{}".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        return Cow::Borrowed(word);
+    }
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in word.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let escapes = if c == '"' {
+            2 * backslashes + 1
+        } else {
+            backslashes
+        };
+        quoted.extend(std::iter::repeat_n('\\', escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.extend(std::iter::repeat_n('\\', 2 * backslashes));
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Splits a command line into words and operators, following the quoting rules of a POSIX
+/// shell. Syntax that would require an actual shell to interpret, such as variable expansion,
+/// command substitution, or command lists, is rejected.
+pub(crate) fn shell_words(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    macro_rules! finish_word {
+        () => {
+            if in_word {
+                tokens.push(Token::Word(std::mem::take(&mut word)));
+                in_word = false;
+                quoted = false;
+            }
+        };
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => finish_word!(),
+            '\'' => {
+                in_word = true;
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(String::from("Unterminated single quote")),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c @ '$') | Some(c @ '`') | Some(c @ '"') | Some(c @ '\\') => {
+                                word.push(c)
+                            }
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(String::from("Unterminated double quote")),
+                        },
+                        Some(c @ '$') | Some(c @ '`') => {
+                            return Err(format!("Unsupported shell syntax: {}", c))
+                        }
+                        Some(c) => word.push(c),
+                        None => return Err(String::from("Unterminated double quote")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => {
+                    in_word = true;
+                    quoted = true;
+                    word.push(c);
+                }
+                None => return Err(String::from("Trailing backslash")),
+            },
+            '|' => {
+                finish_word!();
+                tokens.push(Token::Pipe);
+            }
+            '<' => {
+                finish_word!();
+                tokens.push(Token::Redirect(Redirection::Stdin));
+            }
+            '>' if in_word && !quoted && word == "2" => {
+                word.clear();
+                in_word = false;
+                let redirection = match chars.peek() {
+                    Some('>') => {
+                        chars.next();
+                        Redirection::StderrAppend
+                    }
+                    Some('&') => {
+                        chars.next();
+                        match chars.next() {
+                            Some('1') => Redirection::StderrToStdout,
+                            _ => return Err(String::from("Unsupported redirection: 2>&")),
+                        }
+                    }
+                    _ => Redirection::Stderr,
+                };
+                tokens.push(Token::Redirect(redirection));
+            }
+            '>' => {
+                finish_word!();
+                if let Some(&next @ ('>' | '&')) = chars.peek() {
+                    return Err(format!("Unsupported redirection: >{}", next));
+                }
+                tokens.push(Token::Redirect(Redirection::Stdout));
+            }
+            '$' | '`' | ';' | '&' | '(' | ')' => {
+                return Err(format!("Unsupported shell syntax: {}", c));
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
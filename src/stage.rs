@@ -0,0 +1,124 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Ready-made pipeline stages running common external tools.
+
+use super::compression::Codec;
+use super::process::Process;
+use std::io;
+
+/// A process compressing its standard input with gzip at the given level (1 to 9).
+///
+/// Uses `pigz`, which compresses in parallel and produces the same format, if available in
+/// `PATH`, and `gzip` otherwise. Fails if neither is found.
+///
+/// # Examples
+/// ```
+/// # use experiment::pipeline;
+/// # use experiment::process::{Process, ProcessPipeline};
+/// # use experiment::stage;
+/// let pipeline = pipeline!(
+///     Process::new("echo", &["compressed"]),
+///     stage::gzip_compress(6).unwrap(),
+///     stage::gzip_decompress().unwrap()
+/// );
+/// assert_eq!(pipeline.pipe().unwrap().output().unwrap().stdout, b"compressed\n".to_vec());
+/// ```
+pub fn gzip_compress(level: u32) -> io::Result<Process> {
+    required(Codec::Gzip(level).available_compressor())
+}
+
+/// A process decompressing gzip data from its standard input, using `pigz` or `gzip` like
+/// [`gzip_compress`](fn.gzip_compress.html).
+pub fn gzip_decompress() -> io::Result<Process> {
+    required(Codec::Gzip(6).available_decompressor())
+}
+
+/// A process compressing its standard input with `zstd` at the given level (1 to 19).
+/// Fails if `zstd` is not found in `PATH`.
+///
+/// # Examples
+/// ```
+/// # use experiment::pipeline;
+/// # use experiment::process::{Process, ProcessPipeline};
+/// # use experiment::stage;
+/// # use experiment::Verbosity::Verbose;
+/// let compress = stage::zstd_compress(19).unwrap();
+/// assert_eq!(compress.display(Verbose).to_string(), "zstd -q -c -19");
+/// let pipeline = pipeline!(
+///     Process::new("echo", &["compressed"]),
+///     compress,
+///     stage::zstd_decompress().unwrap()
+/// );
+/// assert_eq!(pipeline.pipe().unwrap().output().unwrap().stdout, b"compressed\n".to_vec());
+/// ```
+pub fn zstd_compress(level: i32) -> io::Result<Process> {
+    required(Codec::Zstd(level).available_compressor())
+}
+
+/// A process decompressing zstd data from its standard input.
+/// Fails if `zstd` is not found in `PATH`.
+pub fn zstd_decompress() -> io::Result<Process> {
+    required(Codec::Zstd(3).available_decompressor())
+}
+
+/// A process compressing its standard input with the given codec, or `None` for
+/// `Codec::None`. See [`Codec::available_compressor`](../compression/enum.Codec.html#method.available_compressor).
+///
+/// # Examples
+/// ```
+/// # use experiment::compression::Codec;
+/// # use experiment::stage;
+/// assert!(stage::compress(Codec::Gzip(9)).unwrap().is_some());
+/// assert!(stage::compress(Codec::None).unwrap().is_none());
+/// ```
+pub fn compress(codec: Codec) -> io::Result<Option<Process>> {
+    codec.available_compressor()
+}
+
+/// A process decompressing its standard input with the given codec, or `None` for
+/// `Codec::None`.
+///
+/// # Examples
+/// ```
+/// # use experiment::compression::Codec;
+/// # use experiment::pipeline;
+/// # use experiment::process::{Process, ProcessPipeline};
+/// # use experiment::stage;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("stage").unwrap();
+/// let path = tmp.path().join("corpus.txt");
+/// std::fs::write(&path, "a\nb\n").unwrap();
+/// let path = Codec::Zstd(3).compress_file(&path).unwrap();
+/// let mut pipeline = ProcessPipeline::new(stage::decompress(Codec::from_path(&path)).unwrap());
+/// pipeline.push(Process::new("wc", &["-l"])).stdin_file(&path);
+/// let output = pipeline.pipe().unwrap().output().unwrap();
+/// assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2");
+/// ```
+pub fn decompress(codec: Codec) -> io::Result<Option<Process>> {
+    codec.available_decompressor()
+}
+
+/// Unwraps the process of a codec other than `Codec::None`.
+fn required(process: io::Result<Option<Process>>) -> io::Result<Process> {
+    process.map(|process| process.expect("Compressing codecs have processes"))
+}
@@ -0,0 +1,156 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Restarting processes that crash.
+
+use super::process::Process;
+use std::io;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+/// Runs a process, restarting it with exponential backoff whenever it exits unsuccessfully,
+/// until it succeeds or the restart budget is exhausted.
+///
+/// # Examples
+/// ```
+/// # use experiment::process::Process;
+/// # use experiment::supervisor::Supervisor;
+/// # use tempdir::TempDir;
+/// # use std::time::Duration;
+/// let tmp = TempDir::new("supervisor").unwrap();
+/// let marker = tmp.path().join("crashed-once");
+/// // Fails on the first run, and succeeds on the second.
+/// let script = format!("test -e {0} || (touch {0}; exit 1)", marker.display());
+/// let mut supervisor = Supervisor::new(Process::new("sh", &["-c", &script]));
+/// supervisor.max_restarts(3).backoff(Duration::from_millis(1));
+/// let report = supervisor.run().unwrap();
+/// assert!(report.status.success());
+/// assert_eq!(report.crashes.len(), 1);
+///
+/// let mut supervisor = Supervisor::new(Process::new("false", &Vec::<&str>::new()));
+/// supervisor.max_restarts(2).backoff(Duration::from_millis(1));
+/// assert!(supervisor.run().is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Supervisor {
+    process: Process,
+    max_restarts: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+/// A record of a single unsuccessful run of a supervised process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Crash {
+    /// The exit status of the run.
+    pub status: ExitStatus,
+    /// How long the process ran before exiting.
+    pub uptime: Duration,
+}
+
+/// The outcome of a supervised process that eventually succeeded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SupervisorReport {
+    /// The exit status of the successful run.
+    pub status: ExitStatus,
+    /// All unsuccessful runs preceding it.
+    pub crashes: Vec<Crash>,
+}
+
+impl Supervisor {
+    /// Creates a supervisor that restarts the process up to 3 times, waiting 1 second before
+    /// the first restart, and at most 1 minute before any restart.
+    pub fn new(process: Process) -> Supervisor {
+        Supervisor {
+            process,
+            max_restarts: 3,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the maximum number of restarts.
+    pub fn max_restarts(&mut self, max_restarts: usize) -> &mut Supervisor {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    /// Sets the delay before the first restart; each following delay is twice as long, up to
+    /// the [maximum](#method.max_backoff).
+    pub fn backoff(&mut self, backoff: Duration) -> &mut Supervisor {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the longest delay before a restart, which caps the exponential backoff.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::process::Process;
+    /// # use experiment::supervisor::Supervisor;
+    /// # use std::time::{Duration, Instant};
+    /// let mut supervisor = Supervisor::new(Process::new("false", &Vec::<&str>::new()));
+    /// supervisor
+    ///     .max_restarts(4)
+    ///     .backoff(Duration::from_millis(20))
+    ///     .max_backoff(Duration::from_millis(20));
+    /// let start = Instant::now();
+    /// assert!(supervisor.run().is_err());
+    /// // Without the cap, the delays would add up to 20 + 40 + 80 + 160 ms.
+    /// assert!(start.elapsed() < Duration::from_millis(300));
+    /// ```
+    pub fn max_backoff(&mut self, max_backoff: Duration) -> &mut Supervisor {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Runs the process until it succeeds. Fails once the process has crashed more times than
+    /// it may be restarted, or if it cannot be started at all.
+    pub fn run(&self) -> io::Result<SupervisorReport> {
+        let mut crashes = Vec::new();
+        let mut delay = self.backoff.min(self.max_backoff);
+        loop {
+            let start = Instant::now();
+            let status = self.process.execute()?;
+            if status.success() {
+                return Ok(SupervisorReport { status, crashes });
+            }
+            crashes.push(Crash {
+                status,
+                uptime: start.elapsed(),
+            });
+            if crashes.len() > self.max_restarts {
+                return Err(io::Error::other(format!(
+                    "{} crashed {} times, last with {}; giving up",
+                    self.process.display(crate::Verbosity::Brief(3)),
+                    crashes.len(),
+                    status
+                )));
+            }
+            std::thread::sleep(delay);
+            delay = delay
+                .checked_mul(2)
+                .unwrap_or(Duration::MAX)
+                .min(self.max_backoff);
+        }
+    }
+}
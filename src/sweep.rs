@@ -0,0 +1,1100 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Sweeps over the values of experiment parameters.
+
+use super::experiment::Experiment;
+#[cfg(feature = "executor")]
+use super::experiment::ExperimentReport;
+use super::expr::Expr;
+use super::rng::SplitMix64;
+use super::template::CommandTemplate;
+use super::OverwritePolicy;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A set of parameters, each with a list of values, whose cartesian product defines the
+/// combinations to run.
+///
+/// # Examples
+/// ```
+/// # use experiment::sweep::Grid;
+/// # use experiment::template::CommandTemplate;
+/// # use experiment::OverwritePolicy;
+/// # use experiment::Verbosity::Verbose;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("sweep").unwrap();
+/// let mut grid = Grid::new();
+/// grid.parameter("algorithm", &["maxscore", "wand"])
+///     .parameter("k", &[10, 1000])
+///     .output_dir(tmp.path(), OverwritePolicy::Fail);
+/// assert_eq!(grid.len(), 4);
+/// let combinations: Vec<_> = grid.combinations().iter().map(|c| c.to_string()).collect();
+/// assert_eq!(
+///     combinations,
+///     vec![
+///         "algorithm=maxscore,k=10",
+///         "algorithm=maxscore,k=1000",
+///         "algorithm=wand,k=10",
+///         "algorithm=wand,k=1000",
+///     ]
+/// );
+///
+/// let query = CommandTemplate::new("sh", &["-c", "echo {algorithm} {k} > $EXPERIMENT_OUTPUT_DIR/run"]);
+/// let experiments = grid.experiments("queries", &[("query", query)]).unwrap();
+/// assert_eq!(experiments[1].name(), "queries[algorithm=maxscore,k=1000]");
+/// for experiment in &experiments {
+///     assert!(experiment.run().unwrap().success());
+/// }
+/// let run = tmp.path().join("algorithm=wand,k=10").join("run");
+/// assert_eq!(std::fs::read_to_string(run).unwrap(), "wand 10\n");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Grid {
+    parameters: Vec<(String, Vec<Value>)>,
+    output_dir: Option<(PathBuf, OverwritePolicy)>,
+    sweep: Sweep,
+    excluded: Vec<Vec<(String, String)>>,
+    included: Vec<Vec<(String, String)>>,
+    derived: Vec<(String, Expr)>,
+}
+
+/// A strategy of choosing which combinations of a [`Grid`](struct.Grid.html) to run.
+///
+/// # Examples
+/// ```
+/// # use experiment::sweep::Sweep;
+/// assert_eq!(Sweep::default(), Sweep::Full);
+/// assert_eq!(Sweep::random(10, 42).to_string(), "random(n=10,seed=42)");
+/// assert_eq!(Sweep::random(10, 42).seed(), Some(42));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Sweep {
+    /// All combinations.
+    #[default]
+    Full,
+    /// `n` distinct combinations sampled uniformly at random, reproducibly for a given seed.
+    Random {
+        /// The number of combinations.
+        n: usize,
+        /// The seed of the random number generator.
+        seed: u64,
+    },
+    /// `n` combinations forming a Latin hypercube sample: the range of each parameter is
+    /// split into `n` equal strata, following the order in which its values were declared,
+    /// and each stratum is sampled exactly once. This covers every parameter evenly, unlike
+    /// plain random sampling with the same number of runs. Combinations may repeat if a
+    /// parameter has fewer than `n` values.
+    LatinHypercube {
+        /// The number of combinations.
+        n: usize,
+        /// The seed of the random number generator.
+        seed: u64,
+    },
+    /// Combinations covering every pair of values of any two parameters at least once (a
+    /// pairwise covering array). The number of combinations grows with the product of the
+    /// two largest numbers of values, rather than of all of them.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::{Grid, Sweep};
+    /// let mut grid = Grid::new();
+    /// for name in &["algorithm", "quantization", "compression", "threads"] {
+    ///     grid.parameter(name, 0..3);
+    /// }
+    /// grid.sweep(Sweep::Pairwise);
+    /// let combinations = grid.combinations();
+    /// assert!(combinations.len() < 15, "{} of 81", combinations.len());
+    /// let names = ["algorithm", "quantization", "compression", "threads"];
+    /// for (idx, first) in names.iter().enumerate() {
+    ///     for second in &names[idx + 1..] {
+    ///         for a in 0..3 {
+    ///             for b in 0..3 {
+    ///                 let (a, b) = (a.to_string(), b.to_string());
+    ///                 assert!(combinations.iter().any(|c| {
+    ///                     c.get(first) == Some(a.as_str()) && c.get(second) == Some(b.as_str())
+    ///                 }));
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    Pairwise,
+}
+
+impl Sweep {
+    /// Samples `n` combinations with the given seed; see [`Sweep::Random`](#variant.Random).
+    pub fn random(n: usize, seed: u64) -> Sweep {
+        Sweep::Random { n, seed }
+    }
+
+    /// Samples a Latin hypercube of `n` combinations with the given seed; see
+    /// [`Sweep::LatinHypercube`](#variant.LatinHypercube).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::{Grid, Sweep};
+    /// let mut grid = Grid::new();
+    /// grid.parameter("k", 1..=100)
+    ///     .parameter("mu", (1..=10).map(|mu| mu * 100))
+    ///     .sweep(Sweep::latin_hypercube(10, 42));
+    /// let sample = grid.combinations();
+    /// assert_eq!(sample.len(), 10);
+    /// // Each of the ten strata of `k` is sampled exactly once.
+    /// let mut strata: Vec<_> = sample
+    ///     .iter()
+    ///     .map(|c| (c.get("k").unwrap().parse::<usize>().unwrap() - 1) / 10)
+    ///     .collect();
+    /// strata.sort();
+    /// assert_eq!(strata, (0..10).collect::<Vec<_>>());
+    /// // With as many strata as values, each value of `mu` is used once.
+    /// let mut mus: Vec<_> = sample.iter().map(|c| c.get("mu").unwrap()).collect();
+    /// mus.sort();
+    /// mus.dedup();
+    /// assert_eq!(mus.len(), 10);
+    /// ```
+    pub fn latin_hypercube(n: usize, seed: u64) -> Sweep {
+        Sweep::LatinHypercube { n, seed }
+    }
+
+    /// The seed of the random number generator, if the strategy is random, so that it can be
+    /// recorded along with the results.
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Sweep::Full | Sweep::Pairwise => None,
+            Sweep::Random { seed, .. } | Sweep::LatinHypercube { seed, .. } => Some(*seed),
+        }
+    }
+}
+
+impl fmt::Display for Sweep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sweep::Full => write!(f, "full"),
+            Sweep::Random { n, seed } => write!(f, "random(n={},seed={})", n, seed),
+            Sweep::LatinHypercube { n, seed } => {
+                write!(f, "latin-hypercube(n={},seed={})", n, seed)
+            }
+            Sweep::Pairwise => write!(f, "pairwise"),
+        }
+    }
+}
+
+/// Values of all parameters of a [`Grid`](struct.Grid.html) in a single run, in the order
+/// the parameters were declared.
+///
+/// Displayed as comma-separated `name=value` pairs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Combination {
+    values: Vec<(String, String)>,
+    typed: Vec<Value>,
+}
+
+/// A typed value of a parameter, displayed the way it is passed to commands.
+#[derive(Clone, Debug)]
+pub enum Value {
+    /// An integer.
+    Integer(i64),
+    /// A floating point number.
+    Float(f64),
+    /// A boolean, displayed as `true` or `false`.
+    Boolean(bool),
+    /// A string, such as a name of a category.
+    String(String),
+}
+
+/// The values of a typed parameter of a [`Grid`](struct.Grid.html).
+///
+/// # Examples
+/// ```
+/// # use experiment::sweep::{Domain, Value};
+/// let values = |domain: Domain| -> Vec<String> {
+///     domain.values().iter().map(|value| value.to_string()).collect()
+/// };
+/// assert_eq!(values(Domain::range(10, 40, 10)), vec!["10", "20", "30", "40"]);
+/// assert_eq!(values(Domain::linspace(0.0, 1.0, 5)), vec!["0", "0.25", "0.5", "0.75", "1"]);
+/// assert_eq!(values(Domain::logspace(1.0, 100.0, 3)), vec!["1", "10", "100"]);
+/// assert_eq!(values(Domain::boolean()), vec!["false", "true"]);
+/// assert_eq!(values(Domain::categorical(&["bm25", "ql"])), vec!["bm25", "ql"]);
+/// assert_eq!(Domain::range(1, 3, 1).values()[2], Value::Integer(3));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Domain {
+    /// The listed values.
+    Values(Vec<Value>),
+    /// Integers from `start` to `end`, inclusive, every `step`.
+    Range {
+        /// The first value.
+        start: i64,
+        /// The upper bound, included if reached.
+        end: i64,
+        /// The positive difference between consecutive values.
+        step: i64,
+    },
+    /// `n` floats evenly spaced from `start` to `end`, inclusive.
+    Linspace {
+        /// The first value.
+        start: f64,
+        /// The last value.
+        end: f64,
+        /// The number of values.
+        n: usize,
+    },
+    /// `n` floats evenly spaced on a logarithmic scale from `start` to `end`, inclusive.
+    Logspace {
+        /// The first value, which must be positive.
+        start: f64,
+        /// The last value, which must be positive.
+        end: f64,
+        /// The number of values.
+        n: usize,
+    },
+}
+
+/// Runs the experiments of many sweep points on a pool of worker threads, each taking the
+/// next experiment as soon as it is done with the previous one, so that thousands of short
+/// configurations keep all workers busy.
+///
+/// If a scratch directory is set, each worker gets its own empty subdirectory
+/// `worker-<n>` of it for every experiment it runs, passed to its stages through the
+/// [`EXPERIMENT_SCRATCH_DIR`](../context/constant.SCRATCH_DIR_VAR.html) variable.
+///
+/// # Examples
+/// ```
+/// # use experiment::sweep::{Grid, SweepExecutor};
+/// # use experiment::template::CommandTemplate;
+/// # use experiment::OverwritePolicy;
+/// # use tempdir::TempDir;
+/// let tmp = TempDir::new("sweep").unwrap();
+/// let mut grid = Grid::new();
+/// grid.parameter("k", 1..=20)
+///     .output_dir(tmp.path().join("results"), OverwritePolicy::Fail);
+/// let query = CommandTemplate::new(
+///     "sh",
+///     &["-c", "echo {k} > $EXPERIMENT_SCRATCH_DIR/k && mv $EXPERIMENT_SCRATCH_DIR/k $EXPERIMENT_OUTPUT_DIR"],
+/// );
+/// let experiments = grid.experiments("queries", &[("query", query)]).unwrap();
+/// let reports = SweepExecutor::new(4)
+///     .scratch_dir(tmp.path().join("scratch"))
+///     .run(&experiments);
+/// assert!(reports.iter().all(|report| report.as_ref().unwrap().success()));
+/// let k = tmp.path().join("results").join("k=7").join("k");
+/// assert_eq!(std::fs::read_to_string(k).unwrap(), "7\n");
+/// assert!(tmp.path().join("scratch").join("worker-0").is_dir());
+/// ```
+#[cfg(feature = "executor")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SweepExecutor {
+    workers: usize,
+    scratch_dir: Option<PathBuf>,
+}
+
+impl Grid {
+    /// Creates a grid with no parameters, which has a single, empty combination.
+    pub fn new() -> Grid {
+        Grid::default()
+    }
+
+    /// Declares a parameter with its values, which are treated as strings; see
+    /// [`domain`](#method.domain) for typed parameters. Parameters declared first vary the
+    /// slowest.
+    pub fn parameter<I, V>(&mut self, name: &str, values: I) -> &mut Grid
+    where
+        I: IntoIterator<Item = V>,
+        V: ToString,
+    {
+        self.parameters.push((
+            String::from(name),
+            values
+                .into_iter()
+                .map(|value| Value::String(value.to_string()))
+                .collect(),
+        ));
+        self
+    }
+
+    /// Declares a typed parameter with the values of the domain, which can be retrieved
+    /// from the combinations with [`Combination::value`](struct.Combination.html#method.value).
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::{Domain, Grid, Value};
+    /// let mut grid = Grid::new();
+    /// grid.domain("k", Domain::range(10, 20, 10))
+    ///     .domain("quantized", Domain::boolean());
+    /// let combinations = grid.combinations();
+    /// assert_eq!(combinations[1].to_string(), "k=10,quantized=true");
+    /// assert_eq!(combinations[1].value("k"), Some(&Value::Integer(10)));
+    /// assert_eq!(combinations[1].value("quantized"), Some(&Value::Boolean(true)));
+    /// ```
+    pub fn domain(&mut self, name: &str, domain: Domain) -> &mut Grid {
+        self.parameters.push((String::from(name), domain.values()));
+        self
+    }
+
+    /// Makes each combination write its outputs to its own subdirectory of `dir`, named after
+    /// the [directory name](struct.Combination.html#method.dir_name) of the combination.
+    pub fn output_dir<P: AsRef<Path>>(&mut self, dir: P, policy: OverwritePolicy) -> &mut Grid {
+        self.output_dir = Some((dir.as_ref().to_path_buf(), policy));
+        self
+    }
+
+    /// Sets the strategy of choosing the combinations to run; all of them by default.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::{Grid, Sweep};
+    /// let mut grid = Grid::new();
+    /// grid.parameter("a", 0..10)
+    ///     .parameter("b", 0..10)
+    ///     .parameter("c", 0..10)
+    ///     .sweep(Sweep::random(5, 42));
+    /// let sample = grid.combinations();
+    /// assert_eq!(sample.len(), 5);
+    /// assert_eq!(grid.len(), 5);
+    /// assert_eq!(sample, grid.combinations());
+    /// grid.sweep(Sweep::random(5, 7));
+    /// assert_ne!(sample, grid.combinations());
+    /// grid.sweep(Sweep::random(5000, 7));
+    /// assert_eq!(grid.combinations().len(), 1000);
+    /// ```
+    pub fn sweep(&mut self, sweep: Sweep) -> &mut Grid {
+        self.sweep = sweep;
+        self
+    }
+
+    /// Returns the strategy of choosing the combinations to run.
+    pub fn get_sweep(&self) -> Sweep {
+        self.sweep
+    }
+
+    /// The number of combinations that are run.
+    pub fn len(&self) -> usize {
+        if !self.excluded.is_empty() || !self.included.is_empty() {
+            return self.combinations().len();
+        }
+        match self.sweep {
+            Sweep::Full => self.size(),
+            Sweep::Random { n, .. } => n.min(self.size()),
+            Sweep::LatinHypercube { n, .. } if self.size() > 0 => n,
+            Sweep::LatinHypercube { .. } => 0,
+            Sweep::Pairwise => self.covering_array().len(),
+        }
+    }
+
+    /// Checks if there are no combinations to run.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Excludes all combinations that have all of the given values, e.g., `wand` as the
+    /// `algorithm` and `false` as `quantized`.
+    ///
+    /// Like the rules of a GitHub Actions matrix, exclusions are applied before
+    /// [inclusions](#method.include), to the combinations chosen by the
+    /// [`sweep`](#method.sweep) strategy; a sample may therefore contain fewer combinations
+    /// than requested.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::Grid;
+    /// let mut grid = Grid::new();
+    /// grid.parameter("algorithm", &["maxscore", "wand"])
+    ///     .parameter("quantized", &[false, true])
+    ///     .exclude(vec![("algorithm", "wand"), ("quantized", "false")]);
+    /// let combinations: Vec<_> = grid.combinations().iter().map(|c| c.to_string()).collect();
+    /// assert_eq!(
+    ///     combinations,
+    ///     vec![
+    ///         "algorithm=maxscore,quantized=false",
+    ///         "algorithm=maxscore,quantized=true",
+    ///         "algorithm=wand,quantized=true",
+    ///     ]
+    /// );
+    /// ```
+    pub fn exclude<I, K, V>(&mut self, values: I) -> &mut Grid
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: ToString,
+    {
+        self.excluded.push(rule(values));
+        self
+    }
+
+    /// Adds values to the combinations, or adds a new combination, following the rules of a
+    /// GitHub Actions matrix: the values of declared parameters select the combinations
+    /// that have them, and the remaining values are added to all selected combinations. If
+    /// no combination is selected, the values are added as a new combination.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::Grid;
+    /// let mut grid = Grid::new();
+    /// grid.parameter("algorithm", &["maxscore", "wand"])
+    ///     .parameter("k", &[10])
+    ///     .include(vec![("algorithm", "wand"), ("block_size", "64")])
+    ///     .include(vec![("algorithm", "ranked-and"), ("k", "1000")]);
+    /// let combinations: Vec<_> = grid.combinations().iter().map(|c| c.to_string()).collect();
+    /// assert_eq!(
+    ///     combinations,
+    ///     vec![
+    ///         "algorithm=maxscore,k=10",
+    ///         "algorithm=wand,k=10,block_size=64",
+    ///         "algorithm=ranked-and,k=1000",
+    ///     ]
+    /// );
+    /// ```
+    pub fn include<I, K, V>(&mut self, values: I) -> &mut Grid
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: ToString,
+    {
+        self.included.push(rule(values));
+        self
+    }
+
+    /// Declares a parameter computed from the values of the other parameters of each
+    /// combination, after the [inclusions](#method.include) are applied.
+    ///
+    /// The expression consists of integer and float literals, names of parameters declared
+    /// before, parentheses, and the `+`, `-`, `*`, `/`, and `%` operators; arithmetic on two
+    /// integers yields an integer, with division rounding toward zero. String values are
+    /// parsed as numbers. The name `cores` refers to the number of available CPUs unless a
+    /// parameter is named so. A string literal in double quotes may contain `{name}`
+    /// placeholders, which are replaced with the values of the parameters.
+    ///
+    /// The parameter is left out of the [combinations](#method.combinations) for which the
+    /// expression cannot be evaluated, e.g., because of a division by zero or a value that is
+    /// not a number; [`try_combinations`](#method.try_combinations) and
+    /// [`experiments`](#method.experiments) report such errors instead.
+    ///
+    /// Fails with `InvalidInput` if the expression is malformed or refers to an unknown name.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::{Domain, Grid, Value};
+    /// let mut grid = Grid::new();
+    /// grid.parameter("algorithm", &["wand"])
+    ///     .domain("k", Domain::range(10, 20, 10))
+    ///     .domain("ratio", Domain::Values(vec![Value::Float(0.5)]));
+    /// grid.derive("depth", "k * 2 + 1").unwrap()
+    ///     .derive("threads", "cores / 2").unwrap()
+    ///     .derive("weighted", "k * ratio").unwrap()
+    ///     .derive("output", "\"{algorithm}-{k}.run\"").unwrap();
+    /// let combinations = grid.combinations();
+    /// assert_eq!(combinations[1].value("depth"), Some(&Value::Integer(41)));
+    /// assert_eq!(combinations[1].value("weighted"), Some(&Value::Float(10.0)));
+    /// assert_eq!(combinations[1].get("output"), Some("wand-20.run"));
+    /// assert!(combinations[1].get("threads").is_some());
+    ///
+    /// let err = grid.derive("shards", "k / threds").unwrap_err();
+    /// assert_eq!(err.to_string(), "Unknown name `threds` in `k / threds`");
+    /// let err = grid.derive("shards", "k / (2").unwrap_err();
+    /// assert_eq!(err.to_string(), "Unclosed `(` in `k / (2`");
+    /// ```
+    pub fn derive(&mut self, name: &str, expression: &str) -> io::Result<&mut Grid> {
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidInput, err);
+        let expr = Expr::parse(expression).map_err(invalid)?;
+        let known = |name: &str| {
+            name == "cores"
+                || self.parameters.iter().any(|(declared, _)| declared == name)
+                || self.derived.iter().any(|(derived, _)| derived == name)
+        };
+        if let Some(unknown) = expr.names().into_iter().find(|name| !known(name)) {
+            return Err(invalid(format!(
+                "Unknown name `{}` in `{}`",
+                unknown, expression
+            )));
+        }
+        self.derived.push((String::from(name), expr));
+        Ok(self)
+    }
+
+    /// The combinations of the parameter values chosen by the [`sweep`](#method.sweep)
+    /// strategy, in the order of the full grid, except for Latin hypercube samples, which are
+    /// returned in the order they were drawn, and covering arrays, in the order they were
+    /// built. [Exclusions](#method.exclude) and [inclusions](#method.include) are applied
+    /// afterwards, followed by [derived parameters](#method.derive).
+    pub fn combinations(&self) -> Vec<Combination> {
+        self.derived_combinations()
+            .into_iter()
+            .map(|(combination, _)| combination)
+            .collect()
+    }
+
+    /// Same as [`combinations`](#method.combinations), but fails with `InvalidData` if a
+    /// [derived parameter](#method.derive) cannot be evaluated for any of them.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::{Domain, Grid};
+    /// let mut grid = Grid::new();
+    /// grid.domain("k", Domain::range(0, 20, 10));
+    /// grid.derive("shards", "100 / k").unwrap();
+    /// assert_eq!(grid.combinations()[0].get("shards"), None);
+    /// let err = grid.try_combinations().unwrap_err();
+    /// assert_eq!(err.to_string(), "k=0: derived `shards`: Division by zero");
+    /// ```
+    pub fn try_combinations(&self) -> io::Result<Vec<Combination>> {
+        self.derived_combinations()
+            .into_iter()
+            .map(|(combination, error)| match error {
+                Some(err) => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", combination, err),
+                )),
+                None => Ok(combination),
+            })
+            .collect()
+    }
+
+    /// The combinations with the derived parameters, each with the first error of evaluating
+    /// them, if any.
+    fn derived_combinations(&self) -> Vec<(Combination, Option<String>)> {
+        let mut combinations: Vec<Combination> = self
+            .chosen()
+            .into_iter()
+            .filter(|combination| {
+                !self.excluded.iter().any(|rule| {
+                    rule.iter()
+                        .all(|(name, value)| combination.get(name) == Some(value))
+                })
+            })
+            .collect();
+        let declared = |name: &str| self.parameters.iter().any(|(declared, _)| declared == name);
+        for rule in &self.included {
+            let mut selected = false;
+            for combination in &mut combinations {
+                let selects = rule
+                    .iter()
+                    .filter(|(name, _)| declared(name))
+                    .all(|(name, value)| combination.get(name) == Some(value));
+                if selects {
+                    selected = true;
+                    for (name, value) in rule.iter().filter(|(name, _)| !declared(name)) {
+                        combination.set(name, Value::String(value.clone()));
+                    }
+                }
+            }
+            if !selected {
+                combinations.push(Combination {
+                    values: rule.clone(),
+                    typed: rule
+                        .iter()
+                        .map(|(_, value)| Value::String(value.clone()))
+                        .collect(),
+                });
+            }
+        }
+        combinations
+            .into_iter()
+            .map(|mut combination| {
+                let mut error = None;
+                for (name, expr) in &self.derived {
+                    let lookup = |name: &str| match combination.value(name) {
+                        Some(value) => Some(value.clone()),
+                        None if name == "cores" => Some(Value::Integer(cores())),
+                        None => None,
+                    };
+                    match expr.evaluate(&lookup) {
+                        Ok(value) => combination.set(name, value),
+                        Err(err) => {
+                            error.get_or_insert(format!("derived `{}`: {}", name, err));
+                        }
+                    }
+                }
+                (combination, error)
+            })
+            .collect()
+    }
+
+    /// The combinations chosen by the sweep strategy.
+    fn chosen(&self) -> Vec<Combination> {
+        match self.sweep {
+            Sweep::Full => (0..self.size())
+                .map(|idx| self.combination_at(idx))
+                .collect(),
+            Sweep::Random { n, seed } => {
+                let size = self.size();
+                if n >= size {
+                    return (0..size).map(|idx| self.combination_at(idx)).collect();
+                }
+                // Floyd's algorithm samples distinct indices without enumerating the grid.
+                let mut rng = SplitMix64::new(seed);
+                let mut sample = std::collections::BTreeSet::new();
+                for bound in size - n..size {
+                    let idx = rng.below(bound as u64 + 1) as usize;
+                    if !sample.insert(idx) {
+                        sample.insert(bound);
+                    }
+                }
+                sample
+                    .into_iter()
+                    .map(|idx| self.combination_at(idx))
+                    .collect()
+            }
+            Sweep::Pairwise => self
+                .covering_array()
+                .iter()
+                .map(|row| self.combination(row))
+                .collect(),
+            Sweep::LatinHypercube { n, seed } => {
+                if self.size() == 0 {
+                    return Vec::new();
+                }
+                let mut rng = SplitMix64::new(seed);
+                let columns: Vec<Vec<usize>> = self
+                    .parameters
+                    .iter()
+                    .map(|(_, values)| {
+                        let mut strata: Vec<usize> = (0..n).collect();
+                        rng.shuffle(&mut strata);
+                        strata
+                            .into_iter()
+                            .map(|stratum| {
+                                let point = (stratum as f64 + rng.unit()) / n as f64;
+                                ((point * values.len() as f64) as usize).min(values.len() - 1)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                (0..n)
+                    .map(|row| {
+                        let indices: Vec<usize> =
+                            columns.iter().map(|column| column[row]).collect();
+                        self.combination(&indices)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Builds a pairwise covering array with the In-Parameter-Order strategy: starts with all
+    /// pairs of the first two parameters, and adds each following parameter by first
+    /// extending the existing rows with the values covering the most new pairs, and then
+    /// adding rows for the pairs still missing. Returns the indices of the values.
+    fn covering_array(&self) -> Vec<Vec<usize>> {
+        let sizes: Vec<usize> = self
+            .parameters
+            .iter()
+            .map(|(_, values)| values.len())
+            .collect();
+        if sizes.len() <= 2 || sizes.contains(&0) {
+            return (0..self.size()).map(|idx| self.indices(idx)).collect();
+        }
+        let mut rows: Vec<Vec<Option<usize>>> = (0..sizes[0])
+            .flat_map(|first| (0..sizes[1]).map(move |second| vec![Some(first), Some(second)]))
+            .collect();
+        for (param, &size) in sizes.iter().enumerate().skip(2) {
+            let mut uncovered = std::collections::BTreeSet::new();
+            for (other, &other_size) in sizes[..param].iter().enumerate() {
+                for other_value in 0..other_size {
+                    for value in 0..size {
+                        uncovered.insert((other, other_value, value));
+                    }
+                }
+            }
+            for row in &mut rows {
+                let covered_by = |value: usize| -> Vec<(usize, usize, usize)> {
+                    row.iter()
+                        .enumerate()
+                        .filter_map(|(other, other_value)| {
+                            other_value.map(|other_value| (other, other_value, value))
+                        })
+                        .filter(|pair| uncovered.contains(pair))
+                        .collect()
+                };
+                let mut best = (0, covered_by(0));
+                for value in 1..size {
+                    let pairs = covered_by(value);
+                    if pairs.len() > best.1.len() {
+                        best = (value, pairs);
+                    }
+                }
+                for pair in &best.1 {
+                    uncovered.remove(pair);
+                }
+                row.push(Some(best.0));
+            }
+            for (other, other_value, value) in uncovered {
+                let free = rows
+                    .iter_mut()
+                    .find(|row| row[param] == Some(value) && row[other].is_none());
+                match free {
+                    Some(row) => row[other] = Some(other_value),
+                    None => {
+                        let mut row = vec![None; param + 1];
+                        row[other] = Some(other_value);
+                        row[param] = Some(value);
+                        rows.push(row);
+                    }
+                }
+            }
+        }
+        rows.into_iter()
+            .map(|row| row.into_iter().map(|value| value.unwrap_or(0)).collect())
+            .collect()
+    }
+
+    /// The number of all combinations, saturating at `usize::MAX`.
+    fn size(&self) -> usize {
+        self.parameters.iter().fold(1, |size: usize, (_, values)| {
+            size.saturating_mul(values.len())
+        })
+    }
+
+    /// The combination at the given position in the full grid.
+    fn combination_at(&self, idx: usize) -> Combination {
+        self.combination(&self.indices(idx))
+    }
+
+    /// The indices of the values of the combination at the given position in the full grid.
+    fn indices(&self, mut idx: usize) -> Vec<usize> {
+        let mut indices = Vec::with_capacity(self.parameters.len());
+        for (_, values) in self.parameters.iter().rev() {
+            indices.push(idx % values.len());
+            idx /= values.len();
+        }
+        indices.reverse();
+        indices
+    }
+
+    /// The combination of the values at the given indices.
+    fn combination(&self, indices: &[usize]) -> Combination {
+        let typed: Vec<Value> = self
+            .parameters
+            .iter()
+            .zip(indices)
+            .map(|((_, values), &idx)| values[idx].clone())
+            .collect();
+        Combination {
+            values: self
+                .parameters
+                .iter()
+                .zip(&typed)
+                .map(|((name, _), value)| (name.clone(), value.to_string()))
+                .collect(),
+            typed,
+        }
+    }
+
+    /// Creates an experiment for each combination, with one stage per template instantiated
+    /// with the values of the combination. The experiments are named `name[<combination>]`,
+    /// have the values set as their parameters, and write to their own subdirectories if the
+    /// [`output_dir`](#method.output_dir) is set.
+    ///
+    /// Fails with `InvalidInput` if any template refers to an unknown parameter, and with
+    /// `InvalidData` if a derived parameter cannot be evaluated (see
+    /// [`try_combinations`](#method.try_combinations)).
+    pub fn experiments(
+        &self,
+        name: &str,
+        stages: &[(&str, CommandTemplate)],
+    ) -> io::Result<Vec<Experiment>> {
+        self.try_combinations()?
+            .iter()
+            .map(|combination| {
+                let mut experiment = Experiment::new(&format!("{}[{}]", name, combination));
+                for (name, value) in combination.iter() {
+                    experiment.parameter(name, value);
+                }
+                if let Some((dir, policy)) = &self.output_dir {
+                    experiment.output_dir(dir.join(combination.dir_name()), *policy);
+                }
+                for (stage, template) in stages {
+                    experiment.process(stage, template.instantiate(|name| combination.get(name))?);
+                }
+                Ok(experiment)
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "executor")]
+impl SweepExecutor {
+    /// Creates an executor with the given number of workers, at least one.
+    pub fn new(workers: usize) -> SweepExecutor {
+        SweepExecutor {
+            workers: workers.max(1),
+            scratch_dir: None,
+        }
+    }
+
+    /// Sets the directory under which the workers get their scratch directories.
+    pub fn scratch_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut SweepExecutor {
+        self.scratch_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Runs all experiments, and returns their reports in the order of the experiments, or
+    /// the errors that prevented them from running.
+    pub fn run(&self, experiments: &[Experiment]) -> Vec<io::Result<ExperimentReport>> {
+        crate::parallel_map_indexed(experiments, self.workers, |worker, experiment| {
+            self.run_one(experiment, worker)
+        })
+    }
+
+    fn run_one(&self, experiment: &Experiment, worker: usize) -> io::Result<ExperimentReport> {
+        let mut context = experiment.context();
+        if let Some(dir) = &self.scratch_dir {
+            let scratch = dir.join(format!("worker-{}", worker));
+            if scratch.exists() {
+                std::fs::remove_dir_all(&scratch)?;
+            }
+            std::fs::create_dir_all(&scratch)?;
+            context.scratch_dir(scratch);
+        }
+        experiment.run_in(context)
+    }
+}
+
+/// Collects the values of an inclusion or exclusion rule.
+fn rule<I, K, V>(values: I) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: ToString,
+{
+    values
+        .into_iter()
+        .map(|(name, value)| (String::from(name.as_ref()), value.to_string()))
+        .collect()
+}
+
+impl Combination {
+    /// The value of a parameter.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The typed value of a parameter; values of parameters that were not declared with a
+    /// [`Domain`](enum.Domain.html) are strings.
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .position(|(key, _)| key == name)
+            .map(|idx| &self.typed[idx])
+    }
+
+    /// Sets the value of a parameter, adding it if missing.
+    fn set(&mut self, name: &str, value: Value) {
+        match self.values.iter().position(|(key, _)| key == name) {
+            Some(idx) => {
+                self.values[idx].1 = value.to_string();
+                self.typed[idx] = value;
+            }
+            None => {
+                self.values.push((String::from(name), value.to_string()));
+                self.typed.push(value);
+            }
+        }
+    }
+
+    /// Iterates over the names and values of the parameters.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// A name of a directory identifying the combination: its display with all characters
+    /// other than alphanumerics and `.,=_-` replaced with `_`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::Grid;
+    /// let mut grid = Grid::new();
+    /// grid.parameter("corpus", &["/data/cw09b"]).parameter("k", &[10]);
+    /// assert_eq!(grid.combinations()[0].dir_name(), "corpus=_data_cw09b,k=10");
+    /// ```
+    pub fn dir_name(&self) -> String {
+        self.to_string()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || ".,=_-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Combination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, (name, value)) in self.values.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Domain {
+    /// Integers from `start` to `end`, inclusive, every `step`.
+    ///
+    /// # Panics
+    /// Panics if `step` is not positive.
+    pub fn range(start: i64, end: i64, step: i64) -> Domain {
+        assert!(step > 0, "Range step must be positive");
+        Domain::Range { start, end, step }
+    }
+
+    /// `n` floats evenly spaced from `start` to `end`, inclusive.
+    pub fn linspace(start: f64, end: f64, n: usize) -> Domain {
+        Domain::Linspace { start, end, n }
+    }
+
+    /// `n` floats evenly spaced on a logarithmic scale from `start` to `end`, inclusive.
+    ///
+    /// # Panics
+    /// Panics if `start` or `end` is not positive.
+    pub fn logspace(start: f64, end: f64, n: usize) -> Domain {
+        assert!(start > 0.0 && end > 0.0, "Logspace bounds must be positive");
+        Domain::Logspace { start, end, n }
+    }
+
+    /// `false` and `true`.
+    pub fn boolean() -> Domain {
+        Domain::Values(vec![Value::Boolean(false), Value::Boolean(true)])
+    }
+
+    /// A set of strings.
+    pub fn categorical<I, S>(values: I) -> Domain
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        Domain::Values(
+            values
+                .into_iter()
+                .map(|value| Value::String(value.to_string()))
+                .collect(),
+        )
+    }
+
+    /// All values of the domain.
+    ///
+    /// The constructors reject invalid bounds, but the variants can be built directly: a `Range`
+    /// whose step is not positive, and a `Logspace` with a bound that is not positive, have no
+    /// values rather than panicking.
+    ///
+    /// # Examples
+    /// ```
+    /// # use experiment::sweep::Domain;
+    /// assert!(Domain::Range { start: 0, end: 10, step: 0 }.values().is_empty());
+    /// assert!(Domain::Range { start: 10, end: 0, step: -1 }.values().is_empty());
+    /// assert!(Domain::Logspace { start: 0.0, end: 100.0, n: 3 }.values().is_empty());
+    /// assert!(Domain::Logspace { start: 1.0, end: -1.0, n: 3 }.values().is_empty());
+    /// assert_eq!(Domain::Range { start: 0, end: 10, step: i64::MAX }.values().len(), 1);
+    /// ```
+    pub fn values(&self) -> Vec<Value> {
+        match self {
+            Domain::Values(values) => values.clone(),
+            Domain::Range { step, .. } if *step <= 0 => Vec::new(),
+            Domain::Range { start, end, step } => (*start..=*end)
+                .step_by(usize::try_from(*step).unwrap_or(usize::MAX))
+                .map(Value::Integer)
+                .collect(),
+            Domain::Linspace { start, end, n } => spaced(*start, *end, *n)
+                .into_iter()
+                .map(Value::Float)
+                .collect(),
+            Domain::Logspace { start, end, .. } if !(*start > 0.0 && *end > 0.0) => Vec::new(),
+            Domain::Logspace { start, end, n } => spaced(start.log10(), end.log10(), *n)
+                .into_iter()
+                .map(|exponent| Value::Float(round(10f64.powf(exponent))))
+                .collect(),
+        }
+    }
+}
+
+/// The number of available CPUs, or 1 if unknown.
+fn cores() -> i64 {
+    std::thread::available_parallelism().map_or(1, |cores| cores.get() as i64)
+}
+
+/// `n` evenly spaced floats from `start` to `end`, inclusive.
+fn spaced(start: f64, end: f64, n: usize) -> Vec<f64> {
+    match n {
+        0 => Vec::new(),
+        1 => vec![start],
+        _ => (0..n)
+            .map(|idx| start + (end - start) * idx as f64 / (n - 1) as f64)
+            .collect(),
+    }
+}
+
+/// Rounds away the error of `powf` in the last few digits, so that, say, `10^2` is
+/// displayed as `100` rather than `100.00000000000004`.
+fn round(value: f64) -> f64 {
+    format!("{:.12e}", value).parse().unwrap_or(value)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Floats are compared by their bits, so that values can be hashed.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Integer(value) => value.hash(state),
+            Value::Float(value) => value.to_bits().hash(state),
+            Value::Boolean(value) => value.hash(state),
+            Value::String(value) => value.hash(state),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Commands with placeholders that are filled in when they are instantiated.
+
+use super::process::Process;
+use std::io;
+
+/// A process whose program and arguments may contain `{name}` placeholders, which are
+/// replaced with the values of parameters when the template is instantiated.
+///
+/// A placeholder is a name of ASCII letters, digits, `_`, and `-` in braces, and a literal
+/// one is written with doubled braces, e.g., `{{k}}` for `{k}`. Any other braces, such as
+/// `{}`, `{print $1}`, or `${HOME}`, are left as they are.
+///
+/// # Examples
+/// ```
+/// # use experiment::template::CommandTemplate;
+/// # use experiment::Verbosity::Verbose;
+/// let template = CommandTemplate::new("evaluate", &["-k", "{k}", "--name", "{{k}}"]);
+/// let process = template
+///     .instantiate(|name| if name == "k" { Some("10") } else { None })
+///     .unwrap();
+/// assert_eq!(process.display(Verbose).to_string(), "evaluate -k 10 --name {k}");
+///
+/// let lookup = |_: &str| None;
+/// let find = CommandTemplate::new("find", &[".", "-exec", "rm", "{}", ";"]);
+/// assert_eq!(
+///     find.instantiate(lookup).unwrap().display(Verbose).to_string(),
+///     "find . -exec rm {} ;"
+/// );
+/// let awk = CommandTemplate::new("awk", &["{print $1}"]);
+/// assert_eq!(
+///     awk.instantiate(lookup).unwrap().display(Verbose).to_string(),
+///     "awk {print $1}"
+/// );
+/// let jq = CommandTemplate::new("jq", &["{a: .b, c: {d: .e}}"]);
+/// assert_eq!(
+///     jq.instantiate(lookup).unwrap().display(Verbose).to_string(),
+///     "jq {a: .b, c: {d: .e}}"
+/// );
+/// let sh = CommandTemplate::new("sh", &["-c", "echo ${HOME}"]);
+/// assert_eq!(
+///     sh.instantiate(lookup).unwrap().display(Verbose).to_string(),
+///     "sh -c echo ${HOME}"
+/// );
+///
+/// let err = template.instantiate(|_| None).unwrap_err();
+/// assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+/// assert_eq!(err.to_string(), "Unknown placeholder `{k}` in `{k}`");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CommandTemplate {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandTemplate {
+    /// Creates a template of the program with the arguments.
+    pub fn new<I, S>(program: &str, args: I) -> CommandTemplate
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        CommandTemplate {
+            program: String::from(program),
+            args: args
+                .into_iter()
+                .map(|arg| String::from(arg.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// Creates a process by replacing every placeholder with the value returned by `lookup`
+    /// for its name. Fails with `InvalidInput` if a placeholder is unknown, i.e., `lookup`
+    /// returns `None`.
+    pub fn instantiate<'a, F>(&self, lookup: F) -> io::Result<Process>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidInput, err);
+        let program = interpolate(&self.program, &lookup).map_err(invalid)?;
+        let args = self
+            .args
+            .iter()
+            .map(|arg| interpolate(arg, &lookup))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        Ok(Process::new(&program, &args))
+    }
+}
+
+/// Replaces `{name}` placeholders in `text` with the values returned by `lookup`, and
+/// `{{name}}` with a literal `{name}`. Other braces are kept as they are.
+pub(crate) fn interpolate<'a, F>(text: &str, lookup: F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let (before, from) = rest.split_at(start);
+        result.push_str(before);
+        if before.ends_with('$') {
+            // A shell parameter expansion, e.g., `${HOME}`.
+            result.push('{');
+            rest = &from[1..];
+        } else if let Some(name) = from
+            .strip_prefix("{{")
+            .and_then(|tail| enclosed(tail, "}}"))
+        {
+            result.push('{');
+            result.push_str(name);
+            result.push('}');
+            rest = &from[name.len() + 4..];
+        } else if let Some(name) = enclosed(&from[1..], "}") {
+            let value = lookup(name)
+                .ok_or_else(|| format!("Unknown placeholder `{{{}}}` in `{}`", name, text))?;
+            result.push_str(value);
+            rest = &from[name.len() + 2..];
+        } else {
+            result.push('{');
+            rest = &from[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Returns the name at the beginning of `text` if it is followed by `end`. A name is
+/// non-empty, and consists of ASCII letters, digits, `_`, and `-`.
+fn enclosed<'t>(text: &'t str, end: &str) -> Option<&'t str> {
+    let len = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(text.len());
+    if len > 0 && text[len..].starts_with(end) {
+        Some(&text[..len])
+    } else {
+        None
+    }
+}
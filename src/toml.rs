@@ -0,0 +1,464 @@
+// MIT License
+//
+// Copyright (c) 2019 Michał Siedlaczek
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Parsing the subset of TOML used by configuration files.
+//!
+//! Supported are comments, bare, quoted, and dotted keys, tables, arrays of tables, and
+//! values that are basic or literal single-line strings, integers, floats, booleans, arrays,
+//! and inline tables. Multi-line strings and dates are rejected.
+
+use super::config::{ConfigError, Position};
+use std::collections::BTreeMap;
+
+/// A value together with the position where it starts.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Item {
+    pub(crate) value: Value,
+    pub(crate) position: Position,
+}
+
+/// A TOML value.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Item>),
+    Table(Table),
+}
+
+/// A TOML table.
+pub(crate) type Table = BTreeMap<String, Item>;
+
+/// A key, split on dots, with the position of each part.
+type Key = Vec<(String, Position)>;
+
+impl Value {
+    /// The name of the type of the value, for error messages.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+/// Parses a TOML document into its root table.
+pub(crate) fn parse(text: &str) -> Result<Table, ConfigError> {
+    Parser {
+        chars: text.chars().collect(),
+        offset: 0,
+        position: Position { line: 1, column: 1 },
+    }
+    .document()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    offset: usize,
+    position: Position,
+}
+
+impl Parser {
+    fn document(&mut self) -> Result<Table, ConfigError> {
+        let mut root = Table::new();
+        let mut current = Key::new();
+        let mut defined = Vec::<Vec<String>>::new();
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                None => break,
+                Some('\n') | Some('\r') => {
+                    self.next();
+                }
+                Some('[') => {
+                    let position = self.position;
+                    self.next();
+                    let array = self.peek() == Some('[');
+                    if array {
+                        self.next();
+                    }
+                    let key = self.key()?;
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    self.end_of_line()?;
+                    let path: Vec<String> = key.iter().map(|(part, _)| part.clone()).collect();
+                    let (last, parents) = key.split_last().expect("Keys are not empty");
+                    let parent = descend(&mut root, parents)?;
+                    if array {
+                        let item = parent.entry(last.0.clone()).or_insert_with(|| Item {
+                            value: Value::Array(Vec::new()),
+                            position,
+                        });
+                        match &mut item.value {
+                            Value::Array(items) => items.push(Item {
+                                value: Value::Table(Table::new()),
+                                position,
+                            }),
+                            _ => {
+                                return Err(error(
+                                    position,
+                                    format!("`{}` is not an array", last.0),
+                                ))
+                            }
+                        }
+                        defined.retain(|table| !table.starts_with(&path));
+                    } else {
+                        if defined.contains(&path) {
+                            return Err(error(
+                                position,
+                                format!("Duplicate table `{}`", path.join(".")),
+                            ));
+                        }
+                        defined.push(path);
+                        let item = parent.entry(last.0.clone()).or_insert_with(|| Item {
+                            value: Value::Table(Table::new()),
+                            position,
+                        });
+                        if !matches!(item.value, Value::Table(_)) {
+                            return Err(error(position, format!("`{}` is not a table", last.0)));
+                        }
+                    }
+                    current = key;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip_whitespace();
+                    self.expect('=')?;
+                    self.skip_whitespace();
+                    let item = self.value()?;
+                    self.end_of_line()?;
+                    let table = descend(&mut root, &current)?;
+                    insert(table, key, item)?;
+                }
+            }
+        }
+        Ok(root)
+    }
+
+    fn key(&mut self) -> Result<Key, ConfigError> {
+        let mut key = Key::new();
+        loop {
+            self.skip_whitespace();
+            let position = self.position;
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                Some(c) if is_bare(c) => {
+                    let mut part = String::new();
+                    while let Some(c) = self.peek().filter(|&c| is_bare(c)) {
+                        part.push(c);
+                        self.next();
+                    }
+                    part
+                }
+                _ => return Err(self.error("Expected a key")),
+            };
+            key.push((part, position));
+            self.skip_whitespace();
+            if self.peek() != Some('.') {
+                return Ok(key);
+            }
+            self.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Item, ConfigError> {
+        let position = self.position;
+        let value = match self.peek() {
+            Some(quote @ '"') | Some(quote @ '\'') if self.lookahead(3) == [quote; 3] => {
+                return Err(self.error("Multi-line strings are not supported"));
+            }
+            Some('"') => Value::String(self.basic_string()?),
+            Some('\'') => Value::String(self.literal_string()?),
+            Some('[') => self.array()?,
+            Some('{') => self.inline_table()?,
+            Some(c) if c.is_ascii_alphanumeric() || c == '+' || c == '-' => {
+                let mut word = String::new();
+                while let Some(c) = self
+                    .peek()
+                    .filter(|&c| c.is_ascii_alphanumeric() || "+-._:".contains(c))
+                {
+                    word.push(c);
+                    self.next();
+                }
+                scalar(&word).ok_or_else(|| error(position, format!("Invalid value `{}`", word)))?
+            }
+            _ => return Err(self.error("Expected a value")),
+        };
+        Ok(Item { value, position })
+    }
+
+    fn array(&mut self) -> Result<Value, ConfigError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.next();
+                break;
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some(']') => {
+                    self.next();
+                    break;
+                }
+                _ => return Err(self.error("Expected `,` or `]`")),
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn inline_table(&mut self) -> Result<Value, ConfigError> {
+        self.expect('{')?;
+        let mut table = Table::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Table(table));
+        }
+        loop {
+            let key = self.key()?;
+            self.expect('=')?;
+            self.skip_whitespace();
+            let item = self.value()?;
+            insert(&mut table, key, item)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some('}') => {
+                    self.next();
+                    return Ok(Value::Table(table));
+                }
+                _ => return Err(self.error("Expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, ConfigError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let position = self.position;
+            match self.next() {
+                None | Some('\n') => return Err(error(position, "Unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(kind @ 'u') | Some(kind @ 'U') => {
+                            let len = if kind == 'u' { 4 } else { 8 };
+                            let digits: String = self.lookahead(len).into_iter().collect();
+                            for _ in 0..len {
+                                self.next();
+                            }
+                            u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| error(position, "Invalid unicode escape"))?
+                        }
+                        _ => return Err(error(position, "Invalid escape sequence")),
+                    };
+                    value.push(escaped);
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ConfigError> {
+        self.expect('\'')?;
+        let mut value = String::new();
+        loop {
+            let position = self.position;
+            match self.next() {
+                None | Some('\n') => return Err(error(position, "Unterminated string")),
+                Some('\'') => return Ok(value),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ConfigError> {
+        self.skip_whitespace();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            _ => Err(self.error("Expected the end of the line")),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ConfigError> {
+        if self.peek() == Some(expected) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected `{}`", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.next();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.next();
+            }
+        }
+    }
+
+    /// Skips whitespace, comments, and new lines.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.next();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.offset).copied()
+    }
+
+    fn lookahead(&self, len: usize) -> Vec<char> {
+        self.chars
+            .iter()
+            .skip(self.offset)
+            .take(len)
+            .copied()
+            .collect()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += 1;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> ConfigError {
+        error(self.position, message)
+    }
+}
+
+fn error<S: Into<String>>(position: Position, message: S) -> ConfigError {
+    ConfigError {
+        position,
+        message: message.into(),
+    }
+}
+
+fn is_bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Parses a boolean, integer, or float.
+fn scalar(word: &str) -> Option<Value> {
+    match word {
+        "true" => return Some(Value::Boolean(true)),
+        "false" => return Some(Value::Boolean(false)),
+        _ => {}
+    }
+    let digits = word.replace('_', "");
+    if let Ok(value) = digits.parse() {
+        return Some(Value::Integer(value));
+    }
+    let numeric = digits
+        .chars()
+        .all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+    match digits.parse() {
+        Ok(value) if numeric => Some(Value::Float(value)),
+        _ => None,
+    }
+}
+
+/// Finds the table at `key`, creating the missing ones; arrays of tables stand for their last
+/// element.
+fn descend<'t>(
+    mut table: &'t mut Table,
+    key: &[(String, Position)],
+) -> Result<&'t mut Table, ConfigError> {
+    for (part, position) in key {
+        let item = table.entry(part.clone()).or_insert_with(|| Item {
+            value: Value::Table(Table::new()),
+            position: *position,
+        });
+        table = match &mut item.value {
+            Value::Table(inner) => inner,
+            Value::Array(items) => match items.last_mut() {
+                Some(Item {
+                    value: Value::Table(inner),
+                    ..
+                }) => inner,
+                _ => return Err(error(*position, format!("`{}` is not a table", part))),
+            },
+            _ => return Err(error(*position, format!("`{}` is not a table", part))),
+        };
+    }
+    Ok(table)
+}
+
+/// Inserts a value at a dotted key, failing if it is already defined.
+fn insert(table: &mut Table, key: Key, item: Item) -> Result<(), ConfigError> {
+    let (last, parents) = key.split_last().expect("Keys are not empty");
+    let table = descend(table, parents)?;
+    if table.contains_key(&last.0) {
+        return Err(error(last.1, format!("Duplicate key `{}`", last.0)));
+    }
+    table.insert(last.0.clone(), item);
+    Ok(())
+}